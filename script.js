@@ -1,5 +1,5 @@
 const GEOSUBMIT_ENDPOINT = "https://api.beacondb.net/v2/geosubmit";
-const RUST_SERVER_URL = "http://192.168.0.251:3030/network_json";
+const RUST_SERVER_URL = "https://192.168.0.251:8080/request?wait=true";
 
 let logBuffer = "";
 
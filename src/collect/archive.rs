@@ -0,0 +1,148 @@
+//! Local history of collected reports, one JSON object per line
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::Report;
+use crate::config::{ARCHIVE_FILE, config_dir};
+use crate::error::Result;
+
+// appends from concurrent tasks must not interleave
+static WRITE_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// Every report collected on this machine, positioned or not
+#[derive(Debug, Clone)]
+pub struct Archive {
+    path: PathBuf,
+}
+
+impl Archive {
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        Archive { path: path.into() }
+    }
+
+    /// The archive in the config directory
+    pub fn local() -> Self {
+        Archive::open(config_dir().join(ARCHIVE_FILE))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, report: &Report) -> Result<()> {
+        append_line(&self.path, report)
+    }
+
+    /// All archived reports, oldest first
+    pub fn read(&self) -> Result<Vec<Report>> {
+        read_lines(&self.path)
+    }
+
+    /// Rewrite the archive in place. Lines that don't parse are dropped.
+    pub fn update(&self, f: impl FnOnce(&mut Vec<Report>)) -> Result<()> {
+        let _guard = WRITE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut reports = read_lines(&self.path)?;
+        f(&mut reports);
+
+        let mut contents = Vec::new();
+        for report in &reports {
+            serde_json::to_writer(&mut contents, report)?;
+            contents.push(b'\n');
+        }
+        let temporary = self.path.with_extension("tmp");
+        fs::write(&temporary, contents)?;
+        fs::rename(temporary, &self.path)?;
+        Ok(())
+    }
+}
+
+/// Append `value` as one JSON line
+pub(crate) fn append_line<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    let mut line = serde_json::to_vec(value)?;
+    line.push(b'\n');
+
+    let _guard = WRITE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(&line)?;
+    Ok(())
+}
+
+/// One non-empty line of a JSON lines file
+#[derive(Debug)]
+pub(crate) enum Line<T> {
+    Parsed(T),
+    Corrupt(String), // kept as it was
+}
+
+/// Parse every JSON line, skipping ones that don't parse. A missing file is empty.
+pub(crate) fn read_lines<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>> {
+    Ok(read_entries(path)?
+        .into_iter()
+        .filter_map(|line| match line {
+            Line::Parsed(value) => Some(value),
+            Line::Corrupt(_) => None,
+        })
+        .collect())
+}
+
+/// Every non-empty line, parsed where possible. A missing file is empty.
+pub(crate) fn read_entries<T: DeserializeOwned>(path: &Path) -> Result<Vec<Line<T>>> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut entries = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(value) => entries.push(Line::Parsed(value)),
+            Err(e) => {
+                tracing::warn!("Skipping {}:{}: {}", path.display(), number + 1, e);
+                entries.push(Line::Corrupt(line));
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Replace the first `read` non-empty lines with `keep`, keeping any appended
+/// since they were read
+pub(crate) fn replace_lines<T: Serialize>(
+    path: &Path,
+    read: usize,
+    keep: &[Line<T>],
+) -> Result<()> {
+    let _guard = WRITE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut contents = Vec::new();
+    for line in keep {
+        match line {
+            Line::Parsed(value) => serde_json::to_writer(&mut contents, value)?,
+            Line::Corrupt(raw) => contents.extend_from_slice(raw.as_bytes()),
+        }
+        contents.push(b'\n');
+    }
+    let appended = fs::read_to_string(path)?;
+    for line in appended
+        .split_inclusive('\n')
+        .filter(|line| !line.trim().is_empty())
+        .skip(read)
+    {
+        contents.extend_from_slice(line.as_bytes());
+    }
+
+    let temporary = path.with_extension("tmp");
+    fs::write(&temporary, contents)?;
+    fs::rename(temporary, path)?;
+    Ok(())
+}
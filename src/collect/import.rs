@@ -0,0 +1,147 @@
+//! `serviceberry import-gpx`: position archived scans from a track recorded elsewhere
+
+use std::path::PathBuf;
+
+use super::Policy;
+use super::privacy::Verdict;
+use super::runner::{Outcome, send};
+use super::{Archive, Queue};
+use crate::error::{Error, Result};
+use crate::position::gpx::{Rejection, Track};
+
+pub const USAGE: &str = "\
+Usage: serviceberry import-gpx <FILE> [OPTIONS]
+
+Positions archived scans that have no position from a GPX track.
+
+Options:
+  --no-submit           only update the archive, don't submit the reports";
+
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    pub path: PathBuf,
+    pub submit: bool,
+}
+
+impl ImportOptions {
+    /// Parse the arguments following `import-gpx`
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut path = None;
+        let mut submit = true;
+        for arg in args {
+            match arg.as_str() {
+                "--no-submit" => submit = false,
+                option if option.starts_with("--") => {
+                    return Err(Error::Config(format!(
+                        "unknown option {}\n\n{}",
+                        option, USAGE
+                    )));
+                }
+                _ if path.is_some() => {
+                    return Err(Error::Config(format!("only one GPX file\n\n{}", USAGE)));
+                }
+                _ => path = Some(PathBuf::from(arg)),
+            }
+        }
+
+        let path = path.ok_or_else(|| Error::Config(format!("missing GPX file\n\n{}", USAGE)))?;
+        Ok(ImportOptions { path, submit })
+    }
+}
+
+/// What an import did with the unpositioned reports
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub positioned: usize,
+    pub before_track: usize,
+    pub after_track: usize,
+    pub in_gaps: usize,
+    pub suppressed: usize, // inside a privacy zone
+    pub submitted: usize,
+    pub queued: usize,
+}
+
+/// Position every unpositioned report in `archive` from `track`, then submit
+/// the newly positioned ones. Rejected reports stay unpositioned, so a later
+/// import with a different track can still pick them up. Reports that turn
+/// out to be inside a privacy zone are handled as if collected with a position.
+pub async fn import_gpx(
+    track: &Track,
+    archive: &Archive,
+    queue: &Queue,
+    policy: Policy<'static>,
+    submit: bool,
+) -> Result<ImportSummary> {
+    let mut summary = ImportSummary::default();
+    let mut positioned = Vec::new();
+
+    archive.update(|reports| {
+        reports.retain_mut(|report| {
+            if report.position.is_some() {
+                return true;
+            }
+            match track.position_at(report.timestamp) {
+                Ok(position) => {
+                    report.position = Some(position);
+                    match policy.zones.apply(report) {
+                        Verdict::Keep => positioned.push(report.clone()),
+                        Verdict::PositionDropped => summary.suppressed += 1,
+                        Verdict::Dropped => {
+                            summary.suppressed += 1;
+                            return false; // as if it had been dropped while collecting
+                        }
+                    }
+                }
+                Err(Rejection::BeforeTrack) => summary.before_track += 1,
+                Err(Rejection::AfterTrack) => summary.after_track += 1,
+                Err(Rejection::Gap { .. }) => summary.in_gaps += 1,
+            }
+            true
+        })
+    })?;
+    summary.positioned = positioned.len();
+    for report in &positioned {
+        if let Err(e) = policy.mobiles.observe(report) {
+            tracing::warn!("Failed to update mobile devices: {}", e);
+        }
+    }
+
+    if submit {
+        for report in &positioned {
+            match send(report, queue, policy).await {
+                Outcome::Submitted => summary.submitted += 1,
+                Outcome::Queued => summary.queued += 1,
+                Outcome::Archived | Outcome::Suppressed | Outcome::Rejected => {}
+            }
+        }
+    }
+    Ok(summary)
+}
+
+/// Read the track and import it into the local archive
+pub async fn run(options: ImportOptions) -> Result<()> {
+    let track = Track::read(&options.path)?;
+    println!(
+        "Read {} track points from {}",
+        track.points().len(),
+        options.path.display()
+    );
+
+    let summary = import_gpx(
+        &track,
+        &Archive::local(),
+        &Queue::local(),
+        Policy::local(),
+        options.submit,
+    )
+    .await?;
+    println!(
+        "Positioned {} reports ({} submitted, {} queued)",
+        summary.positioned, summary.submitted, summary.queued
+    );
+    println!(
+        "Rejected {} before the track, {} after it, {} in gaps, {} in privacy zones",
+        summary.before_track, summary.after_track, summary.in_gaps, summary.suppressed
+    );
+    Ok(())
+}
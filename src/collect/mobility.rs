@@ -0,0 +1,292 @@
+//! Access points and beacons that move around with people
+//!
+//! Phone hotspots, in-car Wi-Fi and wearables show up wherever their owner
+//! goes, which would teach the geolocation database that they are everywhere.
+//! Any device seen at places `MOBILE_DISTANCE_METERS` apart within
+//! `MOBILE_WINDOW_MS` is flagged and left out of uploads. Classifications can
+//! be overridden through the API and persist in `mobile.json`.
+
+use btleplug::api::BDAddr;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use super::{Archive, Report};
+use crate::config::{
+    MOBILE_DISTANCE_METERS, MOBILE_FILE, MOBILE_MAX_SIGHTINGS, MOBILE_WINDOW_MS, config_dir,
+};
+use crate::error::{Error, Result};
+use crate::position::geo::distance_meters;
+
+static LOCAL: Lazy<MobileStore> = Lazy::new(|| MobileStore::open(config_dir().join(MOBILE_FILE)));
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceKind {
+    Wifi,
+    Ble,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Classification {
+    Detected, // flagged from the observation history
+    Denied,   // flagged by hand
+    Allowed,  // never flagged, whatever the history says
+}
+
+/// A device with a classification
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MobileDevice {
+    pub kind: DeviceKind,
+    pub address: String,
+    pub classification: Classification,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub distance: Option<f64>, // in meters, between the sightings that flagged it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detected_at: Option<u128>, // in milliseconds since Unix epoch
+}
+
+impl MobileDevice {
+    pub fn is_denied(&self) -> bool {
+        self.classification != Classification::Allowed
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sighting {
+    timestamp: u128,
+    latitude: f64,
+    longitude: f64,
+}
+
+type Key = (DeviceKind, String);
+
+/// The key for an address given through the API, written the way observations are
+fn key(kind: DeviceKind, address: &str) -> Result<Key> {
+    let parsed: BDAddr = address
+        .parse()
+        .map_err(|_| Error::InvalidAddress(address.to_string()))?;
+    Ok((kind, parsed.to_string()))
+}
+
+#[derive(Debug, Default)]
+struct State {
+    devices: HashMap<Key, MobileDevice>,
+    sightings: HashMap<Key, VecDeque<Sighting>>, // recent, in memory only
+}
+
+/// Persisted classifications plus the recent sightings they are derived from
+#[derive(Debug)]
+pub struct MobileStore {
+    path: PathBuf,
+    state: Mutex<Option<State>>, // loaded on first use
+}
+
+impl MobileStore {
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        MobileStore {
+            path: path.into(),
+            state: Mutex::new(None),
+        }
+    }
+
+    /// The classifications in the config directory
+    pub fn local() -> &'static MobileStore {
+        &LOCAL
+    }
+
+    fn with_state<T>(&self, f: impl FnOnce(&mut State) -> Result<T>) -> Result<T> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.is_none() {
+            let devices: Vec<MobileDevice> = match fs::read_to_string(&self.path) {
+                Ok(contents) => serde_json::from_str(&contents)?,
+                Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+                Err(e) => return Err(e.into()),
+            };
+            *state = Some(State {
+                devices: devices
+                    .into_iter()
+                    .map(|d| ((d.kind, d.address.clone()), d))
+                    .collect(),
+                sightings: HashMap::new(),
+            });
+        }
+        f(state.get_or_insert_with(State::default))
+    }
+
+    fn save(&self, state: &State) -> Result<()> {
+        let mut devices: Vec<&MobileDevice> = state.devices.values().collect();
+        devices.sort_by(|a, b| (a.kind, &a.address).cmp(&(b.kind, &b.address)));
+        let temporary = self.path.with_extension("tmp");
+        fs::write(&temporary, serde_json::to_vec_pretty(&devices)?)?;
+        fs::rename(temporary, &self.path)?;
+        Ok(())
+    }
+
+    /// Add the devices of a positioned report to the history, flagging those
+    /// that moved. Returns how many were newly flagged.
+    pub fn observe(&self, report: &Report) -> Result<usize> {
+        let Some(position) = &report.position else {
+            return Ok(0);
+        };
+        let sighting = Sighting {
+            timestamp: report.timestamp,
+            latitude: position.latitude,
+            longitude: position.longitude,
+        };
+        let keys = report
+            .wifi_access_points
+            .iter()
+            .map(|ap| (DeviceKind::Wifi, ap.bssid.to_string()))
+            .chain(
+                report
+                    .bluetooth_beacons
+                    .iter()
+                    .map(|b| (DeviceKind::Ble, b.mac_address.to_string())),
+            );
+
+        let cutoff = sighting.timestamp.saturating_sub(MOBILE_WINDOW_MS as u128);
+        self.with_state(|state| {
+            let mut flagged = 0;
+            for key in keys {
+                let sightings = state.sightings.entry(key.clone()).or_default();
+                sightings.retain(|s| s.timestamp >= cutoff);
+
+                let farthest = sightings
+                    .iter()
+                    .filter(|s| {
+                        s.timestamp.abs_diff(sighting.timestamp) <= MOBILE_WINDOW_MS as u128
+                    })
+                    .map(|s| {
+                        distance_meters(
+                            s.latitude,
+                            s.longitude,
+                            sighting.latitude,
+                            sighting.longitude,
+                        )
+                    })
+                    .reduce(f64::max);
+
+                if sightings.len() >= MOBILE_MAX_SIGHTINGS {
+                    sightings.pop_front();
+                }
+                sightings.push_back(sighting);
+
+                if let Some(distance) = farthest
+                    && distance > MOBILE_DISTANCE_METERS
+                    && !state.devices.contains_key(&key)
+                {
+                    tracing::info!("Flagging mobile {:?} device {}", key.0, key.1);
+                    state.devices.insert(
+                        key.clone(),
+                        MobileDevice {
+                            kind: key.0,
+                            address: key.1,
+                            classification: Classification::Detected,
+                            distance: Some(distance),
+                            detected_at: Some(sighting.timestamp),
+                        },
+                    );
+                    flagged += 1;
+                }
+            }
+            // devices not seen within the window have nothing left to compare
+            state
+                .sightings
+                .retain(|_, sightings| sightings.back().is_some_and(|s| s.timestamp >= cutoff));
+            if flagged > 0 {
+                self.save(state)?;
+            }
+            Ok(flagged)
+        })
+    }
+
+    /// Number of devices with sightings kept in memory
+    pub fn tracked(&self) -> Result<usize> {
+        self.with_state(|state| Ok(state.sightings.len()))
+    }
+
+    /// Replay the archive's history, oldest first. Returns how many devices were flagged.
+    pub fn learn(&self, archive: &Archive) -> Result<usize> {
+        let mut reports = archive.read()?;
+        reports.sort_by_key(|r| r.timestamp);
+        reports.iter().map(|r| self.observe(r)).sum()
+    }
+
+    /// Remove denied devices from `report`, returning how many were removed
+    pub fn strip(&self, report: &mut Report) -> usize {
+        let result = self.with_state(|state| {
+            let denied = |kind: DeviceKind, address: String| {
+                state
+                    .devices
+                    .get(&(kind, address))
+                    .is_some_and(MobileDevice::is_denied)
+            };
+            let before = report.wifi_access_points.len() + report.bluetooth_beacons.len();
+            report
+                .wifi_access_points
+                .retain(|ap| !denied(DeviceKind::Wifi, ap.bssid.to_string()));
+            report
+                .bluetooth_beacons
+                .retain(|b| !denied(DeviceKind::Ble, b.mac_address.to_string()));
+            Ok(before - report.wifi_access_points.len() - report.bluetooth_beacons.len())
+        });
+        result.unwrap_or_else(|e| {
+            tracing::error!("Failed to load mobile devices: {}", e);
+            0
+        })
+    }
+
+    pub fn list(&self) -> Result<Vec<MobileDevice>> {
+        self.with_state(|state| {
+            let mut devices: Vec<MobileDevice> = state.devices.values().cloned().collect();
+            devices.sort_by(|a, b| (a.kind, &a.address).cmp(&(b.kind, &b.address)));
+            Ok(devices)
+        })
+    }
+
+    /// Classify a device by hand, overriding detection
+    pub fn classify(
+        &self,
+        kind: DeviceKind,
+        address: &str,
+        classification: Classification,
+    ) -> Result<MobileDevice> {
+        let (kind, address) = key(kind, address)?;
+        self.with_state(|state| {
+            let device = state
+                .devices
+                .entry((kind, address.clone()))
+                .or_insert_with(|| MobileDevice {
+                    kind,
+                    address,
+                    classification,
+                    distance: None,
+                    detected_at: None,
+                });
+            device.classification = classification;
+            let device = device.clone();
+            self.save(state)?;
+            Ok(device)
+        })
+    }
+
+    /// Drop a device's classification so detection starts over. Returns whether it had one.
+    pub fn forget(&self, kind: DeviceKind, address: &str) -> Result<bool> {
+        let key = key(kind, address)?;
+        self.with_state(|state| {
+            state.sightings.remove(&key);
+            if state.devices.remove(&key).is_none() {
+                return Ok(false);
+            }
+            self.save(state)?;
+            Ok(true)
+        })
+    }
+}
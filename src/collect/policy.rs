@@ -0,0 +1,33 @@
+//! The stores that decide what is kept, archived and uploaded
+
+use super::Report;
+use super::mobility::MobileStore;
+use super::privacy::{DeviceRuleStore, Dropped, ZoneStore};
+
+/// Privacy zones, mobile devices and device rules, applied together
+#[derive(Debug, Clone, Copy)]
+pub struct Policy<'a> {
+    pub zones: &'a ZoneStore,
+    pub mobiles: &'a MobileStore,
+    pub rules: &'a DeviceRuleStore,
+}
+
+impl Policy<'static> {
+    /// The stores in the config directory
+    pub fn local() -> Self {
+        Policy {
+            zones: ZoneStore::local(),
+            mobiles: MobileStore::local(),
+            rules: DeviceRuleStore::local(),
+        }
+    }
+}
+
+impl Policy<'_> {
+    /// Remove mobile and excluded devices before `report` is uploaded,
+    /// returning what the device rules removed
+    pub fn strip(&self, report: &mut Report) -> Dropped {
+        self.mobiles.strip(report);
+        self.rules.strip(report)
+    }
+}
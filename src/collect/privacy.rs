@@ -0,0 +1,421 @@
+//! Privacy zones around places whose location history must not leave the machine
+//!
+//! Zones live in `zones.json` in the config directory and are only ever
+//! logged by id, never by their coordinates. Rules in `devices.json` keep
+//! devices out of uploads wherever they are seen, by vendor or address type.
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+use super::Report;
+use crate::config::{DEVICE_RULES_FILE, PRIVACY_MAX_RADIUS_METERS, ZONES_FILE, config_dir};
+use crate::error::{Error, Result};
+use crate::geosubmit::Position;
+use crate::position::geo::distance_meters;
+use crate::scanner::BleDevice;
+use crate::scanner::address::RandomAddressPolicy;
+use crate::scanner::oui::MacInfo;
+
+/// Devices removed from a report, by the rule that removed them
+pub type Dropped = BTreeMap<&'static str, usize>;
+
+static LOCAL: Lazy<ZoneStore> = Lazy::new(|| ZoneStore::open(config_dir().join(ZONES_FILE)));
+static LOCAL_RULES: Lazy<DeviceRuleStore> =
+    Lazy::new(|| DeviceRuleStore::open(config_dir().join(DEVICE_RULES_FILE)));
+
+// makers of mobile hotspots, which travel with their owners
+const HOTSPOT_VENDORS: [&str; 4] = [
+    "Novatel Wireless",
+    "Franklin Wireless",
+    "Inseego",
+    "Sierra Wireless",
+];
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Shape {
+    Circle {
+        latitude: f64,
+        longitude: f64,
+        radius: f64, // in meters
+    },
+    Polygon {
+        points: Vec<[f64; 2]>, // [latitude, longitude], closed implicitly
+    },
+}
+
+impl Shape {
+    pub fn contains(&self, latitude: f64, longitude: f64) -> bool {
+        match self {
+            Shape::Circle {
+                latitude: lat,
+                longitude: lon,
+                radius,
+            } => distance_meters(*lat, *lon, latitude, longitude) <= *radius,
+            Shape::Polygon { points } => {
+                // even-odd rule, treating degrees as planar at this scale
+                let mut inside = false;
+                let mut previous = points.len().wrapping_sub(1);
+                for (current, &[lat_i, lon_i]) in points.iter().enumerate() {
+                    let [lat_j, lon_j] = points[previous];
+                    if (lat_i > latitude) != (lat_j > latitude)
+                        && longitude
+                            < (lon_j - lon_i) * (latitude - lat_i) / (lat_j - lat_i) + lon_i
+                    {
+                        inside = !inside;
+                    }
+                    previous = current;
+                }
+                inside
+            }
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        let in_range =
+            |lat: f64, lon: f64| (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon);
+        match self {
+            Shape::Circle {
+                latitude,
+                longitude,
+                radius,
+            } => {
+                if !in_range(*latitude, *longitude) {
+                    return Err(Error::InvalidZone("center out of range".into()));
+                }
+                if !(*radius > 0.0 && *radius <= PRIVACY_MAX_RADIUS_METERS) {
+                    return Err(Error::InvalidZone(format!(
+                        "radius must be between 0 and {} m",
+                        PRIVACY_MAX_RADIUS_METERS
+                    )));
+                }
+            }
+            Shape::Polygon { points } => {
+                if points.len() < 3 {
+                    return Err(Error::InvalidZone("polygon needs 3 points".into()));
+                }
+                if !points.iter().all(|&[lat, lon]| in_range(lat, lon)) {
+                    return Err(Error::InvalidZone("point out of range".into()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Shape::Circle { .. } => "circle",
+            Shape::Polygon { .. } => "polygon",
+        }
+    }
+}
+
+// the whole point of a zone is that nobody learns where it is
+impl fmt::Debug for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}(<redacted>)", self.kind())
+    }
+}
+
+/// What happens to reports positioned inside a zone
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub enum ZoneAction {
+    DropPosition, // archive the scans without a position, never upload them
+    #[default]
+    DropReport,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Zone {
+    pub id: String,
+    #[serde(default)]
+    pub action: ZoneAction,
+    pub shape: Shape,
+}
+
+/// A zone as sent by the phone, before it has an id
+#[derive(Deserialize, Debug, Clone)]
+pub struct NewZone {
+    #[serde(default)]
+    pub action: ZoneAction,
+    pub shape: Shape,
+}
+
+/// A zone as listed back, without its coordinates
+#[derive(Serialize, Debug, Clone)]
+pub struct ZoneSummary {
+    pub id: String,
+    pub action: ZoneAction,
+    pub shape: &'static str,
+}
+
+/// What `apply` did to a report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Keep,
+    PositionDropped,
+    Dropped,
+}
+
+/// Zones persisted in one file
+#[derive(Debug)]
+pub struct ZoneStore {
+    path: PathBuf,
+    zones: Mutex<Option<Vec<Zone>>>, // loaded on first use
+}
+
+impl ZoneStore {
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        ZoneStore {
+            path: path.into(),
+            zones: Mutex::new(None),
+        }
+    }
+
+    /// The zones in the config directory
+    pub fn local() -> &'static ZoneStore {
+        &LOCAL
+    }
+
+    fn with_zones<T>(&self, f: impl FnOnce(&mut Vec<Zone>) -> Result<T>) -> Result<T> {
+        let mut zones = self.zones.lock().unwrap_or_else(|e| e.into_inner());
+        if zones.is_none() {
+            *zones = Some(match fs::read_to_string(&self.path) {
+                Ok(contents) => serde_json::from_str(&contents)?,
+                Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+                Err(e) => return Err(e.into()),
+            });
+        }
+        f(zones.get_or_insert_with(Vec::new))
+    }
+
+    fn save(&self, zones: &[Zone]) -> Result<()> {
+        let temporary = self.path.with_extension("tmp");
+        fs::write(&temporary, serde_json::to_vec_pretty(zones)?)?;
+        fs::rename(temporary, &self.path)?;
+        Ok(())
+    }
+
+    pub fn list(&self) -> Result<Vec<ZoneSummary>> {
+        self.with_zones(|zones| {
+            Ok(zones
+                .iter()
+                .map(|z| ZoneSummary {
+                    id: z.id.clone(),
+                    action: z.action,
+                    shape: z.shape.kind(),
+                })
+                .collect())
+        })
+    }
+
+    /// Store a zone and return its id
+    pub fn add(&self, zone: NewZone) -> Result<String> {
+        zone.shape.validate()?;
+        self.with_zones(|zones| {
+            let id = Uuid::new_v4().to_string();
+            zones.push(Zone {
+                id: id.clone(),
+                action: zone.action,
+                shape: zone.shape,
+            });
+            if let Err(e) = self.save(zones) {
+                zones.pop();
+                return Err(e);
+            }
+            Ok(id)
+        })
+    }
+
+    /// Remove a zone, returning whether it existed
+    pub fn remove(&self, id: &str) -> Result<bool> {
+        self.with_zones(|zones| {
+            let before = zones.len();
+            zones.retain(|z| z.id != id);
+            if zones.len() == before {
+                return Ok(false);
+            }
+            self.save(zones)?;
+            Ok(true)
+        })
+    }
+
+    /// The strictest action of the zones containing `position`
+    pub fn check(&self, position: &Position) -> Option<ZoneAction> {
+        let result = self.with_zones(|zones| {
+            Ok(zones
+                .iter()
+                .filter(|z| z.shape.contains(position.latitude, position.longitude))
+                .map(|z| z.action)
+                .max())
+        });
+        result.unwrap_or_else(|e| {
+            // can't tell where the zones are, so assume we are in one
+            tracing::error!("Failed to load privacy zones: {}", e);
+            Some(ZoneAction::DropReport)
+        })
+    }
+
+    /// Drop `report`, or its position, when it was made inside a zone
+    pub fn apply(&self, report: &mut Report) -> Verdict {
+        let Some(position) = &report.position else {
+            return Verdict::Keep;
+        };
+        match self.check(position) {
+            None => Verdict::Keep,
+            Some(ZoneAction::DropPosition) => {
+                report.position = None;
+                Verdict::PositionDropped
+            }
+            Some(ZoneAction::DropReport) => Verdict::Dropped,
+        }
+    }
+}
+
+/// Devices never uploaded, whatever their position
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceRules {
+    #[serde(default)]
+    pub vendors: Vec<String>, // matched case-insensitively anywhere in the vendor name
+    #[serde(default)]
+    pub locally_administered: bool, // also drop addresses without a vendor
+    #[serde(default)]
+    pub stationary_beacons_only: bool, // drop BLE devices that aren't fixed beacons
+    #[serde(default)]
+    pub random_addresses: RandomAddressPolicy, // BLE address types dropped
+}
+
+impl Default for DeviceRules {
+    fn default() -> Self {
+        DeviceRules {
+            vendors: HOTSPOT_VENDORS.iter().map(|v| v.to_string()).collect(),
+            // access points often serve extra networks from such addresses
+            locally_administered: false,
+            stationary_beacons_only: false,
+            random_addresses: RandomAddressPolicy::default(),
+        }
+    }
+}
+
+impl DeviceRules {
+    pub fn matches(&self, info: &MacInfo) -> bool {
+        if self.locally_administered && info.locally_administered {
+            return true;
+        }
+        let Some(vendor) = &info.vendor else {
+            return false;
+        };
+        let vendor = vendor.to_lowercase();
+        self.vendors
+            .iter()
+            .any(|v| vendor.contains(&v.to_lowercase()))
+    }
+
+    /// Why `device` is never uploaded, `None` if it may be
+    pub fn reason(&self, device: &BleDevice) -> Option<&'static str> {
+        if self.matches(&device.mac_info) {
+            return Some("vendor");
+        }
+        if let Some(kind) = device.advertisement.address_type
+            && self.random_addresses.drops(kind)
+        {
+            return Some(kind.label());
+        }
+        if self.stationary_beacons_only && !device.advertisement.is_stationary() {
+            return Some("notStationary");
+        }
+        None
+    }
+}
+
+/// Device rules persisted in one file
+#[derive(Debug)]
+pub struct DeviceRuleStore {
+    path: PathBuf,
+    rules: Mutex<Option<DeviceRules>>, // loaded on first use
+}
+
+impl DeviceRuleStore {
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        DeviceRuleStore {
+            path: path.into(),
+            rules: Mutex::new(None),
+        }
+    }
+
+    /// The rules in the config directory
+    pub fn local() -> &'static DeviceRuleStore {
+        &LOCAL_RULES
+    }
+
+    fn with_rules<T>(&self, f: impl FnOnce(&mut DeviceRules) -> Result<T>) -> Result<T> {
+        let mut rules = self.rules.lock().unwrap_or_else(|e| e.into_inner());
+        if rules.is_none() {
+            *rules = Some(match fs::read_to_string(&self.path) {
+                Ok(contents) => serde_json::from_str(&contents)?,
+                Err(e) if e.kind() == ErrorKind::NotFound => DeviceRules::default(),
+                Err(e) => return Err(e.into()),
+            });
+        }
+        f(rules.get_or_insert_with(DeviceRules::default))
+    }
+
+    pub fn get(&self) -> Result<DeviceRules> {
+        self.with_rules(|rules| Ok(rules.clone()))
+    }
+
+    /// Replace the rules, ignoring blank vendors that would match everything
+    pub fn set(&self, mut new: DeviceRules) -> Result<DeviceRules> {
+        new.vendors = new
+            .vendors
+            .iter()
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+            .collect();
+        self.with_rules(|rules| {
+            let temporary = self.path.with_extension("tmp");
+            fs::write(&temporary, serde_json::to_vec_pretty(&new)?)?;
+            fs::rename(temporary, &self.path)?;
+            *rules = new.clone();
+            Ok(new)
+        })
+    }
+
+    /// Remove matching devices from `report`, returning how many each rule removed
+    pub fn strip(&self, report: &mut Report) -> Dropped {
+        let rules = self.get().unwrap_or_else(|e| {
+            // fall back to the defaults rather than uploading everything
+            tracing::error!("Failed to load device rules: {}", e);
+            DeviceRules::default()
+        });
+        let mut dropped = Dropped::new();
+        report.wifi_access_points.retain(|ap| {
+            let matched = rules.matches(&ap.mac_info);
+            if matched {
+                *dropped.entry("vendor").or_default() += 1;
+            }
+            !matched
+        });
+        report.bluetooth_beacons.retain(|b| match rules.reason(b) {
+            Some(reason) => {
+                *dropped.entry(reason).or_default() += 1;
+                false
+            }
+            None => true,
+        });
+
+        if !dropped.is_empty() {
+            tracing::info!("Device rules dropped {:?}", dropped);
+        }
+        dropped
+    }
+}
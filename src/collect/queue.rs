@@ -0,0 +1,83 @@
+//! Reports waiting to be submitted, kept on disk across restarts
+
+use std::future::Future;
+use std::path::PathBuf;
+
+use super::archive::{Line, append_line, read_entries, read_lines, replace_lines};
+use crate::config::{QUEUE_FILE, config_dir};
+use crate::error::Result;
+use crate::geosubmit::{items, submit_geo_payload};
+
+#[derive(Debug, Clone)]
+pub struct Queue {
+    path: PathBuf,
+}
+
+impl Queue {
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        Queue { path: path.into() }
+    }
+
+    /// The queue in the config directory
+    pub fn local() -> Self {
+        Queue::open(config_dir().join(QUEUE_FILE))
+    }
+
+    pub fn push(&self, report: &items) -> Result<()> {
+        append_line(&self.path, report)
+    }
+
+    pub fn pending(&self) -> Result<Vec<items>> {
+        read_lines(&self.path)
+    }
+
+    /// Submit queued reports in order, stopping at the first failure worth
+    /// retrying. Reports the provider rejects are dropped. Returns how many
+    /// were submitted.
+    pub async fn flush(&self) -> Result<usize> {
+        self.flush_with(submit_geo_payload).await
+    }
+
+    /// Like [`Queue::flush`], submitting with `submit`
+    pub async fn flush_with<F, Fut>(&self, mut submit: F) -> Result<usize>
+    where
+        F: FnMut(items) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let entries: Vec<Line<items>> = read_entries(&self.path)?;
+        let read = entries.len();
+
+        let mut submitted = 0;
+        let mut rejected = 0;
+        let mut keep = Vec::new();
+        let mut failed = false;
+        for entry in entries {
+            match entry {
+                Line::Parsed(report) if !failed => match submit(report.clone()).await {
+                    Ok(()) => submitted += 1,
+                    Err(e) if !e.is_transient() => {
+                        tracing::warn!("Dropping queued report the provider rejected: {}", e);
+                        rejected += 1;
+                    }
+                    Err(e) => {
+                        tracing::warn!("Queued report not submitted: {}", e);
+                        failed = true;
+                        keep.push(Line::Parsed(report));
+                    }
+                },
+                entry => keep.push(entry), // unsent, or a line we can't read
+            }
+        }
+
+        if submitted + rejected > 0 {
+            replace_lines(&self.path, read, &keep)?;
+            tracing::info!(
+                "Submitted {} queued reports, dropped {}, {} left",
+                submitted,
+                rejected,
+                keep.len()
+            );
+        }
+        Ok(submitted)
+    }
+}
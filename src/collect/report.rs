@@ -0,0 +1,53 @@
+//! One round of observations, with or without a position
+
+use serde::{Deserialize, Serialize};
+
+use crate::geosubmit::{CellTower, Position, assemble_geo_payload, items};
+use crate::scanner::cache::Observations;
+use crate::scanner::{BleDevice, WifiBssid};
+use crate::time::unix_millis;
+
+/// Scanner records as archived, richer than the geosubmit wire format
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub timestamp: u128, // in milliseconds since Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<Position>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub wifi_access_points: Vec<WifiBssid>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bluetooth_beacons: Vec<BleDevice>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cell_towers: Vec<CellTower>,
+}
+
+impl Report {
+    pub fn new(observations: Observations, position: Option<Position>) -> Self {
+        let timestamp = observations.timestamp().unwrap_or_else(unix_millis);
+        Report {
+            timestamp,
+            position,
+            wifi_access_points: observations.wifi.map(|s| s.records).unwrap_or_default(),
+            bluetooth_beacons: observations.ble.map(|s| s.records).unwrap_or_default(),
+            cell_towers: observations.cell.map(|s| s.records).unwrap_or_default(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.wifi_access_points.is_empty()
+            && self.bluetooth_beacons.is_empty()
+            && self.cell_towers.is_empty()
+    }
+
+    /// Geosubmit report, `None` until the report has a position
+    pub fn to_items(&self) -> Option<items> {
+        Some(assemble_geo_payload(
+            self.timestamp,
+            self.position.clone()?,
+            &self.wifi_access_points,
+            &self.bluetooth_beacons,
+            self.cell_towers.clone(),
+        ))
+    }
+}
@@ -0,0 +1,290 @@
+//! `serviceberry collect`: scan and submit on our own, with a local position source
+
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use super::privacy::Verdict;
+use super::{Archive, Policy, Queue, Report};
+use crate::config::{
+    COLLECT_BLIND_INTERVAL_SECS, COLLECT_DISTANCE_METERS, COLLECT_MAX_FIX_AGE_MS,
+    COLLECT_MAX_INTERVAL_SECS, COLLECT_MIN_INTERVAL_SECS, COLLECT_STATIONARY_SPEED, GPSD_ADDRESS,
+    NMEA_BAUD_RATE,
+};
+use crate::error::{Error, Result};
+use crate::geosubmit::submit_geo_payload;
+use crate::position::nmea::{self, NmeaSource};
+use crate::position::{FixReceiver, gpsd};
+use crate::scanner::ScanProfile;
+use crate::scanner::cache::{self, ScanKind, ScanOptions};
+use crate::server::{metrics, status};
+
+pub const USAGE: &str = "\
+Usage: serviceberry collect [OPTIONS]
+
+Options:
+  --gpsd <HOST:PORT>    read positions from gpsd (default 127.0.0.1:2947)
+  --nmea <DEVICE>       read NMEA sentences from a serial receiver
+  --baud <RATE>         serial baud rate for --nmea (default 9600)
+  --nmea-file <PATH>    replay a recorded NMEA file
+  --no-position         archive scans without a position, for `import-gpx` later
+  --no-submit           only archive reports, don't submit them
+  --profile <PROFILE>   Wi-Fi scan profile: fast, full (default), passive or 6ghz";
+
+/// Where collect mode gets its positions from
+#[derive(Debug, Clone)]
+pub enum PositionProvider {
+    Gpsd(String),
+    Nmea(NmeaSource),
+}
+
+impl PositionProvider {
+    fn spawn(&self) -> FixReceiver {
+        match self {
+            PositionProvider::Gpsd(address) => gpsd::spawn(address.clone()),
+            PositionProvider::Nmea(source) => nmea::spawn(source.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CollectOptions {
+    pub provider: Option<PositionProvider>, // None to scan blind and position later
+    pub submit: bool,
+    pub profile: ScanProfile,
+}
+
+impl CollectOptions {
+    /// Parse the arguments following `collect`
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut provider = PositionProvider::Gpsd(GPSD_ADDRESS.to_string());
+        let mut baud_rate = NMEA_BAUD_RATE;
+        let mut submit = true;
+        let mut positioned = true;
+        let mut profile = ScanProfile::default();
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| Error::Config(format!("{} needs a value\n\n{}", arg, USAGE)))
+            };
+            match arg.as_str() {
+                "--gpsd" => provider = PositionProvider::Gpsd(value()?),
+                "--nmea" => {
+                    provider = PositionProvider::Nmea(NmeaSource::Serial {
+                        path: value()?,
+                        baud_rate,
+                    })
+                }
+                "--nmea-file" => {
+                    provider = PositionProvider::Nmea(NmeaSource::File(PathBuf::from(value()?)))
+                }
+                "--baud" => {
+                    baud_rate = value()?
+                        .parse()
+                        .map_err(|_| Error::Config(format!("invalid baud rate\n\n{}", USAGE)))?
+                }
+                "--no-position" => positioned = false,
+                "--no-submit" => submit = false,
+                "--profile" => {
+                    profile = value()?
+                        .parse()
+                        .map_err(|e| Error::Config(format!("{}\n\n{}", e, USAGE)))?
+                }
+                other => {
+                    return Err(Error::Config(format!(
+                        "unknown option {}\n\n{}",
+                        other, USAGE
+                    )));
+                }
+            }
+        }
+
+        // --baud may come after --nmea
+        if let PositionProvider::Nmea(NmeaSource::Serial {
+            baud_rate: rate, ..
+        }) = &mut provider
+        {
+            *rate = baud_rate;
+        }
+        Ok(CollectOptions {
+            provider: positioned.then_some(provider),
+            submit,
+            profile,
+        })
+    }
+}
+
+/// Time between the starts of two scans: about one report every
+/// `COLLECT_DISTANCE_METERS` while moving, rarely while standing still
+pub fn next_interval(speed: Option<f64>) -> Duration {
+    let max = Duration::from_secs(COLLECT_MAX_INTERVAL_SECS);
+    let min = Duration::from_secs(COLLECT_MIN_INTERVAL_SECS);
+    match speed {
+        Some(speed) if speed >= COLLECT_STATIONARY_SPEED => {
+            Duration::from_secs_f64(COLLECT_DISTANCE_METERS / speed).clamp(min, max)
+        }
+        _ => max,
+    }
+}
+
+/// What happened to a report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Submitted,
+    Queued,     // submission failed, will be retried
+    Archived,   // no position, or submitting is disabled
+    Suppressed, // made inside a privacy zone, not even archived
+    Rejected,   // refused by the provider, only archived
+}
+
+/// Run `f` on the blocking thread pool, for the stores, archive and queue
+/// that read and write files
+pub async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(value) => value,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+/// Archive a report, then submit it, queueing it when the submission fails.
+/// Privacy zones are applied first, and archived reports feed mobile detection.
+pub async fn dispatch(
+    report: &Report,
+    archive: &Archive,
+    queue: &Queue,
+    policy: Policy<'static>,
+    submit: bool,
+) -> Outcome {
+    let mut report = report.clone();
+    let archive = archive.clone();
+    let archived = blocking(move || {
+        if policy.zones.apply(&mut report) == Verdict::Dropped {
+            return None;
+        }
+        if let Err(e) = archive.append(&report) {
+            tracing::error!("Failed to archive report: {}", e);
+        }
+        if let Err(e) = policy.mobiles.observe(&report) {
+            tracing::warn!("Failed to update mobile devices: {}", e);
+        }
+        Some(report)
+    })
+    .await;
+    let Some(report) = archived else {
+        return Outcome::Suppressed;
+    };
+    if !submit {
+        return Outcome::Archived;
+    }
+    send(&report, queue, policy).await
+}
+
+/// Submit a positioned report without its mobile or excluded devices,
+/// queueing it when the submission fails
+pub async fn send(report: &Report, queue: &Queue, policy: Policy<'static>) -> Outcome {
+    let mut report = report.clone();
+    let (report, dropped) = blocking(move || {
+        let dropped = policy.strip(&mut report);
+        (report, dropped)
+    })
+    .await;
+    metrics::observe_dropped(&dropped);
+    status::record_dropped(&dropped);
+    let Some(payload) = report.to_items() else {
+        return Outcome::Archived;
+    };
+    match submit_geo_payload(payload.clone()).await {
+        Ok(()) => Outcome::Submitted,
+        Err(e) if !e.is_transient() => {
+            tracing::warn!("Submission rejected, not queueing report: {}", e);
+            Outcome::Rejected
+        }
+        Err(e) => {
+            tracing::warn!("Submission failed, queueing report: {}", e);
+            let queue = queue.clone();
+            match blocking(move || queue.push(&payload)).await {
+                Ok(()) => Outcome::Queued,
+                Err(e) => {
+                    tracing::error!("Failed to queue report: {}", e);
+                    Outcome::Archived
+                }
+            }
+        }
+    }
+}
+
+/// Get a fix, scan, dispatch, repeat
+pub async fn run(options: CollectOptions) -> Result<()> {
+    let archive = Archive::local();
+    let queue = Queue::local();
+    let policy = Policy::local();
+    match policy.mobiles.learn(&archive) {
+        Ok(flagged) => println!("Found {} mobile devices in the archive", flagged),
+        Err(e) => tracing::warn!("Failed to learn mobile devices: {}", e),
+    }
+    let mut fixes = options.provider.as_ref().map(PositionProvider::spawn);
+    match &options.provider {
+        Some(provider) => println!("Collecting with {:?}", provider),
+        None => println!("Collecting without positions, import a GPX track later"),
+    }
+    println!("Scanning Wi-Fi with the {} profile", options.profile);
+
+    loop {
+        if let Some(fixes) = fixes.as_mut()
+            && fixes.borrow().is_none()
+        {
+            println!("Waiting for a position fix");
+            fixes
+                .wait_for(Option::is_some)
+                .await
+                .map_err(|_| Error::Gps("position provider stopped".into()))?;
+        }
+
+        let started = Instant::now();
+        let scan = ScanOptions {
+            profile: options.profile,
+            ..Default::default()
+        };
+        let observations = cache::scan_with(ScanKind::All, Duration::ZERO, scan).await;
+        let mut report = Report::new(observations, None);
+
+        let fix = fixes.as_ref().and_then(|f| f.borrow().clone());
+        let speed = fix.as_ref().and_then(|f| f.position.speed);
+        match fix {
+            Some(fix)
+                if report.timestamp.abs_diff(fix.timestamp) <= COLLECT_MAX_FIX_AGE_MS as u128 =>
+            {
+                report.position = Some(fix.position_at(report.timestamp));
+            }
+            _ if fixes.is_none() => {}
+            _ => println!("Lost the fix during the scan, archiving without a position"),
+        }
+
+        if report.is_empty() {
+            println!("Nothing observed, skipping report");
+        } else {
+            let outcome = dispatch(&report, &archive, &queue, policy, options.submit).await;
+            println!(
+                "Report with {} access points, {} beacons, {} cells: {:?}",
+                report.wifi_access_points.len(),
+                report.bluetooth_beacons.len(),
+                report.cell_towers.len(),
+                outcome
+            );
+            if outcome == Outcome::Submitted {
+                // the network is back, catch up on what failed before
+                if let Err(e) = queue.flush().await {
+                    tracing::warn!("Failed to flush queue: {}", e);
+                }
+            }
+        }
+
+        let interval = match fixes {
+            Some(_) => next_interval(speed),
+            None => Duration::from_secs(COLLECT_BLIND_INTERVAL_SECS), // speed unknown until import
+        };
+        tokio::time::sleep_until(started + interval).await;
+    }
+}
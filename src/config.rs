@@ -6,11 +6,56 @@ use rustls::pki_types::{CertificateDer, PrivateKeyDer};
 use std::{error::Error, fs, path::PathBuf};
 
 pub const SCAN_DURATION_SECS: u64 = 10;
+pub const BLE_SNAPSHOT_MAX_AGE_MS: u64 = 10_000; // beacons heard longer ago are left out of snapshots
+pub const BLE_RETENTION_MS: u64 = 120_000; // advertisements kept for scan windows
+pub const BLE_MAX_SIGHTINGS: usize = 512; // advertisements kept per device, oldest are forgotten first
+pub const BLE_RETRY_SECS: u64 = 5; // wait before looking for the adapter again
+pub const WIFI_SCAN_TIMEOUT_SECS: u64 = 15; // give up waiting for the kernel to report a finished scan
+pub const WIFI_MAX_AGE_MS: u64 = 30_000; // access points last seen longer ago are left out of scans
+pub const SCAN_WINDOW_MAX_PASSES: u32 = 6; // scans one request may combine into a window
 pub const GEOSUBMIT_ENDPOINT: &str = "https://api.beacondb.net/v2/geosubmit";
 pub const APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
 pub const MDNS_SERVICE_TYPE: &str = "serviceberry"; // no capitals
 pub const HTTP_SERVER_PORT: u16 = 8080;
 pub const DEFAULT_HOSTNAME: &str = "turtle";
+pub const REQUEST_MAX_AGE_MS: u64 = 30_000; // default freshness for /request results
+pub const EVENT_BUFFER_SIZE: usize = 256; // events kept for slow /events listeners
+pub const SUBMIT_RATE_BURST: u32 = 3; // submissions a client may make back to back
+pub const SUBMIT_RATE_INTERVAL_SECS: u64 = 30; // time for a client to earn another submission
+pub const STREAM_RATE_BURST: u32 = 10; // `POST /location` requests a client may make back to back
+pub const STREAM_RATE_INTERVAL_SECS: u64 = 1; // time for a client to earn another request
+pub const MAX_SCAN_WAITERS: usize = 8; // requests allowed to wait on the in-flight scan
+pub const GEOSUBMIT_PROVIDER: &str = "beacondb"; // name reported in /status
+pub const GPSD_ADDRESS: &str = "127.0.0.1:2947"; // gpsd's default JSON port
+pub const NMEA_BAUD_RATE: u32 = 9600; // NMEA 0183 standard rate, most USB receivers default to it
+pub const ARCHIVE_FILE: &str = "archive.jsonl"; // every collected report, in the config dir
+pub const ZONES_FILE: &str = "zones.json"; // privacy zones, in the config dir
+pub const MOBILE_FILE: &str = "mobile.json"; // devices flagged as mobile, in the config dir
+pub const OUI_FILE: &str = "oui.csv"; // installed IEEE registry, in the config dir
+pub const DEVICE_RULES_FILE: &str = "devices.json"; // vendors kept out of uploads, in the config dir
+pub const QUEUE_FILE: &str = "queue.jsonl"; // reports whose submission failed, in the config dir
+pub const COLLECT_DISTANCE_METERS: f64 = 50.0; // spacing between reports while moving
+pub const COLLECT_STATIONARY_SPEED: f64 = 0.5; // in m/s, slower counts as standing still
+pub const COLLECT_MIN_INTERVAL_SECS: u64 = 2; // shortest time between scan starts
+pub const COLLECT_MAX_INTERVAL_SECS: u64 = 120; // time between scans while standing still
+pub const COLLECT_MAX_FIX_AGE_MS: u64 = 5_000; // drop positions older than this at scan end
+pub const COLLECT_BLIND_INTERVAL_SECS: u64 = 15; // time between scans when collecting without positions
+pub const GPX_MAX_GAP_MS: u64 = 30_000; // don't interpolate between track points further apart
+pub const TRAJECTORY_WINDOW_MS: u64 = 600_000; // positions kept per streaming phone
+pub const TRAJECTORY_MAX_GAP_MS: u64 = 10_000; // don't interpolate across longer pauses in a stream
+pub const STREAM_SCAN_INTERVAL_SECS: u64 = 15; // background scans while a phone is streaming
+pub const STREAM_IDLE_MS: u64 = 30_000; // stop background scans after this long without updates
+pub const LOCATION_LINE_MAX_BYTES: usize = 1024; // longer location updates are dropped
+pub const POSITION_MAX_ACCURACY_METERS: f64 = 100.0; // coarser phone positions are rejected
+pub const POSITION_MAX_SPEED: f64 = 70.0; // in m/s, faster movement between fixes is a jump
+pub const POSITION_MAX_JUMPS: u32 = 3; // consecutive jumps after which the new location is believed
+pub const POSITION_FILTER_RESET_MS: u64 = 300_000; // fixes further apart are checked on their own
+pub const POSITION_SMOOTHING: bool = false; // Kalman-smooth phone positions before submitting
+pub const PRIVACY_MAX_RADIUS_METERS: f64 = 50_000.0; // larger circles are probably a mistake
+pub const MOBILE_DISTANCE_METERS: f64 = 1_000.0; // farther than any access point reaches
+pub const MOBILE_WINDOW_MS: u64 = 86_400_000; // sightings this close in time are compared
+pub const MOBILE_MAX_SIGHTINGS: usize = 32; // per device, oldest are forgotten first
+pub const POSITION_KALMAN_NOISE: f64 = 3.0; // in m/s, how fast the phone's position drifts
 
 /// Get the project configuration directory
 pub fn config_dir() -> PathBuf {
@@ -26,6 +71,7 @@ pub struct Identity {
     pub certs: Vec<CertificateDer<'static>>,
     pub key: PrivateKeyDer<'static>,
     pub certs_hash: [u8; 32],
+    pub not_after: Option<i64>, // certificate expiry, in seconds since Unix epoch
 }
 
 /// Generate self-signed certificate and key if they don't already exist in the config dir
@@ -63,10 +109,7 @@ pub fn load_identity(
     let keys = fs::read(key_path)?;
 
     let cert_content: Vec<CertificateDer<'static>> = rustls_pemfile::certs(&mut &*certs) // load cert from file into PEM format
-        .collect::<Result<Vec<_>, _>>()?
-        .into_iter()
-        .map(CertificateDer::from)
-        .collect();
+        .collect::<Result<Vec<_>, _>>()?;
     let key_content = PrivateKeyDer::from(
         // load key from file into PEM format
         rustls_pemfile::pkcs8_private_keys(&mut &*keys)
@@ -75,7 +118,7 @@ pub fn load_identity(
             .ok_or("No private key found")?,
     );
 
-    Ok(Identity::new(cert_content, key_content)?)
+    Identity::new(cert_content, key_content)
 }
 
 impl Identity {
@@ -87,18 +130,30 @@ impl Identity {
             certs,
             key,
             certs_hash: [0u8; 32],
+            not_after: None,
         };
         identity.certs_hash = identity.fingerprint_sha256()?;
+        identity.not_after = identity.expiry()?;
         Ok(identity)
     }
 
+    /// Get the expiry (notAfter) of the certificate
+    fn expiry(&self) -> Result<Option<i64>, Box<dyn std::error::Error>> {
+        let Some(cert) = self.certs.first() else {
+            return Ok(None);
+        };
+
+        let (_, parsed) = x509_parser::parse_x509_certificate(cert.as_ref())?;
+        Ok(Some(parsed.validity().not_after.timestamp()))
+    }
+
     /// Get SHA256 fingerprint of the certificate
     fn fingerprint_sha256(&self) -> Result<[u8; 32], Box<dyn std::error::Error>> {
         use sha2::{Digest, Sha256};
 
         let cert = self
             .certs
-            .get(0)
+            .first()
             .ok_or("No certificates available for fingerprint")?;
 
         let mut hasher = Sha256::new();
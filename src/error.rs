@@ -1,26 +1,40 @@
 use std::fmt;
+use std::time::Duration;
 
 use axum::{
     Json,
     response::{IntoResponse, Response},
 };
-use hyper::StatusCode;
+use hyper::{StatusCode, header};
 use serde_json::json;
 
+use crate::position::filter::Rejection;
+
 #[derive(Debug)]
 pub enum Error {
     // Scanner errors
     BleAdapter(String),
     WifiScan(String),
+    CellScan(String),
     InvalidSsid(String),
+    InvalidChannel(String),
+    InvalidScan(String),
+    InvalidAddress(String),
 
     // Geosubmit errors
     Transport(String),
     HttpStatus { status: u16, body: String },
     Serialization(String),
+    InvalidCell(String),
+
+    // Position errors
+    Gps(String),
+    PositionRejected(Rejection),
+    InvalidZone(String),
 
     // Server errors
     Bind(String),
+    RateLimited { retry_after: Duration },
 
     // Config errors
     Config(String),
@@ -38,11 +52,24 @@ impl fmt::Display for Error {
         match self {
             Error::BleAdapter(msg) => write!(f, "BLE adapter error: {}", msg),
             Error::WifiScan(msg) => write!(f, "WiFi scan error: {}", msg),
+            Error::CellScan(msg) => write!(f, "Cell scan error: {}", msg),
             Error::InvalidSsid(msg) => write!(f, "Invalid SSID: {}", msg),
+            Error::InvalidChannel(msg) => write!(f, "Invalid channel: {}", msg),
+            Error::InvalidScan(msg) => write!(f, "Invalid scan request: {}", msg),
+            Error::InvalidAddress(msg) => write!(f, "Invalid address: {}", msg),
             Error::Transport(msg) => write!(f, "Transport error: {}", msg),
             Error::HttpStatus { status, body } => write!(f, "HTTP {}: {}", status, body),
             Error::Serialization(msg) => write!(f, "Serialization error: {}", msg),
+            Error::InvalidCell(msg) => write!(f, "Invalid cell tower: {}", msg),
+            Error::Gps(msg) => write!(f, "GPS error: {}", msg),
+            Error::PositionRejected(rejection) => write!(f, "Position rejected: {}", rejection),
+            Error::InvalidZone(msg) => write!(f, "Invalid privacy zone: {}", msg),
             Error::Bind(msg) => write!(f, "Bind error: {}", msg),
+            Error::RateLimited { retry_after } => write!(
+                f,
+                "Rate limited, retry after {}s",
+                retry_after.as_secs_f64().ceil()
+            ),
             Error::Config(msg) => write!(f, "Config error: {}", msg),
             Error::Io(e) => write!(f, "IO error: {}", e),
             Error::Json(e) => write!(f, "JSON error: {}", e),
@@ -51,6 +78,20 @@ impl fmt::Display for Error {
     }
 }
 
+impl Error {
+    /// Whether a failed submission is worth retrying: transport errors, server
+    /// errors and rate limiting are, other 4xx rejections will only fail again
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Transport(_) | Error::RateLimited { .. } => true,
+            Error::HttpStatus { status, .. } => {
+                !(400..500).contains(status) || matches!(status, 408 | 429)
+            }
+            _ => false,
+        }
+    }
+}
+
 impl std::error::Error for Error {}
 
 impl From<std::io::Error> for Error {
@@ -71,17 +112,45 @@ pub type Result<T> = std::result::Result<T, Error>;
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
         let (status, error_message) = match self {
-            Error::Other(ref msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
-            Error::Bind(ref msg) => (StatusCode::BAD_REQUEST, msg),
-            Error::BleAdapter(_) => todo!(),
-            Error::WifiScan(_) => todo!(),
-            Error::InvalidSsid(_) => todo!(),
-            Error::Transport(_) => todo!(),
-            Error::HttpStatus { status, body } => todo!(),
-            Error::Serialization(_) => todo!(),
-            Error::Config(_) => todo!(),
-            Error::Io(error) => todo!(),
-            Error::Json(error) => todo!(),
+            Error::RateLimited { retry_after } => {
+                let seconds = retry_after.as_secs_f64().ceil() as u64;
+                let body = Json(json!({
+                    "error": self.to_string(),
+                    "retryAfter": seconds,
+                }));
+                return (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    [(header::RETRY_AFTER, seconds.to_string())],
+                    body,
+                )
+                    .into_response();
+            }
+            Error::PositionRejected(ref rejection) => {
+                let body = Json(json!({
+                    "error": self.to_string(),
+                    "rejection": rejection,
+                }));
+                return (StatusCode::UNPROCESSABLE_ENTITY, body).into_response();
+            }
+            Error::Other(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
+            Error::Bind(msg) => (StatusCode::BAD_REQUEST, msg),
+            Error::BleAdapter(_) | Error::WifiScan(_) | Error::CellScan(_) | Error::Gps(_) => {
+                (StatusCode::SERVICE_UNAVAILABLE, self.to_string())
+            }
+            Error::InvalidSsid(_)
+            | Error::InvalidChannel(_)
+            | Error::InvalidScan(_)
+            | Error::InvalidAddress(_)
+            | Error::InvalidCell(_)
+            | Error::InvalidZone(_)
+            | Error::Serialization(_)
+            | Error::Json(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            Error::Transport(_) | Error::HttpStatus { .. } => {
+                (StatusCode::BAD_GATEWAY, self.to_string())
+            }
+            Error::Config(_) | Error::Io(_) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, self.to_string())
+            }
         };
 
         let body = Json(json!({
@@ -1,65 +1,102 @@
 //! HTTP client for submitting geosubmit payloads
 
 use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
-use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
+use reqwest_retry::{RetryTransientMiddleware, policies::ExponentialBackoff};
 use reqwest_tracing::TracingMiddleware;
-use std::time::{SystemTime, UNIX_EPOCH};
-use tokio::time::Instant;
 
-use crate::config::{APP_USER_AGENT, GEOSUBMIT_ENDPOINT};
-use crate::scanner::{bluetooth, wifi};
+use crate::config::{APP_USER_AGENT, GEOSUBMIT_ENDPOINT, GEOSUBMIT_PROVIDER};
 use crate::error::{Error, Result};
-
-use super::payload::items;
-
-/// Assemble geolocation payload from current scans
-pub async fn assemble_geo_payload(
-    position: serde_json::Value,
-    cell_towers: Option<serde_json::Value>,
-) -> Result<items> {
-    let position: crate::geosubmit::payload::Position = serde_json::from_value(position)
-        .map_err(|e| Error::Serialization(e.to_string()))?;
-
-    let cell_towers: Option<Vec<crate::geosubmit::payload::CellTower>> = match cell_towers {
-        Some(ct_value) => Some(
-            serde_json::from_value(ct_value)
-                .map_err(|e| Error::Serialization(e.to_string()))?,
+use crate::scanner::{BleDevice, WifiBssid};
+use crate::server::events::{self, Event};
+use crate::server::{metrics, status};
+
+use super::payload::{BluetoothBeacon, CellTower, Geosubmit, Position, WifiAccessPoint, items};
+
+/// Assemble a geosubmit report from scanner records, dropping invalid ones
+pub fn assemble_geo_payload(
+    timestamp: u128,
+    position: Position,
+    access_points: &[WifiBssid],
+    beacons: &[BleDevice],
+    cells: Vec<CellTower>,
+) -> items {
+    let cells = valid_cells(cells);
+    items {
+        timestamp,
+        position,
+        wifiAccessPoints: valid_access_points(
+            access_points.iter().map(WifiAccessPoint::from).collect(),
         ),
-        None => None,
-    };
-
-    let wifi_start = Instant::now();
-    let ble_start = Instant::now();
-
-    let (wifi, ble) = tokio::join!(
-        // run simultaneously
-        wifi::fetch_wifi_stats(),
-        bluetooth::fetch_ble_devices()
-    );
-
-    let wifi_duration = wifi_start.elapsed();
-    let ble_duration = ble_start.elapsed();
-    tracing::debug!("WiFi scan duration: {:?}", wifi_duration);
-    tracing::debug!("BLE scan duration: {:?}", ble_duration);
+        bluetoothBeacons: beacons.iter().map(BluetoothBeacon::from).collect(),
+        cellTowers: Some(cells).filter(|cells| !cells.is_empty()),
+    }
+}
 
-    let unix_epoch = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_millis();
+/// Add cells seen by local modems that the phone did not report
+pub fn merge_cells(mut cells: Vec<CellTower>, local: Vec<CellTower>) -> Vec<CellTower> {
+    for cell in local {
+        let known = cells.iter().any(|c| {
+            c.radioType == cell.radioType
+                && c.mobileCountryCode == cell.mobileCountryCode
+                && c.mobileNetworkCode == cell.mobileNetworkCode
+                && c.locationAreaCode == cell.locationAreaCode
+                && c.cellId == cell.cellId
+        });
+        if !known {
+            cells.push(cell);
+        }
+    }
+    cells
+}
 
-    let payload = items {
-        timestamp: unix_epoch,
-        position,
-        wifiAccessPoints: wifi,
-        bluetoothBeacons: ble,
-        CellTowers: cell_towers,
-    };
+/// Drop cell towers whose fields are out of range for their radio type
+fn valid_cells(cells: Vec<CellTower>) -> Vec<CellTower> {
+    cells
+        .into_iter()
+        .filter(|cell| match cell.validate() {
+            Ok(()) => true,
+            Err(e) => {
+                tracing::warn!("Dropping cell tower: {}", e);
+                false
+            }
+        })
+        .collect()
+}
 
-    Ok(payload)
+fn valid_access_points(access_points: Vec<WifiAccessPoint>) -> Vec<WifiAccessPoint> {
+    access_points
+        .into_iter()
+        .filter(|ap| match ap.validate() {
+            Ok(()) => true,
+            Err(e) => {
+                tracing::warn!("Dropping access point: {}", e);
+                false
+            }
+        })
+        .collect()
 }
 
 /// Submit geolocation payload to the geosubmit API
 pub async fn submit_geo_payload(payload: items) -> Result<()> {
+    let result = post_geo_payload(payload).await;
+    status::record_submission(
+        GEOSUBMIT_PROVIDER,
+        result.as_ref().map(|_| ()).map_err(|e| e.to_string()),
+    );
+    events::publish(Event::Submission {
+        provider: GEOSUBMIT_PROVIDER.to_string(),
+        status: match &result {
+            Ok(code) => Some(*code),
+            Err(Error::HttpStatus { status, .. }) => Some(*status),
+            Err(_) => None,
+        },
+        error: result.as_ref().err().map(|e| e.to_string()),
+    });
+    result.map(|_| ())
+}
+
+/// POST the payload and return the HTTP status code
+async fn post_geo_payload(payload: items) -> Result<u16> {
     let retry_policy = ExponentialBackoff::builder().build_with_max_retries(5);
     let http_client = reqwest::Client::builder()
         .user_agent(APP_USER_AGENT)
@@ -70,20 +107,28 @@ pub async fn submit_geo_payload(payload: items) -> Result<()> {
     let client: ClientWithMiddleware = ClientBuilder::new(http_client.clone())
         .with(TracingMiddleware::default())
         .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .with(metrics::count_retries)
         .build();
 
     let req = http_client
         .post(GEOSUBMIT_ENDPOINT)
-        .json(&payload)
+        .json(&Geosubmit {
+            items: vec![payload],
+        })
         .build()
         .map_err(|e| Error::Transport(e.to_string()))?;
 
-    let res = client
-        .execute(req)
-        .await
-        .map_err(|e| Error::Transport(e.to_string()))?;
+    let res = client.execute(req).await.map_err(|e| {
+        metrics::SUBMISSIONS
+            .with_label_values(&[GEOSUBMIT_PROVIDER, "error"])
+            .inc();
+        Error::Transport(e.to_string())
+    })?;
 
     let status = res.status();
+    metrics::SUBMISSIONS
+        .with_label_values(&[GEOSUBMIT_PROVIDER, status.as_str()])
+        .inc();
     let body = res.text().await.unwrap_or_default();
 
     if !status.is_success() {
@@ -96,5 +141,5 @@ pub async fn submit_geo_payload(payload: items) -> Result<()> {
     tracing::info!("Geosubmit response status: {}", status);
     tracing::info!("Geosubmit response body: {}", body);
 
-    Ok(())
+    Ok(status.as_u16())
 }
@@ -1,62 +1,360 @@
 //! Geosubmit API payload types
+//!
+//! These mirror the Ichnaea geosubmit v2 schema field by field. Scanner
+//! records carry more detail than the schema allows, so they are converted
+//! into these types right before submission.
 
-use serde::{Deserialize, Serialize};
+use btleplug::api::BDAddr as mac_address;
+use serde::{Deserialize, Serialize, Serializer};
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+
+use crate::scanner::band::{self, Band};
+use crate::scanner::wifi::PhyType;
+use crate::scanner::{BleDevice, WifiBssid};
+
+/// Request body of `POST /v2/geosubmit`
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct Geosubmit {
+    pub items: Vec<items>,
+}
 
 #[derive(Serialize, Debug, Deserialize, Clone)]
 #[allow(nonstandard_style)]
 pub struct items {
-    pub timestamp: u128,
     /// Timestamp in milliseconds since Unix epoch
+    pub timestamp: u128,
     pub position: Position,
-    pub bluetoothBeacons: Vec<crate::scanner::BleDevice>,
-    pub wifiAccessPoints: Vec<crate::scanner::WifiBssid>,
-    pub CellTowers: Option<Vec<CellTower>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bluetoothBeacons: Vec<BluetoothBeacon>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub wifiAccessPoints: Vec<WifiAccessPoint>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cellTowers: Option<Vec<CellTower>>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 #[allow(non_snake_case)]
 pub struct Position {
     pub latitude: f64,
     pub longitude: f64,
-    pub accuracy: f64,
-    pub altitude: f64,
-    pub altitudeAccuracy: f64,
-    pub heading: f64,
-    pub speed: f64,
-    pub source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accuracy: Option<f64>, // in meters
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub age: Option<u64>, // ms between the position fix and the observations
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub altitude: Option<f64>, // in meters above sea level
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub altitudeAccuracy: Option<f64>, // in meters
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heading: Option<f64>, // degrees clockwise from true north
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pressure: Option<f64>, // in hPa
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed: Option<f64>, // in meters per second
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<PositionSource>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PositionSource {
+    Gps,
+    Manual,
+    Fused,
 }
 
 #[derive(Serialize, Debug, Deserialize, Clone)]
 #[allow(non_snake_case)]
-pub struct CellTower {
-    pub radioType: Option<RadioType>, // "gsm", "wcdma", or "lte"
-    pub mobileCountryCode: u16,       // MCC
-    pub mobileNetworkCode: u16,       // MNC
-    pub locationAreaCode: u32,        // LAC (GSM/WCDMA) or TAC (LTE)
-    pub cellId: u32,                  // Cell Identity
-    pub age: Option<u32>,             // ms since last seen
-    pub asu: Option<u8>,              // Arbitrary Strength Unit
+pub struct WifiAccessPoint {
+    #[serde(serialize_with = "lowercase_mac")]
+    pub macAddress: mac_address,
+    #[serde(alias = "wifiVersion", skip_serializing_if = "Option::is_none")]
+    pub radioType: Option<WifiRadioType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub age: Option<u64>, // in milliseconds since last seen
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency: Option<u32>, // in MHz
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signalStrength: Option<i32>, // in dBm
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signalToNoiseRatio: Option<i32>, // in dB
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssid: Option<String>,
+}
+
+/// Wi-Fi standards accepted by the schema
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum WifiRadioType {
+    #[serde(rename = "802.11a")]
+    A,
+    #[serde(rename = "802.11b")]
+    B,
+    #[serde(rename = "802.11g")]
+    G,
+    #[serde(rename = "802.11n")]
+    N,
+    #[serde(rename = "802.11ac")]
+    Ac,
+    #[serde(rename = "802.11ax")]
+    Ax,
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone)]
+#[allow(non_snake_case)]
+pub struct BluetoothBeacon {
+    #[serde(serialize_with = "lowercase_mac")]
+    pub macAddress: mac_address,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub age: Option<u64>, // in milliseconds since last seen
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signalStrength: Option<i16>, // in dBm
 }
 
 #[derive(Serialize, Debug, Deserialize, Clone)]
+#[allow(non_snake_case)]
+pub struct CellTower {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub radioType: Option<RadioType>,
+    pub mobileCountryCode: u16, // MCC
+    pub mobileNetworkCode: u16, // MNC, or SID for CDMA
+    pub locationAreaCode: u32,  // LAC (GSM/WCDMA), TAC (LTE/NR) or NID (CDMA)
+    pub cellId: u64,            // CI, 28-bit UCI/ECI, 36-bit NCI or BID (CDMA)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub age: Option<u32>, // ms since last seen
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asu: Option<u8>, // Arbitrary Strength Unit
+    #[serde(
+        rename = "primaryScramblingCode",
+        alias = "psc",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub psc: Option<u16>, // PSC (WCDMA), PCI (LTE/NR) or BSIC (GSM)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub serving: Option<u8>, // 1 if this is the serving cell, 0 for neighbours
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signalStrength: Option<i32>, // in dBm
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timingAdvance: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arfcn: Option<u32>, // ARFCN, UARFCN, EARFCN or NR-ARFCN depending on radio
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
 #[allow(non_camel_case_types)]
 pub enum RadioType {
     #[serde(rename = "gsm")]
     Gsm,
+    #[serde(rename = "cdma")]
+    Cdma,
     #[serde(rename = "wcdma")]
     Wcdma,
     #[serde(rename = "lte")]
     Lte,
+    #[serde(rename = "nr")]
+    Nr,
+}
+
+/// Valid ranges for one radio type
+struct CellLimits {
+    area_code: (u32, u32),
+    cell_id_bits: u32,
+    psc_max: u16,
+    arfcn_max: u32,
+    timing_advance_max: u32,
+}
+
+impl RadioType {
+    fn limits(self) -> CellLimits {
+        match self {
+            RadioType::Gsm => CellLimits {
+                area_code: (1, 65533),
+                cell_id_bits: 16,
+                psc_max: 63,
+                arfcn_max: 1023,
+                timing_advance_max: 63,
+            },
+            RadioType::Cdma => CellLimits {
+                area_code: (0, 65535),
+                cell_id_bits: 16,
+                psc_max: 511, // PN offset
+                arfcn_max: 2047,
+                timing_advance_max: 0,
+            },
+            RadioType::Wcdma => CellLimits {
+                area_code: (1, 65533),
+                cell_id_bits: 28,
+                psc_max: 511,
+                arfcn_max: 16383,
+                timing_advance_max: 0,
+            },
+            RadioType::Lte => CellLimits {
+                area_code: (1, 65533),
+                cell_id_bits: 28,
+                psc_max: 503,
+                arfcn_max: 262143,
+                timing_advance_max: 1282,
+            },
+            RadioType::Nr => CellLimits {
+                area_code: (0, 16_777_215), // 24-bit TAC
+                cell_id_bits: 36,
+                psc_max: 1007,
+                arfcn_max: 3_279_165,
+                timing_advance_max: 3846,
+            },
+        }
+    }
+}
+
+impl FromStr for RadioType {
+    type Err = Error;
+
+    fn from_str(radio: &str) -> Result<Self> {
+        match radio.to_lowercase().as_str() {
+            "gsm" => Ok(RadioType::Gsm),
+            "cdma" => Ok(RadioType::Cdma),
+            "wcdma" | "umts" => Ok(RadioType::Wcdma),
+            "lte" => Ok(RadioType::Lte),
+            "nr" | "5gnr" => Ok(RadioType::Nr),
+            _ => Err(Error::InvalidCell(format!(
+                "unknown radio type '{}'",
+                radio
+            ))),
+        }
+    }
 }
 
 impl CellTower {
     /// Set radio type from string
-    pub fn set_radio_type(&mut self, radio: &str) {
-        self.radioType = match radio.to_lowercase().as_str() {
-            "gsm" => Some(RadioType::Gsm),
-            "wcdma" => Some(RadioType::Wcdma),
-            "lte" => Some(RadioType::Lte),
-            _ => None,
+    pub fn set_radio_type(&mut self, radio: &str) -> Result<()> {
+        self.radioType = Some(radio.parse()?);
+        Ok(())
+    }
+
+    /// Check every field against the ranges allowed for this radio type
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |what: String| Err(Error::InvalidCell(what));
+
+        if !(1..=999).contains(&self.mobileCountryCode) {
+            return invalid(format!("MCC {} out of range", self.mobileCountryCode));
+        }
+        let mnc_max = match self.radioType {
+            Some(RadioType::Cdma) => 32767, // SID
+            _ => 999,
+        };
+        if self.mobileNetworkCode > mnc_max {
+            return invalid(format!("MNC {} out of range", self.mobileNetworkCode));
+        }
+
+        let Some(radio) = self.radioType else {
+            return Ok(()); // without a radio type only MCC/MNC can be checked
+        };
+        let limits = radio.limits();
+
+        let (lac_min, lac_max) = limits.area_code;
+        if !(lac_min..=lac_max).contains(&self.locationAreaCode) {
+            return invalid(format!(
+                "{:?} area code {} out of range",
+                radio, self.locationAreaCode
+            ));
+        }
+        if self.cellId >> limits.cell_id_bits != 0 {
+            return invalid(format!(
+                "{:?} cell ID {} wider than {} bits",
+                radio, self.cellId, limits.cell_id_bits
+            ));
+        }
+        if self.psc.is_some_and(|psc| psc > limits.psc_max) {
+            return invalid(format!("{:?} PSC/PCI {:?} out of range", radio, self.psc));
         }
+        if self.arfcn.is_some_and(|arfcn| arfcn > limits.arfcn_max) {
+            return invalid(format!("{:?} ARFCN {:?} out of range", radio, self.arfcn));
+        }
+        if self
+            .timingAdvance
+            .is_some_and(|ta| ta > limits.timing_advance_max)
+        {
+            return invalid(format!(
+                "{:?} timing advance {:?} out of range",
+                radio, self.timingAdvance
+            ));
+        }
+        if self.serving.is_some_and(|serving| serving > 1) {
+            return invalid(format!("serving flag {:?} is not 0 or 1", self.serving));
+        }
+        if self
+            .signalStrength
+            .is_some_and(|dbm| !(-150..=-25).contains(&dbm))
+        {
+            return invalid(format!(
+                "signal strength {:?} dBm out of range",
+                self.signalStrength
+            ));
+        }
+
+        Ok(())
     }
 }
+
+impl WifiAccessPoint {
+    /// Check that the channel, if any, is the one the frequency is on
+    pub fn validate(&self) -> Result<()> {
+        match (self.frequency, self.channel) {
+            (Some(frequency), Some(channel)) => band::check(frequency, channel).map(|_| ()),
+            (Some(frequency), None) if Band::of(frequency).is_none() => Err(Error::InvalidChannel(
+                format!("{} MHz is in no Wi-Fi band", frequency),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl WifiRadioType {
+    /// Closest schema value for a detected PHY; newer standards report as 802.11ax
+    pub fn from_phy(phy: &PhyType) -> Option<Self> {
+        match phy {
+            PhyType::Uhr | PhyType::Eht | PhyType::He => Some(WifiRadioType::Ax),
+            PhyType::Vht => Some(WifiRadioType::Ac),
+            PhyType::Ht => Some(WifiRadioType::N),
+            PhyType::Legacy => None, // can't tell 802.11a/b/g apart
+        }
+    }
+}
+
+impl From<&WifiBssid> for WifiAccessPoint {
+    fn from(ap: &WifiBssid) -> Self {
+        WifiAccessPoint {
+            macAddress: ap.bssid,
+            radioType: WifiRadioType::from_phy(&ap.phy),
+            age: ap.age,
+            channel: ap.channel,
+            frequency: Some(ap.frequency).filter(|&f| f > 0),
+            signalStrength: Some(ap.rssi),
+            signalToNoiseRatio: None,
+            ssid: ap.ssid.clone(),
+        }
+    }
+}
+
+impl From<&BleDevice> for BluetoothBeacon {
+    fn from(device: &BleDevice) -> Self {
+        BluetoothBeacon {
+            macAddress: device.mac_address,
+            age: device.age,
+            name: device.name.clone(),
+            signalStrength: device.rssi,
+        }
+    }
+}
+
+fn lowercase_mac<S: Serializer>(
+    mac: &mac_address,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("{:x}", mac))
+}
@@ -1,11 +1,23 @@
 pub mod config;
 pub mod error;
+pub mod time;
 
 pub mod scanner {
+    pub mod address;
+    pub mod band;
+    pub mod beacon;
     pub mod bluetooth;
+    pub mod cache;
+    pub mod cell;
+    pub mod ie;
+    pub mod oui;
+    pub mod profile;
     pub mod wifi;
+    pub mod window;
 
+    pub use self::band::Band;
     pub use self::bluetooth::BleDevice;
+    pub use self::profile::ScanProfile;
     pub use self::wifi::WifiBssid;
 }
 
@@ -14,7 +26,40 @@ pub mod geosubmit {
     pub mod payload;
 
     pub use self::client::{assemble_geo_payload, submit_geo_payload};
-    pub use self::payload::{CellTower, Position, RadioType, items};
+    pub use self::payload::{
+        BluetoothBeacon, CellTower, Geosubmit, Position, PositionSource, RadioType,
+        WifiAccessPoint, WifiRadioType, items,
+    };
+}
+
+pub mod position {
+    pub mod filter;
+    pub mod fix;
+    pub mod geo;
+    pub mod gpsd;
+    pub mod gpx;
+    pub mod nmea;
+    pub mod trajectory;
+
+    pub use self::fix::{Fix, FixMode, FixReceiver};
+}
+
+pub mod collect {
+    pub mod archive;
+    pub mod import;
+    pub mod mobility;
+    pub mod policy;
+    pub mod privacy;
+    pub mod queue;
+    pub mod report;
+    pub mod runner;
+
+    pub use self::archive::Archive;
+    pub use self::mobility::MobileStore;
+    pub use self::policy::Policy;
+    pub use self::privacy::ZoneStore;
+    pub use self::queue::Queue;
+    pub use self::report::Report;
 }
 
 pub mod peripheral {
@@ -24,16 +69,24 @@ pub mod peripheral {
 }
 
 pub mod server {
+    pub mod events;
     pub mod handlers;
     pub mod mdns_service;
+    pub mod metrics;
+    pub mod rate_limit;
+    pub mod status;
+    pub mod stream;
 
-    use axum::routing::{get, post};
+    use axum::extract::ConnectInfo;
+    use axum::routing::{delete, get, post, put};
     use axum::{Router, body::Body, http::Request};
+    use hyper::body::Incoming;
     use hyper_util::rt::tokio::TokioIo;
     use rustls::ServerConfig;
     use std::{net::SocketAddr, sync::Arc};
     use tokio::net::TcpListener;
     use tokio_rustls::TlsAcceptor;
+    use tower::ServiceExt;
     use tower_http::trace::TraceLayer;
     use tracing::Span;
 
@@ -45,6 +98,23 @@ pub mod server {
             .route("/submit", post(handlers::process_submit_http))
             .route("/status", get(handlers::handle_status))
             .route("/request", get(handlers::handle_request))
+            .route("/metrics", get(handlers::handle_metrics))
+            .route("/events", get(handlers::handle_events))
+            .route("/location", post(stream::handle_location_stream))
+            .route(
+                "/zones",
+                get(handlers::handle_list_zones).post(handlers::handle_add_zone),
+            )
+            .route("/zones/{id}", delete(handlers::handle_remove_zone))
+            .route(
+                "/privacy/devices",
+                get(handlers::handle_get_device_rules).put(handlers::handle_set_device_rules),
+            )
+            .route("/mobile", get(handlers::handle_list_mobile))
+            .route(
+                "/mobile/{kind}/{address}",
+                put(handlers::handle_classify_mobile).delete(handlers::handle_forget_mobile),
+            )
             .layer(
                 TraceLayer::new_for_http()
                     .make_span_with(|request: &Request<Body>| {
@@ -55,8 +125,8 @@ pub mod server {
                             .unwrap_or("<unknown>");
                         let remote_addr = request
                             .extensions()
-                            .get::<SocketAddr>()
-                            .map(|sa| sa.ip().to_string())
+                            .get::<ConnectInfo<SocketAddr>>()
+                            .map(|ConnectInfo(sa)| sa.ip().to_string())
                             .unwrap_or_else(|| "<unknown>".into());
 
                         tracing::info_span!(
@@ -89,13 +159,18 @@ pub mod server {
 
         let router = create_router();
         loop {
-            let (stream, _) = listener
+            let (stream, peer) = listener
                 .accept()
                 .await
                 .map_err(|e| crate::error::Error::Bind(e.to_string()))?;
 
             let acceptor = acceptor.clone();
-            let router = router.clone();
+            let router = router
+                .clone()
+                .map_request(move |mut request: Request<Incoming>| {
+                    request.extensions_mut().insert(ConnectInfo(peer));
+                    request
+                });
 
             tokio::spawn(async move {
                 match acceptor.accept(stream).await {
@@ -110,7 +185,10 @@ pub mod server {
                             eprintln!("Connection error: {}", e);
                         }
                     }
-                    Err(e) => eprintln!("TLS handshake error: {}", e),
+                    Err(e) => {
+                        metrics::TLS_HANDSHAKE_ERRORS.inc();
+                        eprintln!("TLS handshake error: {}", e)
+                    }
                 }
             });
         }
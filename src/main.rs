@@ -4,14 +4,58 @@
 //! location data to the Ichnaea geolocation service.
 
 use local_ip_address::local_ip;
+use service_berry::collect::import::{self, ImportOptions};
+use service_berry::collect::runner::{self, CollectOptions};
+use service_berry::collect::{Archive, MobileStore};
+use service_berry::scanner::{bluetooth, oui};
 use service_berry::{config, peripheral, server};
 use users::get_current_username;
 
+const OUI_USAGE: &str = "\
+Usage: serviceberry update-oui <FILE>
+
+Installs an IEEE MA-L registry (oui.csv from standards-oui.ieee.org)
+used to name the vendors of access points and beacons.";
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Initialize logging
     tracing_subscriber::fmt::init();
 
+    // `serviceberry collect` scans and submits on its own, without a phone,
+    // `serviceberry import-gpx` positions what it collected blind,
+    // `serviceberry update-oui` installs a newer vendor registry
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        Some("collect") => {
+            let options = CollectOptions::from_args(args)?;
+            runner::run(options).await?;
+            return Ok(());
+        }
+        Some("import-gpx") => {
+            let options = ImportOptions::from_args(args)?;
+            import::run(options).await?;
+            return Ok(());
+        }
+        Some("update-oui") => {
+            let path = args.next().ok_or(OUI_USAGE)?;
+            let count = oui::update_from_file(&path)?;
+            println!("Installed {} vendors from {}", count, path);
+            return Ok(());
+        }
+        Some("help" | "--help" | "-h") => {
+            println!(
+                "Usage: serviceberry [collect|import-gpx|update-oui]\n\n{}\n\n{}\n\n{}",
+                runner::USAGE,
+                import::USAGE,
+                OUI_USAGE
+            );
+            return Ok(());
+        }
+        Some(other) => return Err(format!("unknown command {}", other).into()),
+        None => {}
+    }
+
     // get system info
     let instance_name = hostname::get() // computer name
         .unwrap_or_else(|_| config::DEFAULT_HOSTNAME.into())
@@ -29,6 +73,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Generate TLS certificates
     let config_directory = config::config_dir();
     let identity = config::load_identity(instance_name.clone(), config_directory)?;
+    server::status::init(&identity);
 
     // Register mDNS service
     let _mdns = server::mdns_service::register_mdns_service(
@@ -42,6 +87,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<server::handlers::PartialPayload>();
 
+    // Flag mobile devices in what was collected before
+    tokio::task::spawn_blocking(|| {
+        if let Err(e) = MobileStore::local().learn(&Archive::local()) {
+            tracing::warn!("Failed to learn mobile devices: {}", e);
+        }
+    });
+
+    // Listen for BLE advertisements, so scans can answer at once
+    bluetooth::spawn();
+
     // Start the BLE peripheral
     tokio::spawn(async move {
         peripheral::ble_peripheral(tx).await;
@@ -50,6 +105,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Start the Worker
     tokio::spawn(async move {
         while let Some(payload) = rx.recv().await {
+            server::status::queue_pop();
             tracing::info!("Worker received payload from BLE: {:?}", payload);
             // This is where you call your submission logic
             if let Err(e) = server::handlers::process_submit(payload).await {
@@ -1,4 +1,6 @@
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::{Mutex, mpsc};
@@ -18,37 +20,81 @@ use ble_peripheral_rust::{
     uuid::ShortUuid,
 };
 
+use crate::config::STREAM_IDLE_MS;
+use crate::server::events::{self, Event, PairingState};
 use crate::server::handlers::PartialPayload;
+use crate::server::stream::{self, LineBuffer, StreamSummary};
+use crate::server::{metrics, rate_limit, status};
+
+/// Add one write to `buffer` and parse the complete lines in it, each exactly
+/// once. Only the bytes after the last line break are kept for the next write.
+pub fn take_payloads(buffer: &mut Vec<u8>, value: &[u8]) -> Vec<PartialPayload> {
+    buffer.extend_from_slice(value);
+    let mut payloads = Vec::new();
+    if let Some(end) = buffer.iter().rposition(|&b| b == b'\n') {
+        let complete: Vec<u8> = buffer.drain(..=end).collect();
+        for line in complete.split(|&b| b == b'\n') {
+            let line = line.trim_ascii();
+            if line.is_empty() {
+                continue;
+            }
+            match serde_json::from_slice::<PartialPayload>(line) {
+                Ok(payload) => payloads.push(payload),
+                Err(_) => metrics::BLE_PARSE_FAILURES.inc(),
+            }
+        }
+    }
+    // older apps write a single payload without a line break
+    if let Ok(payload) = serde_json::from_slice::<PartialPayload>(buffer) {
+        payloads.push(payload);
+        buffer.clear();
+    }
+    payloads
+}
 
 pub async fn ble_peripheral(payload_tx: UnboundedSender<PartialPayload>) {
     let service_uuid =
         Uuid::parse_str("12345678-1234-5678-1234-56789abcdef0").expect("invalid service UUID");
     let char_uuid =
         Uuid::parse_str("abcdef01-1234-5678-1234-56789abcdef0").expect("invalid char UUID");
+    // newline-delimited positions, the BLE equivalent of `POST /location`
+    let location_uuid =
+        Uuid::parse_str("abcdef02-1234-5678-1234-56789abcdef0").expect("invalid char UUID");
 
     let char_value = Arc::new(Mutex::new(b"Hello iOS".to_vec()));
 
     let service = Service {
         uuid: service_uuid,
         primary: true,
-        characteristics: vec![Characteristic {
-            uuid: char_uuid,
-            properties: vec![
-                CharacteristicProperty::Read,
-                CharacteristicProperty::Write,
-                CharacteristicProperty::Notify,
-            ],
-            permissions: vec![
-                AttributePermission::Readable,
-                AttributePermission::Writeable,
-            ],
-            value: Some(char_value.lock().await.clone()),
-            descriptors: vec![Descriptor {
-                uuid: Uuid::from_short(0x2A13_u16),
-                value: Some(vec![0, 1]),
+        characteristics: vec![
+            Characteristic {
+                uuid: char_uuid,
+                properties: vec![
+                    CharacteristicProperty::Read,
+                    CharacteristicProperty::Write,
+                    CharacteristicProperty::Notify,
+                ],
+                permissions: vec![
+                    AttributePermission::Readable,
+                    AttributePermission::Writeable,
+                ],
+                value: Some(char_value.lock().await.clone()),
+                descriptors: vec![Descriptor {
+                    uuid: Uuid::from_short(0x2A13_u16),
+                    value: Some(vec![0, 1]),
+                    ..Default::default()
+                }],
+            },
+            Characteristic {
+                uuid: location_uuid,
+                properties: vec![
+                    CharacteristicProperty::Write,
+                    CharacteristicProperty::WriteWithoutResponse,
+                ],
+                permissions: vec![AttributePermission::Writeable],
                 ..Default::default()
-            }],
-        }],
+            },
+        ],
     };
 
     let (event_tx, mut event_rx) = mpsc::channel::<PeripheralEvent>(256);
@@ -61,12 +107,15 @@ pub async fn ble_peripheral(payload_tx: UnboundedSender<PartialPayload>) {
         .expect("failed to add service");
 
     let mut write_buffer = Vec::new();
+    // per client, with the time of its last write; there is no disconnect event
+    let mut location_buffers: HashMap<String, (LineBuffer, Instant)> = HashMap::new();
     let char_value_loop = Arc::clone(&char_value);
 
     info!("Advertising as Serviceberry...");
-    let _ = peripheral
+    let advertising = peripheral
         .start_advertising("Serviceberry", &[service_uuid])
         .await;
+    status::record_advertising(advertising.is_ok());
 
     while let Some(event) = event_rx.recv().await {
         match event {
@@ -81,48 +130,94 @@ pub async fn ble_peripheral(payload_tx: UnboundedSender<PartialPayload>) {
             }
 
             PeripheralEvent::WriteRequest {
-                value, responder, ..
+                request,
+                value,
+                responder,
+                ..
             } => {
+                metrics::BLE_WRITE_FRAMES.inc();
                 let _ = responder.send(WriteRequestResponse {
                     response: RequestResponse::Success,
                 });
 
+                if request.characteristic == location_uuid {
+                    let idle = Duration::from_millis(STREAM_IDLE_MS);
+                    location_buffers.retain(|_, (_, written)| written.elapsed() < idle);
+                    let (buffer, written) = location_buffers
+                        .entry(request.client.clone())
+                        .or_insert_with(|| (LineBuffer::default(), Instant::now()));
+                    *written = Instant::now();
+                    let lines = buffer.push(&value);
+                    let client = format!("ble:{}", request.client);
+                    stream::receive("ble", &client, lines, &mut StreamSummary::default());
+                    continue;
+                }
+
                 {
                     let mut data = char_value_loop.lock().await;
                     *data = value.clone();
                 }
 
-                write_buffer.extend_from_slice(&value);
-
-                if let Ok(text) = String::from_utf8(write_buffer.clone()) {
-                    let mut success = false;
+                let payloads = take_payloads(&mut write_buffer, &value);
+                if !payloads.is_empty() {
+                    events::publish(Event::Pairing {
+                        client: request.client.clone(),
+                        state: PairingState::PayloadReceived,
+                    });
+                }
 
-                    if text.contains('\n') {
-                        for line in text.lines() {
-                            if let Ok(payload) = serde_json::from_str::<PartialPayload>(line) {
-                                let _ = payload_tx.send(payload);
-                                success = true;
-                            }
-                        }
-                    } else if let Ok(payload) = serde_json::from_str::<PartialPayload>(&text) {
-                        let _ = payload_tx.send(payload);
-                        success = true;
+                for mut payload in payloads {
+                    let client = format!("ble:{}", request.client);
+                    if let Err(e) = rate_limit::SUBMISSIONS.check(&client) {
+                        // leave the reason in the characteristic for the phone to read
+                        info!("Dropping BLE submission from {}: {}", request.client, e);
+                        *char_value_loop.lock().await =
+                            serde_json::json!({ "error": e.to_string() })
+                                .to_string()
+                                .into_bytes();
+                        continue;
                     }
 
-                    if success {
-                        write_buffer.clear();
+                    payload.client = client;
+                    status::queue_push();
+                    if payload_tx.send(payload).is_err() {
+                        status::queue_pop();
                     }
                 }
 
                 if write_buffer.len() > 2048 {
+                    metrics::BLE_PARSE_FAILURES.inc();
                     write_buffer.clear();
                 }
             }
 
             _ => {
-                let _ = peripheral
+                match &event {
+                    PeripheralEvent::StateUpdate { is_powered } => {
+                        status::record_powered(*is_powered)
+                    }
+                    PeripheralEvent::CharacteristicSubscriptionUpdate {
+                        request,
+                        subscribed,
+                    } => {
+                        if !*subscribed {
+                            location_buffers.remove(&request.client);
+                        }
+                        events::publish(Event::Pairing {
+                            client: request.client.clone(),
+                            state: if *subscribed {
+                                PairingState::Subscribed
+                            } else {
+                                PairingState::Unsubscribed
+                            },
+                        })
+                    }
+                    _ => {}
+                }
+                let advertising = peripheral
                     .start_advertising("Serviceberry", &[service_uuid])
                     .await;
+                status::record_advertising(advertising.is_ok());
             }
         }
     }
@@ -0,0 +1,211 @@
+//! Quality checks on phone positions before they are submitted
+//!
+//! Indoors, phones fall back to coarse network positions and sometimes jump
+//! hundreds of meters between fixes. Each client gets its own filter that
+//! rejects fixes too coarse to be useful and moves faster than anything the
+//! phone could be riding in, and can smooth what is left with a Kalman filter.
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::geo::distance_meters;
+use crate::config::{
+    POSITION_FILTER_RESET_MS, POSITION_KALMAN_NOISE, POSITION_MAX_ACCURACY_METERS,
+    POSITION_MAX_JUMPS, POSITION_MAX_SPEED, POSITION_SMOOTHING,
+};
+use crate::geosubmit::Position;
+
+// each client's filter, and when the server last ran it
+static FILTERS: Lazy<Mutex<HashMap<String, (PositionFilter, Instant)>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Why a position was rejected, returned to the phone
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(
+    tag = "reason",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum Rejection {
+    Inaccurate {
+        accuracy: f64, // in meters
+        limit: f64,
+    },
+    Jump {
+        meters: f64, // from the last accepted position
+        seconds: f64,
+        speed: f64, // implied, in m/s
+    },
+    OutOfRange,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::Inaccurate { accuracy, limit } => {
+                write!(f, "accuracy of {:.0} m exceeds {:.0} m", accuracy, limit)
+            }
+            Rejection::Jump {
+                meters,
+                seconds,
+                speed,
+            } => write!(
+                f,
+                "jumped {:.0} m in {:.1} s ({:.0} m/s)",
+                meters, seconds, speed
+            ),
+            Rejection::OutOfRange => write!(f, "coordinates out of range"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FilterConfig {
+    pub max_accuracy: f64, // in meters
+    pub max_speed: f64,    // in m/s
+    pub smoothing: bool,
+}
+
+impl Default for FilterConfig {
+    fn default() -> Self {
+        FilterConfig {
+            max_accuracy: POSITION_MAX_ACCURACY_METERS,
+            max_speed: POSITION_MAX_SPEED,
+            smoothing: POSITION_SMOOTHING,
+        }
+    }
+}
+
+/// Kalman filter with a constant position model, variance in square meters
+#[derive(Debug, Clone)]
+struct Kalman {
+    latitude: f64,
+    longitude: f64,
+    variance: f64,
+    timestamp: u128,
+}
+
+impl Kalman {
+    fn new(position: &Position, accuracy: f64, timestamp: u128) -> Self {
+        Kalman {
+            latitude: position.latitude,
+            longitude: position.longitude,
+            variance: accuracy * accuracy,
+            timestamp,
+        }
+    }
+
+    fn update(&mut self, position: &Position, accuracy: f64, timestamp: u128) {
+        // the phone may have moved since the last fix
+        let seconds = timestamp.saturating_sub(self.timestamp) as f64 / 1000.0;
+        self.variance += seconds * POSITION_KALMAN_NOISE * POSITION_KALMAN_NOISE;
+        self.timestamp = self.timestamp.max(timestamp);
+
+        let gain = self.variance / (self.variance + accuracy * accuracy);
+        self.latitude += gain * (position.latitude - self.latitude);
+        self.longitude += gain * (position.longitude - self.longitude);
+        self.variance *= 1.0 - gain;
+    }
+}
+
+/// Filter state for one client
+#[derive(Debug, Clone, Default)]
+pub struct PositionFilter {
+    config: FilterConfig,
+    last: Option<(u128, Position)>, // last accepted raw position
+    jumps: u32,                     // consecutive jump rejections
+    kalman: Option<Kalman>,
+}
+
+impl PositionFilter {
+    pub fn new(config: FilterConfig) -> Self {
+        PositionFilter {
+            config,
+            ..Default::default()
+        }
+    }
+
+    /// Check `position`, measured at `timestamp`, against the ones before it.
+    /// Returns the position to use, smoothed if enabled.
+    pub fn process(&mut self, timestamp: u128, position: Position) -> Result<Position, Rejection> {
+        if !(-90.0..=90.0).contains(&position.latitude)
+            || !(-180.0..=180.0).contains(&position.longitude)
+        {
+            return Err(Rejection::OutOfRange);
+        }
+        if let Some(accuracy) = position.accuracy
+            && accuracy > self.config.max_accuracy
+        {
+            return Err(Rejection::Inaccurate {
+                accuracy,
+                limit: self.config.max_accuracy,
+            });
+        }
+
+        if let Some((last_timestamp, last)) = &self.last
+            && timestamp.abs_diff(*last_timestamp) < POSITION_FILTER_RESET_MS as u128
+        {
+            let seconds = timestamp.abs_diff(*last_timestamp) as f64 / 1000.0;
+            let meters = distance_meters(
+                last.latitude,
+                last.longitude,
+                position.latitude,
+                position.longitude,
+            );
+            // both fixes may be off by their accuracy
+            let slack = last.accuracy.unwrap_or(0.0) + position.accuracy.unwrap_or(0.0);
+            if meters > seconds * self.config.max_speed + slack {
+                self.jumps += 1;
+                if self.jumps < POSITION_MAX_JUMPS {
+                    return Err(Rejection::Jump {
+                        meters,
+                        seconds,
+                        speed: meters / seconds.max(0.001),
+                    });
+                }
+                // the phone keeps insisting, the earlier fix was the bad one
+                tracing::info!("Accepting position after {} jumps", self.jumps);
+                self.kalman = None;
+            }
+        } else {
+            self.kalman = None;
+        }
+        self.jumps = 0;
+        self.last = Some((timestamp, position.clone()));
+
+        if !self.config.smoothing {
+            return Ok(position);
+        }
+        let accuracy = position.accuracy.unwrap_or(self.config.max_accuracy);
+        let fresh = self.kalman.is_none();
+        let kalman = self
+            .kalman
+            .get_or_insert_with(|| Kalman::new(&position, accuracy, timestamp));
+        if !fresh {
+            kalman.update(&position, accuracy, timestamp);
+        }
+        Ok(Position {
+            latitude: kalman.latitude,
+            longitude: kalman.longitude,
+            accuracy: Some(kalman.variance.sqrt()),
+            ..position
+        })
+    }
+}
+
+/// Run `position` through `client`'s filter
+pub fn process(client: &str, timestamp: u128, position: Position) -> Result<Position, Rejection> {
+    let mut filters = FILTERS.lock().unwrap_or_else(|e| e.into_inner());
+    // forget clients that went quiet, by our clock since theirs may be wrong
+    let reset = Duration::from_millis(POSITION_FILTER_RESET_MS);
+    filters.retain(|_, (_, used)| used.elapsed() < reset);
+    let (filter, used) = filters
+        .entry(client.to_string())
+        .or_insert_with(|| (PositionFilter::default(), Instant::now()));
+    *used = Instant::now();
+    filter.process(timestamp, position)
+}
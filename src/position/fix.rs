@@ -0,0 +1,52 @@
+//! Positions from local GNSS receivers, shared by all position providers
+
+use tokio::sync::watch;
+
+use crate::geosubmit::{Position, PositionSource};
+
+/// Rough user equivalent range error of a consumer receiver, used to turn DOP into meters
+pub const UERE_METERS: f64 = 5.0;
+
+/// Latest fix of a provider, `None` while the receiver has no fix
+pub type FixReceiver = watch::Receiver<Option<Fix>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FixMode {
+    NoFix,
+    TwoD,
+    ThreeD,
+}
+
+/// A position reported by a local GNSS receiver
+#[derive(Debug, Clone)]
+pub struct Fix {
+    pub position: Position, // `age` is left unset, see `position_at`
+    pub timestamp: u128,    // time of the fix, in milliseconds since Unix epoch
+    pub mode: FixMode,
+    pub satellites: Option<u32>, // satellites used in the solution
+}
+
+impl Fix {
+    /// Position with `age` set relative to observations made at `at` (ms since Unix epoch)
+    pub fn position_at(&self, at: u128) -> Position {
+        Position {
+            age: Some(at.abs_diff(self.timestamp) as u64),
+            ..self.position.clone()
+        }
+    }
+}
+
+/// Accuracy in meters estimated from a dilution of precision (HDOP or VDOP)
+pub fn accuracy_from_dop(dop: f64) -> f64 {
+    dop * UERE_METERS
+}
+
+/// Empty position tagged as coming from a GNSS receiver
+pub(crate) fn gps_position(latitude: f64, longitude: f64) -> Position {
+    Position {
+        latitude,
+        longitude,
+        source: Some(PositionSource::Gps),
+        ..Default::default()
+    }
+}
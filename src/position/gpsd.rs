@@ -0,0 +1,182 @@
+//! Position provider backed by gpsd's JSON protocol
+//!
+//! Watches TPV (time-position-velocity) reports for the fix itself and SKY
+//! reports for the satellites used, falling back to HDOP when a TPV carries
+//! no error estimates.
+
+use chrono::DateTime;
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::watch;
+
+use super::fix::{Fix, FixMode, FixReceiver, accuracy_from_dop, gps_position};
+use crate::error::{Error, Result};
+use crate::time::unix_millis;
+
+const WATCH_COMMAND: &[u8] = b"?WATCH={\"enable\":true,\"json\":true};\n";
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "class")]
+enum Report {
+    #[serde(rename = "TPV")]
+    Tpv(Tpv),
+    #[serde(rename = "SKY")]
+    Sky(Sky),
+    #[serde(other)]
+    Other, // VERSION, DEVICES, WATCH, ...
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Tpv {
+    #[serde(default)]
+    mode: u8, // 0 unknown, 1 no fix, 2 2D, 3 3D
+    time: Option<String>,
+    lat: Option<f64>,
+    lon: Option<f64>,
+    #[serde(rename = "altMSL")]
+    alt_msl: Option<f64>,
+    alt: Option<f64>, // deprecated, only sent by gpsd before 3.20
+    epx: Option<f64>, // longitude error, 95% confidence, in meters
+    epy: Option<f64>, // latitude error, 95% confidence, in meters
+    epv: Option<f64>, // vertical error, 95% confidence, in meters
+    eph: Option<f64>,
+    track: Option<f64>,
+    speed: Option<f64>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct Sky {
+    hdop: Option<f64>,
+    u_sat: Option<u32>,
+    satellites: Option<Vec<Satellite>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Satellite {
+    #[serde(default)]
+    used: bool,
+}
+
+/// What the latest TPV and SKY reports of one gpsd connection say
+#[derive(Debug, Default)]
+pub struct GpsdState {
+    hdop: Option<f64>,
+    satellites: Option<u32>,
+    fix: Option<Fix>,
+}
+
+impl GpsdState {
+    /// Latest fix, `None` while gpsd reports no 2D or 3D fix
+    pub fn fix(&self) -> Option<&Fix> {
+        self.fix.as_ref()
+    }
+
+    /// Feed one line from gpsd, returns whether it was a TPV report
+    pub fn update(&mut self, line: &str) -> Result<bool> {
+        let report: Report = serde_json::from_str(line)
+            .map_err(|e| Error::Gps(format!("bad gpsd report: {}", e)))?;
+
+        match report {
+            Report::Sky(sky) => {
+                self.hdop = sky.hdop.or(self.hdop);
+                self.satellites = sky
+                    .u_sat
+                    .or_else(|| {
+                        sky.satellites
+                            .map(|sats| sats.iter().filter(|s| s.used).count() as u32)
+                    })
+                    .or(self.satellites);
+                Ok(false)
+            }
+            Report::Tpv(tpv) => {
+                self.fix = self.fix_from(tpv);
+                Ok(true)
+            }
+            Report::Other => Ok(false),
+        }
+    }
+
+    fn fix_from(&self, tpv: Tpv) -> Option<Fix> {
+        let mode = match tpv.mode {
+            2 => FixMode::TwoD,
+            3 => FixMode::ThreeD,
+            _ => return None,
+        };
+        let mut position = gps_position(tpv.lat?, tpv.lon?);
+
+        // same combination gpsd uses for eph when the receiver doesn't report it
+        position.accuracy = match (tpv.epx, tpv.epy) {
+            (Some(x), Some(y)) => Some(x.hypot(y)),
+            _ => tpv.eph.or_else(|| self.hdop.map(accuracy_from_dop)),
+        };
+        if mode == FixMode::ThreeD {
+            position.altitude = tpv.alt_msl.or(tpv.alt);
+            position.altitudeAccuracy = tpv.epv;
+        }
+        position.heading = tpv.track;
+        position.speed = tpv.speed;
+
+        let timestamp = tpv
+            .time
+            .as_deref()
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+            .and_then(|t| u128::try_from(t.timestamp_millis()).ok())
+            .unwrap_or_else(unix_millis);
+
+        Some(Fix {
+            position,
+            timestamp,
+            mode,
+            satellites: self.satellites,
+        })
+    }
+}
+
+/// Follow gpsd at `address` in the background, reconnecting whenever it goes away
+pub fn spawn(address: impl Into<String>) -> FixReceiver {
+    let address = address.into();
+    let (tx, rx) = watch::channel(None);
+
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = watch_gpsd(&address, &tx).await {
+                tracing::warn!("[gpsd] {}", e);
+            }
+            tx.send_replace(None); // a fix from a dead connection is stale
+            if tx.is_closed() {
+                break;
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    });
+
+    rx
+}
+
+/// Stream fixes from one gpsd connection into `tx` until gpsd disconnects
+pub async fn watch_gpsd(address: &str, tx: &watch::Sender<Option<Fix>>) -> Result<()> {
+    let mut stream = TcpStream::connect(address)
+        .await
+        .map_err(|e| Error::Gps(format!("cannot connect to gpsd at {}: {}", address, e)))?;
+    stream.write_all(WATCH_COMMAND).await?;
+    tracing::info!("[gpsd] Watching {}", address);
+
+    let mut lines = BufReader::new(stream).lines();
+    let mut state = GpsdState::default();
+    while let Some(line) = lines.next_line().await? {
+        match state.update(&line) {
+            Ok(true) => {
+                tx.send_replace(state.fix().cloned());
+            }
+            Ok(false) => {}
+            Err(e) => tracing::debug!("[gpsd] {}", e),
+        }
+    }
+
+    Err(Error::Gps("gpsd closed the connection".into()))
+}
@@ -0,0 +1,115 @@
+//! GPX tracks recorded by the phone, for positioning scans after the fact
+//!
+//! Every `trkpt` with a time becomes a track point, regardless of the track
+//! or segment it belongs to. Positions between two points are interpolated
+//! linearly, which is plenty for the few seconds between GPS samples.
+
+use chrono::DateTime;
+use std::path::Path;
+
+use super::fix::{accuracy_from_dop, gps_position};
+use super::trajectory::{Sample, bracket, interpolate};
+use crate::config::GPX_MAX_GAP_MS;
+use crate::error::{Error, Result};
+use crate::geosubmit::Position;
+
+pub use super::trajectory::Rejection;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackPoint {
+    pub timestamp: u128, // in milliseconds since Unix epoch
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: Option<f64>,
+    pub hdop: Option<f64>,
+}
+
+impl TrackPoint {
+    fn sample(&self) -> Sample {
+        let mut position = gps_position(self.latitude, self.longitude);
+        position.altitude = self.altitude;
+        position.accuracy = self.hdop.map(accuracy_from_dop);
+        Sample {
+            timestamp: self.timestamp,
+            position,
+        }
+    }
+}
+
+/// Track points sorted by time
+#[derive(Debug, Clone)]
+pub struct Track {
+    points: Vec<TrackPoint>,
+    max_gap: u128, // in milliseconds
+}
+
+impl Track {
+    pub fn new(mut points: Vec<TrackPoint>) -> Self {
+        points.sort_by_key(|p| p.timestamp);
+        points.dedup_by_key(|p| p.timestamp);
+        Track {
+            points,
+            max_gap: GPX_MAX_GAP_MS as u128,
+        }
+    }
+
+    /// Allow interpolating across gaps of up to `max_gap` milliseconds
+    pub fn with_max_gap(mut self, max_gap: u128) -> Self {
+        self.max_gap = max_gap;
+        self
+    }
+
+    pub fn parse(xml: &str) -> Result<Self> {
+        let document = roxmltree::Document::parse(xml)
+            .map_err(|e| Error::Gps(format!("invalid GPX: {}", e)))?;
+
+        let mut points = Vec::new();
+        for node in document.descendants().filter(|n| n.has_tag_name("trkpt")) {
+            let child = |name: &str| {
+                node.children()
+                    .find(|c| c.has_tag_name(name))
+                    .and_then(|c| c.text())
+                    .map(str::trim)
+            };
+            let Some(time) = child("time") else {
+                continue; // can't place it in time, useless for georeferencing
+            };
+            let timestamp = DateTime::parse_from_rfc3339(time)
+                .map_err(|e| Error::Gps(format!("invalid GPX time {:?}: {}", time, e)))?
+                .timestamp_millis();
+            let coordinate = |name: &str| {
+                node.attribute(name)
+                    .and_then(|v| v.parse::<f64>().ok())
+                    .ok_or_else(|| Error::Gps(format!("trkpt without a valid {}", name)))
+            };
+
+            points.push(TrackPoint {
+                timestamp: u128::try_from(timestamp)
+                    .map_err(|_| Error::Gps(format!("GPX time before 1970: {}", time)))?,
+                latitude: coordinate("lat")?,
+                longitude: coordinate("lon")?,
+                altitude: child("ele").and_then(|v| v.parse().ok()),
+                hdop: child("hdop").and_then(|v| v.parse().ok()),
+            });
+        }
+
+        if points.is_empty() {
+            return Err(Error::Gps("GPX file has no timed track points".into()));
+        }
+        Ok(Track::new(points))
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        Track::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn points(&self) -> &[TrackPoint] {
+        &self.points
+    }
+
+    /// Position at `timestamp`, interpolated between the surrounding track points
+    pub fn position_at(&self, timestamp: u128) -> std::result::Result<Position, Rejection> {
+        let (before, after) = bracket(&self.points, timestamp, self.max_gap, |p| p.timestamp)?;
+        Ok(interpolate(&before.sample(), &after.sample(), timestamp))
+    }
+}
@@ -0,0 +1,354 @@
+//! Position provider for NMEA 0183 receivers, on a serial port or recorded to a file
+//!
+//! GGA and RMC carry the fix, GSA the fix type and DOPs, GST the error
+//! estimates and VTG course and speed. Sentences from any talker are accepted,
+//! but once a receiver sends combined GN sentences the per-constellation ones
+//! (GP, GL, GA, ...) are ignored so two solutions never get mixed.
+
+use chrono::{NaiveDate, NaiveTime, Utc};
+use std::io::{BufRead, BufReader, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::watch;
+
+use super::fix::{Fix, FixMode, FixReceiver, accuracy_from_dop, gps_position};
+use crate::error::{Error, Result};
+
+const KNOTS_TO_MPS: f64 = 0.514_444;
+const KMH_TO_MPS: f64 = 1.0 / 3.6;
+const REOPEN_DELAY: Duration = Duration::from_secs(5);
+const SERIAL_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Where NMEA sentences come from
+#[derive(Debug, Clone)]
+pub enum NmeaSource {
+    Serial { path: String, baud_rate: u32 },
+    File(PathBuf), // replayed at the pace it was recorded
+}
+
+/// What the sentences of the current epoch say about the fix
+#[derive(Debug, Default)]
+pub struct NmeaParser {
+    combined: bool, // seen a GN talker, ignore single-constellation sentences
+    date: Option<NaiveDate>,
+    time: Option<NaiveTime>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    valid: bool, // GGA quality or RMC status allow using the position
+    satellites: Option<u32>,
+    altitude: Option<f64>,
+    hdop: Option<f64>,
+    vdop: Option<f64>,
+    mode: Option<FixMode>,                              // from GSA
+    errors: Option<(NaiveTime, f64, f64, Option<f64>)>, // GST lat, lon, alt 1-sigma errors
+    course: Option<f64>,
+    speed: Option<f64>,
+    fix: Option<Fix>,
+}
+
+impl NmeaParser {
+    /// Latest fix, `None` while the receiver reports no valid fix
+    pub fn fix(&self) -> Option<&Fix> {
+        self.fix.as_ref()
+    }
+
+    /// Feed one sentence, returns whether it was used
+    pub fn update(&mut self, line: &str) -> Result<bool> {
+        let body = checked_body(line.trim())?;
+        let mut fields = body.split(',');
+        let address = fields.next().unwrap_or_default();
+        if address.len() != 5 || address.starts_with('P') {
+            return Ok(false); // proprietary sentence
+        }
+        let (talker, kind) = address.split_at(2);
+        let fields: Vec<&str> = fields.collect();
+
+        if talker == "GN" {
+            self.combined = true;
+        } else if self.combined && matches!(kind, "GGA" | "RMC" | "VTG" | "GST") {
+            return Ok(false);
+        }
+
+        match kind {
+            "GGA" => self.gga(&fields)?,
+            "RMC" => self.rmc(&fields)?,
+            "GSA" => self.gsa(&fields),
+            "GST" => self.gst(&fields)?,
+            "VTG" => self.vtg(&fields),
+            _ => return Ok(false),
+        }
+        self.fix = self.build_fix();
+        Ok(true)
+    }
+
+    fn gga(&mut self, f: &[&str]) -> Result<()> {
+        self.new_epoch(parse_time(field(f, 0))?);
+        self.latitude = parse_coordinate(field(f, 1), field(f, 2), 2)?;
+        self.longitude = parse_coordinate(field(f, 3), field(f, 4), 3)?;
+        // 1 GPS, 2 DGPS, 3 PPS, 4 RTK fixed, 5 RTK float; not 6 dead reckoning, 7 manual or 8 simulated
+        self.valid = matches!(field(f, 5), "1" | "2" | "3" | "4" | "5");
+        self.satellites = field(f, 6).parse().ok();
+        self.hdop = field(f, 7).parse().ok();
+        self.altitude = field(f, 8).parse().ok();
+        Ok(())
+    }
+
+    fn rmc(&mut self, f: &[&str]) -> Result<()> {
+        self.new_epoch(parse_time(field(f, 0))?);
+        // position mode indicator (NMEA 2.3+): N not valid, E estimated, M manual, S simulated
+        let mode_ok = !matches!(field(f, 11), "N" | "E" | "M" | "S");
+        self.valid = field(f, 1) == "A" && mode_ok;
+        self.latitude = parse_coordinate(field(f, 2), field(f, 3), 2)?;
+        self.longitude = parse_coordinate(field(f, 4), field(f, 5), 3)?;
+        self.speed = field(f, 6)
+            .parse::<f64>()
+            .ok()
+            .map(|knots| knots * KNOTS_TO_MPS);
+        self.course = field(f, 7).parse().ok();
+        if let Ok(date) = NaiveDate::parse_from_str(field(f, 8), "%d%m%y") {
+            self.date = Some(date);
+        }
+        Ok(())
+    }
+
+    fn gsa(&mut self, f: &[&str]) {
+        self.mode = match field(f, 1) {
+            "2" => Some(FixMode::TwoD),
+            "3" => Some(FixMode::ThreeD),
+            _ => Some(FixMode::NoFix),
+        };
+        // DOPs follow the 12 satellite slots
+        self.hdop = field(f, 15).parse().ok().or(self.hdop);
+        self.vdop = field(f, 16).parse().ok().or(self.vdop);
+    }
+
+    fn gst(&mut self, f: &[&str]) -> Result<()> {
+        let time = parse_time(field(f, 0))?;
+        let lat = field(f, 5).parse().ok();
+        let lon = field(f, 6).parse().ok();
+        self.errors = lat
+            .zip(lon)
+            .map(|(lat, lon)| (time, lat, lon, field(f, 7).parse().ok()));
+        Ok(())
+    }
+
+    fn vtg(&mut self, f: &[&str]) {
+        self.course = field(f, 0).parse().ok().or(self.course);
+        let knots = field(f, 4).parse::<f64>().ok().map(|v| v * KNOTS_TO_MPS);
+        let kmh = field(f, 6).parse::<f64>().ok().map(|v| v * KMH_TO_MPS);
+        self.speed = kmh.or(knots).or(self.speed);
+    }
+
+    /// Forget what belonged to the previous epoch when a sentence with a new time arrives
+    fn new_epoch(&mut self, time: NaiveTime) {
+        if self.time != Some(time) {
+            if self.time.is_some_and(|previous| time < previous) {
+                // the clock wrapped past midnight, RMC will confirm the new date
+                self.date = self.date.and_then(|d| d.succ_opt());
+            }
+            self.time = Some(time);
+            self.course = None;
+            self.speed = None;
+        }
+    }
+
+    fn build_fix(&self) -> Option<Fix> {
+        if !self.valid || self.mode == Some(FixMode::NoFix) {
+            return None;
+        }
+        let mut position = gps_position(self.latitude?, self.longitude?);
+        let time = self.time?;
+        let mode = self.mode.unwrap_or(if self.altitude.is_some() {
+            FixMode::ThreeD
+        } else {
+            FixMode::TwoD
+        });
+
+        // GST gives 1-sigma errors in meters, fall back to scaling the DOPs
+        let errors = self.errors.filter(|(at, ..)| *at == time);
+        position.accuracy = match errors {
+            Some((_, lat, lon, _)) => Some(f64::hypot(lat, lon)),
+            None => self.hdop.map(accuracy_from_dop),
+        };
+        if mode == FixMode::ThreeD {
+            position.altitude = self.altitude;
+            position.altitudeAccuracy = match errors {
+                Some((_, _, _, alt)) => alt,
+                None => self.vdop.map(accuracy_from_dop),
+            };
+        }
+        position.heading = self.course;
+        position.speed = self.speed;
+
+        let date = self.date.unwrap_or_else(|| Utc::now().date_naive());
+        let timestamp = date.and_time(time).and_utc().timestamp_millis();
+
+        Some(Fix {
+            position,
+            timestamp: u128::try_from(timestamp).ok()?,
+            mode,
+            satellites: self.satellites,
+        })
+    }
+}
+
+/// Sentence between `$` and `*` after verifying its checksum
+fn checked_body(line: &str) -> Result<&str> {
+    let line = line
+        .strip_prefix('$')
+        .or_else(|| line.strip_prefix('!'))
+        .ok_or_else(|| Error::Gps(format!("not an NMEA sentence: {}", line)))?;
+    let (body, checksum) = line
+        .rsplit_once('*')
+        .ok_or_else(|| Error::Gps(format!("missing checksum: {}", line)))?;
+
+    let expected = u8::from_str_radix(checksum, 16)
+        .map_err(|_| Error::Gps(format!("bad checksum: {}", line)))?;
+    let actual = body.bytes().fold(0, |sum, b| sum ^ b);
+    if actual != expected {
+        return Err(Error::Gps(format!(
+            "checksum mismatch, expected {:02X} got {:02X}: {}",
+            expected, actual, line
+        )));
+    }
+    Ok(body)
+}
+
+fn field<'a>(fields: &[&'a str], index: usize) -> &'a str {
+    fields.get(index).copied().unwrap_or_default()
+}
+
+/// `hhmmss.ss` in UTC
+fn parse_time(value: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(value, "%H%M%S%.f")
+        .or_else(|_| NaiveTime::parse_from_str(value, "%H%M%S"))
+        .map_err(|_| Error::Gps(format!("bad time: {:?}", value)))
+}
+
+/// `ddmm.mmmm` or `dddmm.mmmm` with a hemisphere, `None` when the receiver left it empty
+fn parse_coordinate(value: &str, hemisphere: &str, degree_digits: usize) -> Result<Option<f64>> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    let bad = || Error::Gps(format!("bad coordinate: {:?} {:?}", value, hemisphere));
+    if value.len() < degree_digits + 2 || !value.is_char_boundary(degree_digits) {
+        return Err(bad());
+    }
+
+    let (degrees, minutes) = value.split_at(degree_digits);
+    let degrees: f64 = degrees.parse().map_err(|_| bad())?;
+    let minutes: f64 = minutes.parse().map_err(|_| bad())?;
+    let unsigned = degrees + minutes / 60.0;
+
+    match hemisphere {
+        "N" | "E" => Ok(Some(unsigned)),
+        "S" | "W" => Ok(Some(-unsigned)),
+        _ => Err(bad()),
+    }
+}
+
+/// Every distinct fix in a recorded NMEA file
+pub fn read_file(path: &Path) -> Result<Vec<Fix>> {
+    let reader = BufReader::new(std::fs::File::open(path)?);
+    let mut parser = NmeaParser::default();
+    let mut fixes: Vec<Fix> = Vec::new();
+
+    for line in reader.split(b'\n') {
+        let line = line?;
+        match parser.update(&String::from_utf8_lossy(&line)) {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(e) => {
+                tracing::debug!("[NMEA] {}", e);
+                continue;
+            }
+        }
+        if let Some(fix) = parser.fix() {
+            // later sentences of an epoch refine the fix instead of adding one
+            match fixes.last_mut() {
+                Some(last) if last.timestamp == fix.timestamp => *last = fix.clone(),
+                _ => fixes.push(fix.clone()),
+            }
+        }
+    }
+    Ok(fixes)
+}
+
+/// Follow an NMEA receiver or recording in the background
+pub fn spawn(source: NmeaSource) -> FixReceiver {
+    let (tx, rx) = watch::channel(None);
+
+    std::thread::spawn(move || {
+        match source {
+            NmeaSource::Serial { path, baud_rate } => loop {
+                if let Err(e) = watch_serial(&path, baud_rate, &tx) {
+                    tracing::warn!("[NMEA] {}", e);
+                }
+                tx.send_replace(None);
+                if tx.is_closed() {
+                    break;
+                }
+                std::thread::sleep(REOPEN_DELAY);
+            },
+            NmeaSource::File(path) => {
+                if let Err(e) = replay_file(&path, &tx) {
+                    tracing::warn!("[NMEA] {}", e);
+                }
+                tx.send_replace(None); // recording is over
+            }
+        }
+    });
+
+    rx
+}
+
+/// Stream fixes from a serial receiver into `tx` until the port fails
+pub fn watch_serial(path: &str, baud_rate: u32, tx: &watch::Sender<Option<Fix>>) -> Result<()> {
+    let port = serialport::new(path, baud_rate)
+        .timeout(SERIAL_TIMEOUT)
+        .open()
+        .map_err(|e| Error::Gps(format!("cannot open {}: {}", path, e)))?;
+    tracing::info!("[NMEA] Reading {} at {} baud", path, baud_rate);
+
+    let mut reader = BufReader::new(port);
+    let mut parser = NmeaParser::default();
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) => return Err(Error::Gps(format!("{} closed", path))),
+            Ok(_) => publish(&mut parser, &line, tx),
+            Err(e) if e.kind() == ErrorKind::TimedOut => {} // receiver is quiet, keep waiting
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Replay a recording into `tx`, sleeping between fixes as long as the receiver did.
+/// Fixes are stamped with the time they are replayed so they look current.
+fn replay_file(path: &Path, tx: &watch::Sender<Option<Fix>>) -> Result<()> {
+    let mut previous: Option<u128> = None;
+    for mut fix in read_file(path)? {
+        if let Some(previous) = previous {
+            let gap = fix.timestamp.saturating_sub(previous) as u64;
+            std::thread::sleep(Duration::from_millis(gap));
+        }
+        previous = Some(fix.timestamp);
+        fix.timestamp = Utc::now().timestamp_millis() as u128;
+        tx.send_replace(Some(fix));
+        if tx.is_closed() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn publish(parser: &mut NmeaParser, line: &[u8], tx: &watch::Sender<Option<Fix>>) {
+    match parser.update(&String::from_utf8_lossy(line)) {
+        Ok(true) => {
+            tx.send_replace(parser.fix().cloned());
+        }
+        Ok(false) => {}
+        Err(e) => tracing::debug!("[NMEA] {}", e),
+    }
+}
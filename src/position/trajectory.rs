@@ -0,0 +1,195 @@
+//! Recent positions pushed by streaming phones, one trajectory per client
+//!
+//! Scans that finish between two updates get a position interpolated from
+//! the samples around them, the same way GPX tracks are handled.
+
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+use super::geo::{bearing_degrees, distance_meters};
+use crate::config::{TRAJECTORY_MAX_GAP_MS, TRAJECTORY_WINDOW_MS};
+use crate::geosubmit::Position;
+use crate::time::unix_millis;
+
+static TRAJECTORIES: Lazy<Mutex<HashMap<String, Trajectory>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// A position and when it was measured
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub timestamp: u128, // in milliseconds since Unix epoch
+    pub position: Position,
+}
+
+/// Why a scan could not be positioned from a track or trajectory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    BeforeTrack,
+    AfterTrack,
+    Gap { millis: u128 }, // the points around the scan are this far apart
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::BeforeTrack => write!(f, "before the track starts"),
+            Rejection::AfterTrack => write!(f, "after the track ends"),
+            Rejection::Gap { millis } => write!(f, "in a {}s gap of the track", millis / 1000),
+        }
+    }
+}
+
+/// The points at or around `timestamp` in `points`, which are sorted by `time`
+pub(crate) fn bracket<T>(
+    points: &[T],
+    timestamp: u128,
+    max_gap: u128,
+    time: impl Fn(&T) -> u128,
+) -> Result<(&T, &T), Rejection> {
+    let (first, last) = match (points.first(), points.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return Err(Rejection::AfterTrack),
+    };
+    if timestamp < time(first) {
+        return Err(Rejection::BeforeTrack);
+    }
+    if timestamp > time(last) {
+        return Err(Rejection::AfterTrack);
+    }
+
+    // first point at or after the scan
+    let index = points.partition_point(|p| time(p) < timestamp);
+    let after = &points[index];
+    let before = if time(after) == timestamp {
+        after
+    } else {
+        &points[index - 1]
+    };
+
+    let span = time(after) - time(before);
+    if span > max_gap {
+        return Err(Rejection::Gap { millis: span });
+    }
+    Ok((before, after))
+}
+
+/// Linear interpolation between two samples, `before.timestamp <= timestamp <= after.timestamp`
+pub(crate) fn interpolate(before: &Sample, after: &Sample, timestamp: u128) -> Position {
+    let span = after.timestamp - before.timestamp;
+    let t = if span == 0 {
+        0.0
+    } else {
+        (timestamp - before.timestamp) as f64 / span as f64
+    };
+    let lerp = |a: f64, b: f64| a + (b - a) * t;
+    let (a, b) = (&before.position, &after.position);
+
+    let mut position = a.clone();
+    position.latitude = lerp(a.latitude, b.latitude);
+    position.longitude = lerp(a.longitude, b.longitude);
+    position.altitude = match (a.altitude, b.altitude) {
+        (Some(a), Some(b)) => Some(lerp(a, b)),
+        (a, b) => a.or(b),
+    };
+    // the worse of the two samples bounds the interpolated one
+    position.accuracy = [a.accuracy, b.accuracy]
+        .into_iter()
+        .flatten()
+        .reduce(f64::max);
+    // time to the nearest real sample
+    position.age = Some((timestamp - before.timestamp).min(after.timestamp - timestamp) as u64);
+
+    if span > 0 {
+        let meters = distance_meters(a.latitude, a.longitude, b.latitude, b.longitude);
+        position.speed = Some(meters / (span as f64 / 1000.0));
+        if meters > 0.0 {
+            position.heading = Some(bearing_degrees(
+                a.latitude,
+                a.longitude,
+                b.latitude,
+                b.longitude,
+            ));
+        }
+    }
+    position
+}
+
+/// Samples from one client, sorted by time and trimmed to `TRAJECTORY_WINDOW_MS`
+#[derive(Debug, Clone, Default)]
+pub struct Trajectory {
+    samples: Vec<Sample>,
+}
+
+impl Trajectory {
+    pub fn push(&mut self, sample: Sample) {
+        // updates may arrive out of order over BLE
+        let index = self
+            .samples
+            .partition_point(|s| s.timestamp <= sample.timestamp);
+        self.samples.insert(index, sample);
+
+        let newest = self.samples.last().map_or(0, |s| s.timestamp);
+        let cutoff = newest.saturating_sub(TRAJECTORY_WINDOW_MS as u128);
+        self.samples.retain(|s| s.timestamp >= cutoff);
+    }
+
+    pub fn samples(&self) -> &[Sample] {
+        &self.samples
+    }
+
+    pub fn latest(&self) -> Option<&Sample> {
+        self.samples.last()
+    }
+
+    pub fn position_at(&self, timestamp: u128) -> Result<Position, Rejection> {
+        let (before, after) = bracket(
+            &self.samples,
+            timestamp,
+            TRAJECTORY_MAX_GAP_MS as u128,
+            |s| s.timestamp,
+        )?;
+        Ok(interpolate(before, after, timestamp))
+    }
+}
+
+/// Add a sample to `client`'s trajectory, forgetting clients that stopped streaming
+pub fn record(client: &str, sample: Sample) {
+    let cutoff = unix_millis().saturating_sub(TRAJECTORY_WINDOW_MS as u128);
+    let mut trajectories = TRAJECTORIES.lock().unwrap_or_else(|e| e.into_inner());
+    trajectories.retain(|_, t| t.latest().is_some_and(|s| s.timestamp >= cutoff));
+    trajectories
+        .entry(client.to_string())
+        .or_default()
+        .push(sample);
+}
+
+/// Whether any client sent a sample in the last `within_ms`
+pub fn active(within_ms: u64) -> bool {
+    let cutoff = unix_millis().saturating_sub(within_ms as u128);
+    let trajectories = TRAJECTORIES.lock().unwrap_or_else(|e| e.into_inner());
+    trajectories
+        .values()
+        .any(|t| t.latest().is_some_and(|s| s.timestamp >= cutoff))
+}
+
+/// The client that sent the most recent sample
+pub fn latest_client() -> Option<String> {
+    let trajectories = TRAJECTORIES.lock().unwrap_or_else(|e| e.into_inner());
+    trajectories
+        .iter()
+        .filter_map(|(client, t)| Some((t.latest()?.timestamp, client)))
+        .max()
+        .map(|(_, client)| client.clone())
+}
+
+/// Position at `timestamp` from `client`'s trajectory.
+/// `AfterTrack` means the client may still send the samples needed.
+pub fn locate(client: &str, timestamp: u128) -> Result<Position, Rejection> {
+    let trajectories = TRAJECTORIES.lock().unwrap_or_else(|e| e.into_inner());
+    trajectories
+        .get(client)
+        .ok_or(Rejection::AfterTrack)?
+        .position_at(timestamp)
+}
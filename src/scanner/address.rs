@@ -0,0 +1,74 @@
+//! BLE address types
+//!
+//! BlueZ only says whether an address is public or random. The two most
+//! significant bits of a random address tell its kind: static addresses stay
+//! the same until the device restarts, private ones change every few minutes
+//! to keep the device from being followed, and can't be found again later.
+
+use btleplug::api::{AddressType as BluezAddressType, BDAddr};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub enum AddressType {
+    Public,
+    RandomStatic,
+    #[serde(rename = "rpa")]
+    ResolvablePrivate, // only the device's bonded peers can tell who it is
+    #[serde(rename = "nrpa")]
+    NonResolvablePrivate,
+}
+
+impl AddressType {
+    /// Kind of `address`, given whether BlueZ reported it as random.
+    /// `None` for random addresses with the reserved prefix.
+    pub fn classify(address: &BDAddr, kind: BluezAddressType) -> Option<AddressType> {
+        if kind == BluezAddressType::Public {
+            return Some(AddressType::Public);
+        }
+        match address.into_inner()[0] >> 6 {
+            0b11 => Some(AddressType::RandomStatic),
+            0b01 => Some(AddressType::ResolvablePrivate),
+            0b00 => Some(AddressType::NonResolvablePrivate),
+            _ => None,
+        }
+    }
+
+    /// Whether the address changes every few minutes
+    pub fn is_rotating(self) -> bool {
+        matches!(
+            self,
+            AddressType::ResolvablePrivate | AddressType::NonResolvablePrivate
+        )
+    }
+
+    /// Short label for logs and statistics
+    pub fn label(self) -> &'static str {
+        match self {
+            AddressType::Public => "public",
+            AddressType::RandomStatic => "randomStatic",
+            AddressType::ResolvablePrivate => "rpa",
+            AddressType::NonResolvablePrivate => "nrpa",
+        }
+    }
+}
+
+/// Which random addresses are left out of uploads
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum RandomAddressPolicy {
+    Keep,
+    #[default]
+    DropRotating, // private addresses, which can't be located again anyway
+    DropRandom, // static ones as well
+}
+
+impl RandomAddressPolicy {
+    pub fn drops(self, kind: AddressType) -> bool {
+        match self {
+            RandomAddressPolicy::Keep => false,
+            RandomAddressPolicy::DropRotating => kind.is_rotating(),
+            RandomAddressPolicy::DropRandom => kind != AddressType::Public,
+        }
+    }
+}
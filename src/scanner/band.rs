@@ -0,0 +1,121 @@
+//! Wi-Fi bands and their channel numbering
+//!
+//! Channel numbers repeat across bands, 6 GHz channel 1 is not 2.4 GHz
+//! channel 1, so a channel only means something together with its band.
+//! The frequency decides the band.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::error::{Error, Result};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Band {
+    #[serde(rename = "2.4GHz")]
+    TwoPointFour,
+    #[serde(rename = "5GHz")]
+    Five, // including the 4.9 GHz public safety channels
+    #[serde(rename = "6GHz")]
+    Six,
+    #[serde(rename = "60GHz")]
+    Sixty,
+}
+
+impl Band {
+    pub const ALL: [Band; 4] = [Band::TwoPointFour, Band::Five, Band::Six, Band::Sixty];
+
+    /// Band of a center frequency in MHz
+    pub fn of(frequency: u32) -> Option<Band> {
+        match frequency {
+            2401..=2495 => Some(Band::TwoPointFour),
+            4910..=5895 => Some(Band::Five),
+            5925..=7125 => Some(Band::Six),
+            57240..=71000 => Some(Band::Sixty),
+            _ => None,
+        }
+    }
+
+    /// Channel number of `frequency` in this band, `None` if it isn't on the raster
+    pub fn channel(self, frequency: u32) -> Option<u8> {
+        let (base, spacing) = match (self, frequency) {
+            (Band::TwoPointFour, 2484) => return Some(14),
+            (Band::TwoPointFour, _) => (2407, 5),
+            (Band::Five, ..5000) => (4000, 5),
+            (Band::Five, _) => (5000, 5),
+            (Band::Six, 5935) => return Some(2),
+            (Band::Six, _) => (5950, 5),
+            (Band::Sixty, _) => (56160, 2160),
+        };
+        let offset = frequency.checked_sub(base)?;
+        if offset % spacing != 0 {
+            return None;
+        }
+        let channel = u8::try_from(offset / spacing).ok()?;
+        (self.frequency(channel) == Some(frequency)).then_some(channel)
+    }
+
+    /// Center frequency in MHz of `channel` in this band
+    pub fn frequency(self, channel: u8) -> Option<u32> {
+        let channel = channel as u32;
+        match self {
+            Band::TwoPointFour => match channel {
+                1..=13 => Some(2407 + 5 * channel),
+                14 => Some(2484),
+                _ => None,
+            },
+            Band::Five => match channel {
+                182..=196 => Some(4000 + 5 * channel),
+                32..=177 => Some(5000 + 5 * channel),
+                _ => None,
+            },
+            Band::Six => match channel {
+                2 => Some(5935),
+                1..=233 => Some(5950 + 5 * channel), // 20 MHz channels are 1, 5, 9, ..
+                _ => None,
+            },
+            Band::Sixty => match channel {
+                1..=6 => Some(56160 + 2160 * channel),
+                _ => None,
+            },
+        }
+    }
+
+    /// Short label for metrics
+    pub fn label(self) -> &'static str {
+        match self {
+            Band::TwoPointFour => "2.4",
+            Band::Five => "5",
+            Band::Six => "6",
+            Band::Sixty => "60",
+        }
+    }
+}
+
+impl fmt::Display for Band {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} GHz", self.label())
+    }
+}
+
+/// Band and channel of a center frequency in MHz
+pub fn channel(frequency: u32) -> Option<(Band, u8)> {
+    let band = Band::of(frequency)?;
+    Some((band, band.channel(frequency)?))
+}
+
+/// Check that `channel` is what `frequency` is on
+pub fn check(frequency: u32, channel: u8) -> Result<Band> {
+    let band = Band::of(frequency)
+        .ok_or_else(|| Error::InvalidChannel(format!("{} MHz is in no Wi-Fi band", frequency)))?;
+    match band.channel(frequency) {
+        Some(expected) if expected == channel => Ok(band),
+        Some(expected) => Err(Error::InvalidChannel(format!(
+            "{} MHz is channel {} of the {} band, not {}",
+            frequency, expected, band, channel
+        ))),
+        None => Err(Error::InvalidChannel(format!(
+            "{} MHz is not a {} channel",
+            frequency, band
+        ))),
+    }
+}
@@ -0,0 +1,189 @@
+//! Beacon frames in BLE advertisements
+//!
+//! Fixed beacons are what makes BLE useful for geolocation. They announce
+//! themselves in one of a few well-known formats: Apple's iBeacon and
+//! AltBeacon in manufacturer data, Google's Eddystone in service data.
+
+use btleplug::api::PeripheralProperties;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use super::address::AddressType;
+
+const APPLE: u16 = 0x004c;
+const EDDYSTONE: Uuid = Uuid::from_u128(0x0000feaa_0000_1000_8000_00805f9b34fb);
+
+const URL_SCHEMES: [&str; 4] = ["http://www.", "https://www.", "http://", "https://"];
+const URL_EXPANSIONS: [&str; 14] = [
+    ".com/", ".org/", ".edu/", ".net/", ".info/", ".biz/", ".gov/", ".com", ".org", ".edu", ".net",
+    ".info", ".biz", ".gov",
+];
+
+/// What a device advertises about itself, beyond its name
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Advertisement {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address_type: Option<AddressType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx_power: Option<i16>, // advertised TX power level, in dBm
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub service_uuids: Vec<Uuid>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub beacons: Vec<BeaconFrame>,
+}
+
+impl Advertisement {
+    pub fn from_properties(props: &PeripheralProperties) -> Self {
+        let mut beacons = BeaconFrame::from_manufacturer_data(&props.manufacturer_data);
+        beacons.extend(BeaconFrame::from_service_data(&props.service_data));
+        Advertisement {
+            address_type: props
+                .address_type
+                .and_then(|kind| AddressType::classify(&props.address, kind)),
+            tx_power: props.tx_power_level,
+            service_uuids: props.services.clone(),
+            beacons,
+        }
+    }
+
+    /// Whether any of the frames identifies a beacon that stays put
+    pub fn is_stationary(&self) -> bool {
+        self.beacons.iter().any(BeaconFrame::is_stationary)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum BeaconFrame {
+    #[serde(rename = "iBeacon")]
+    IBeacon {
+        uuid: Uuid,
+        major: u16,
+        minor: u16,
+        tx_power: i8, // in dBm at 1 m
+    },
+    EddystoneUid {
+        namespace: String, // 10 bytes in hex
+        instance: String,  // 6 bytes in hex
+        tx_power: i8,      // in dBm at 0 m
+    },
+    EddystoneUrl {
+        url: String,
+        tx_power: i8, // in dBm at 0 m
+    },
+    EddystoneTlm {
+        battery: Option<u16>,     // in mV
+        temperature: Option<f32>, // in °C
+        advertisements: u32,      // since power-on
+        uptime: u32,              // in tenths of a second
+    },
+    EddystoneEid {
+        eid: String,  // 8 bytes in hex, changes every few minutes
+        tx_power: i8, // in dBm at 0 m
+    },
+    AltBeacon {
+        id: String, // 20 bytes in hex
+        manufacturer: u16,
+        reference_rssi: i8, // in dBm at 1 m
+    },
+}
+
+impl BeaconFrame {
+    /// Frames from manufacturer data, keyed by company id
+    pub fn from_manufacturer_data(data: &HashMap<u16, Vec<u8>>) -> Vec<BeaconFrame> {
+        let mut companies: Vec<_> = data.iter().collect();
+        companies.sort_by_key(|&(&company, _)| company); // the map has no order
+        companies
+            .into_iter()
+            .filter_map(|(&company, data)| Self::manufacturer_frame(company, data))
+            .collect()
+    }
+
+    /// Frames from service data, keyed by service UUID
+    pub fn from_service_data(data: &HashMap<Uuid, Vec<u8>>) -> Vec<BeaconFrame> {
+        data.get(&EDDYSTONE)
+            .and_then(|data| Self::eddystone_frame(data))
+            .into_iter()
+            .collect()
+    }
+
+    fn manufacturer_frame(company: u16, data: &[u8]) -> Option<BeaconFrame> {
+        match data {
+            [0x02, 0x15, rest @ ..] if company == APPLE && rest.len() == 21 => {
+                Some(BeaconFrame::IBeacon {
+                    uuid: Uuid::from_slice(&rest[..16]).ok()?,
+                    major: u16::from_be_bytes([rest[16], rest[17]]),
+                    minor: u16::from_be_bytes([rest[18], rest[19]]),
+                    tx_power: rest[20] as i8,
+                })
+            }
+            [0xbe, 0xac, rest @ ..] if rest.len() == 22 => Some(BeaconFrame::AltBeacon {
+                id: hex::encode(&rest[..20]),
+                manufacturer: company,
+                reference_rssi: rest[20] as i8,
+            }),
+            _ => None,
+        }
+    }
+
+    fn eddystone_frame(data: &[u8]) -> Option<BeaconFrame> {
+        match data {
+            [0x00, tx_power, rest @ ..] if rest.len() >= 16 => Some(BeaconFrame::EddystoneUid {
+                namespace: hex::encode(&rest[..10]),
+                instance: hex::encode(&rest[10..16]),
+                tx_power: *tx_power as i8,
+            }),
+            [0x10, tx_power, scheme, encoded @ ..] => Some(BeaconFrame::EddystoneUrl {
+                url: decode_url(*scheme, encoded)?,
+                tx_power: *tx_power as i8,
+            }),
+            // version 0 is plain, version 1 is encrypted and not for us
+            [0x20, 0x00, rest @ ..] if rest.len() >= 12 => {
+                let battery = u16::from_be_bytes([rest[0], rest[1]]);
+                let temperature = i16::from_be_bytes([rest[2], rest[3]]);
+                Some(BeaconFrame::EddystoneTlm {
+                    battery: (battery != 0).then_some(battery),
+                    // signed 8.8 fixed point, 0x8000 when there is no sensor
+                    temperature: (temperature != i16::MIN).then(|| temperature as f32 / 256.0),
+                    advertisements: u32::from_be_bytes([rest[4], rest[5], rest[6], rest[7]]),
+                    uptime: u32::from_be_bytes([rest[8], rest[9], rest[10], rest[11]]),
+                })
+            }
+            [0x30, tx_power, rest @ ..] if rest.len() >= 8 => Some(BeaconFrame::EddystoneEid {
+                eid: hex::encode(&rest[..8]),
+                tx_power: *tx_power as i8,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Whether this frame identifies a beacon that stays put.
+    /// Telemetry identifies nothing and ephemeral ids are made to be untraceable.
+    pub fn is_stationary(&self) -> bool {
+        matches!(
+            self,
+            BeaconFrame::IBeacon { .. }
+                | BeaconFrame::AltBeacon { .. }
+                | BeaconFrame::EddystoneUid { .. }
+                | BeaconFrame::EddystoneUrl { .. }
+        )
+    }
+}
+
+fn decode_url(scheme: u8, encoded: &[u8]) -> Option<String> {
+    let mut url = URL_SCHEMES.get(scheme as usize)?.to_string();
+    for &byte in encoded {
+        match URL_EXPANSIONS.get(byte as usize) {
+            Some(expansion) => url.push_str(expansion),
+            None if byte.is_ascii_graphic() => url.push(byte as char),
+            None => return None,
+        }
+    }
+    Some(url)
+}
@@ -1,11 +1,47 @@
+//! BLE scanning
+//!
+//! One long-lived scan runs in the background from the first time it's
+//! needed. Every advertisement it hears is kept for a while with its RSSI,
+//! so snapshots and scan windows are answered from what was already heard
+//! instead of starting a new scan each time.
+
 use btleplug::api::BDAddr as mac_address;
-use btleplug::api::{Central, Manager as _, Peripheral as _, ScanFilter};
-use btleplug::platform::Manager;
+use btleplug::api::{
+    Central, CentralEvent, CentralState, Manager as _, Peripheral as _, PeripheralProperties,
+    ScanFilter,
+};
+use btleplug::platform::{Adapter, Manager, Peripheral, PeripheralId};
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, Once};
 use std::time::Duration;
-use tokio::time;
+use tokio::time::{self, Instant};
+use tokio_stream::StreamExt;
+
+use super::beacon::Advertisement;
+use super::oui::MacInfo;
+use super::window::{Samples, Window};
+use crate::config::{
+    BLE_MAX_SIGHTINGS, BLE_RETENTION_MS, BLE_RETRY_SECS, BLE_SNAPSHOT_MAX_AGE_MS,
+    SCAN_DURATION_SECS,
+};
+use crate::error::{Error, Result};
+use crate::server::events::{self, Event};
+use crate::server::status::{self, AdapterState};
+use crate::time::unix_millis;
 
-use crate::config::SCAN_DURATION_SECS;
+static TRACKER: Lazy<Mutex<Tracker>> = Lazy::new(|| Mutex::new(Tracker::new()));
+static SESSION: Lazy<Mutex<Session>> = Lazy::new(|| {
+    Mutex::new(Session {
+        spawned_at: Instant::now(),
+        listening_since: None,
+    })
+});
+static START: Once = Once::new();
+
+/// Advertisements this close together are the same one, reported as several events
+const SAME_ADVERTISEMENT_MS: u128 = 10;
 
 #[derive(Serialize, Debug, Deserialize, Clone)]
 pub struct BleDevice {
@@ -15,52 +51,320 @@ pub struct BleDevice {
     pub rssi: Option<i16>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub age: Option<u64>, // in milliseconds since last seen
+    #[serde(flatten)]
+    pub mac_info: MacInfo,
+    #[serde(flatten)]
+    pub advertisement: Advertisement,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub samples: Option<Samples>, // set when combined over a scan window
 }
 
-pub async fn fetch_ble_devices() -> Vec<BleDevice> {
-    let mut devices = vec![];
+impl BleDevice {
+    /// A device with only its address known yet
+    pub fn new(mac_address: mac_address) -> Self {
+        Self::advertised(mac_address, Advertisement::default())
+    }
 
-    let manager = match Manager::new().await {
-        Ok(m) => m,
-        Err(e) => {
-            println!("[BLE] Manager error: {:?}", e);
-            return devices;
+    /// A device heard sending `advertisement`, random addresses get no vendor
+    pub fn advertised(mac_address: mac_address, advertisement: Advertisement) -> Self {
+        BleDevice {
+            mac_address,
+            rssi: None,
+            name: None,
+            age: None,
+            mac_info: MacInfo::of_type(&mac_address, advertisement.address_type),
+            advertisement,
+            samples: None,
         }
-    };
+    }
+}
+
+/// Every device heard recently, with the RSSI of each advertisement
+#[derive(Debug, Default)]
+pub struct Tracker {
+    devices: HashMap<mac_address, Tracked>,
+}
+
+#[derive(Debug)]
+struct Tracked {
+    device: BleDevice,                        // as last heard
+    sightings: VecDeque<(Option<i16>, u128)>, // RSSI and time, in milliseconds since Unix epoch
+}
+
+impl Tracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record an advertisement from `device` heard at `at`
+    pub fn record(&mut self, device: BleDevice, at: u128) {
+        let tracked = self
+            .devices
+            .entry(device.mac_address)
+            .or_insert_with(|| Tracked {
+                device: device.clone(),
+                sightings: VecDeque::new(),
+            });
+        match tracked.sightings.back_mut() {
+            Some((rssi, last)) if at.abs_diff(*last) < SAME_ADVERTISEMENT_MS => {
+                *rssi = device.rssi.or(*rssi);
+            }
+            _ => tracked.sightings.push_back((device.rssi, at)),
+        }
+        if tracked.sightings.len() > BLE_MAX_SIGHTINGS {
+            tracked.sightings.pop_front();
+        }
+        tracked.device = device;
+    }
+
+    /// Forget advertisements older than [`BLE_RETENTION_MS`], and devices without any left
+    pub fn prune(&mut self, now: u128) {
+        let cutoff = now.saturating_sub(BLE_RETENTION_MS as u128);
+        self.devices.retain(|_, tracked| {
+            while tracked
+                .sightings
+                .front()
+                .is_some_and(|&(_, at)| at < cutoff)
+            {
+                tracked.sightings.pop_front();
+            }
+            !tracked.sightings.is_empty()
+        });
+    }
+
+    /// Number of devices with advertisements still kept
+    pub fn len(&self) -> usize {
+        self.devices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    /// Devices heard in the last `max_age_ms`, each as last heard
+    pub fn snapshot(&self, now: u128, max_age_ms: u64) -> Vec<BleDevice> {
+        let cutoff = now.saturating_sub(max_age_ms as u128);
+        let mut devices: Vec<BleDevice> = self
+            .devices
+            .values()
+            .filter_map(|tracked| {
+                let &(_, last) = tracked.sightings.back()?;
+                (last >= cutoff).then(|| {
+                    let mut device = tracked.device.clone();
+                    device.age = Some(now.saturating_sub(last) as u64);
+                    device
+                })
+            })
+            .collect();
+        devices.sort_by_key(|device| device.mac_address);
+        devices
+    }
 
-    let adapters = manager.adapters().await.unwrap_or_default();
-    let adapter = match adapters.into_iter().next() {
-        // check to see if there's at least one bluetooth adapter/card
-        Some(a) => a,
-        None => {
-            println!("[BLE] No adapters found");
-            return devices;
+    /// Advertisements heard since `from` combined per device, see [`Window`]
+    pub fn window(&self, from: u128, now: u128) -> Vec<BleDevice> {
+        let mut window = Window::new();
+        let mut sightings: Vec<_> = self
+            .devices
+            .values()
+            .flat_map(|tracked| {
+                tracked
+                    .sightings
+                    .iter()
+                    .filter(move |&&(_, at)| at >= from && at <= now)
+                    .map(move |&(rssi, at)| (tracked, rssi, at))
+            })
+            .collect();
+        sightings.sort_by_key(|&(tracked, _, at)| (at, tracked.device.mac_address));
+        for (tracked, rssi, at) in sightings {
+            window.add(
+                BleDevice {
+                    rssi,
+                    ..tracked.device.clone()
+                },
+                at,
+            );
         }
+        window.finish(now)
+    }
+}
+
+/// Whether the background scan is listening, and since when
+struct Session {
+    spawned_at: Instant,
+    listening_since: Option<Instant>,
+}
+
+fn set_listening(listening: bool) {
+    let mut session = SESSION.lock().unwrap_or_else(|e| e.into_inner());
+    session.listening_since = if listening {
+        session.listening_since.or(Some(Instant::now()))
+    } else {
+        None
     };
+}
 
-    println!("[BLE] Starting BLE scan...");
+/// Start the background scan, if it isn't running yet
+pub fn spawn() {
+    START.call_once(|| {
+        Lazy::force(&SESSION);
+        tokio::spawn(run());
+    });
+}
 
-    if let Err(e) = adapter.start_scan(ScanFilter::default()).await {
-        println!("[BLE] Scan failed: {:?}", e);
-        return devices;
+/// Keep a scan running, starting over whenever the adapter goes away
+async fn run() {
+    loop {
+        if let Err(e) = listen().await {
+            println!("[BLE] Scanning stopped: {}", e);
+            status::record_ble_adapter(AdapterState::Unavailable(e.to_string()));
+        }
+        set_listening(false);
+        time::sleep(Duration::from_secs(BLE_RETRY_SECS)).await;
     }
+}
 
-    time::sleep(Duration::from_secs(SCAN_DURATION_SECS)).await;
+/// Scan on the first adapter until its event stream ends
+async fn listen() -> Result<()> {
+    let manager = Manager::new()
+        .await
+        .map_err(|e| Error::BleAdapter(e.to_string()))?;
+    let adapter = manager
+        .adapters()
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .next() // check to see if there's at least one bluetooth adapter/card
+        .ok_or_else(|| Error::BleAdapter("no adapters found".into()))?;
 
-    let scan_results = adapter.peripherals().await.unwrap_or_default();
+    // subscribe first so nothing heard after starting is missed
+    let mut advertisements = adapter
+        .events()
+        .await
+        .map_err(|e| Error::BleAdapter(e.to_string()))?;
+    match adapter.adapter_state().await {
+        Ok(CentralState::PoweredOff) => {
+            println!("[BLE] Adapter is powered off, waiting for it");
+            status::record_ble_adapter(AdapterState::Unavailable("powered off".into()));
+        }
+        _ => start_scan(&adapter).await?,
+    }
 
-    for broadcaster in scan_results {
-        if let Ok(Some(props)) = broadcaster.properties().await {
-            let device = BleDevice {
-                mac_address: broadcaster.address(),
-                rssi: props.rssi,
-                name: props.local_name.filter(|n| !n.is_empty()),
-            };
+    let mut pruned = unix_millis();
+    while let Some(event) = advertisements.next().await {
+        match event {
+            CentralEvent::StateUpdate(CentralState::PoweredOff) => {
+                println!("[BLE] Adapter powered off");
+                set_listening(false);
+                status::record_ble_adapter(AdapterState::Unavailable("powered off".into()));
+            }
+            CentralEvent::StateUpdate(CentralState::PoweredOn) => {
+                println!("[BLE] Adapter powered on");
+                start_scan(&adapter).await?;
+            }
+            CentralEvent::DeviceDiscovered(id)
+            | CentralEvent::DeviceUpdated(id)
+            | CentralEvent::ManufacturerDataAdvertisement { id, .. }
+            | CentralEvent::ServiceDataAdvertisement { id, .. } => heard(&adapter, &id).await,
+            _ => {}
+        }
 
-            devices.push(device);
+        let now = unix_millis();
+        if now.saturating_sub(pruned) >= BLE_RETENTION_MS as u128 / 4 {
+            TRACKER.lock().unwrap_or_else(|e| e.into_inner()).prune(now);
+            pruned = now;
         }
     }
 
+    let _ = adapter.stop_scan().await;
+    Err(Error::BleAdapter("event stream ended".into()))
+}
+
+async fn start_scan(adapter: &Adapter) -> Result<()> {
+    println!("[BLE] Starting BLE scan...");
+    adapter
+        .start_scan(ScanFilter::default())
+        .await
+        .map_err(|e| Error::BleAdapter(e.to_string()))?;
+    set_listening(true);
+    status::record_ble_adapter(AdapterState::Available);
+    Ok(())
+}
+
+async fn heard(adapter: &Adapter, id: &PeripheralId) {
+    let at = unix_millis();
+    if let Ok(broadcaster) = adapter.peripheral(id).await
+        && let Ok(Some(props)) = broadcaster.properties().await
+    {
+        let device = device(&broadcaster, props);
+        TRACKER
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .record(device, at);
+    }
+}
+
+/// Wait until the background scan has been listening for a scan's duration.
+/// Returns at once if the adapter has been unavailable for that long.
+async fn warm_up() {
+    let scan = Duration::from_secs(SCAN_DURATION_SECS);
+    let ready_at = {
+        let session = SESSION.lock().unwrap_or_else(|e| e.into_inner());
+        session.listening_since.unwrap_or(session.spawned_at) + scan
+    };
+    time::sleep_until(ready_at.min(Instant::now() + scan)).await;
+}
+
+fn device(broadcaster: &Peripheral, props: PeripheralProperties) -> BleDevice {
+    let advertisement = Advertisement::from_properties(&props);
+    BleDevice {
+        rssi: props.rssi,
+        name: props.local_name.filter(|n| !n.is_empty()),
+        ..BleDevice::advertised(broadcaster.address(), advertisement)
+    }
+}
+
+fn publish(devices: &[BleDevice]) {
+    events::publish(Event::BleScan {
+        timestamp: unix_millis(),
+        beacons: devices.to_vec(),
+    });
+}
+
+/// Devices heard in the last [`BLE_SNAPSHOT_MAX_AGE_MS`]
+pub async fn fetch_ble_devices() -> Vec<BleDevice> {
+    spawn();
+    warm_up().await;
+
+    let devices = TRACKER
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .snapshot(unix_millis(), BLE_SNAPSHOT_MAX_AGE_MS);
+
     println!("[BLE] Total devices: {}", devices.len());
+    publish(&devices);
+    devices
+}
+
+/// Advertisements heard during the next `duration`, combined per device
+pub async fn fetch_ble_window(duration: Duration) -> Vec<BleDevice> {
+    spawn();
+    let from = unix_millis();
+    time::sleep(duration).await;
+
+    let tracker = TRACKER.lock().unwrap_or_else(|e| e.into_inner());
+    let seen = tracker
+        .snapshot(unix_millis(), duration.as_millis() as u64)
+        .len();
+    let devices = tracker.window(from, unix_millis());
+    drop(tracker);
+    println!(
+        "[BLE] Window of {:?}: {} devices, {} seen only once",
+        duration,
+        devices.len(),
+        seen.saturating_sub(devices.len())
+    );
+    publish(&devices);
     devices
 }
@@ -0,0 +1,326 @@
+//! Most recent scan results, shared by submissions and `/request`
+//!
+//! Only one scan per radio runs at a time. Callers that ask for a scan while
+//! one is in flight wait for it and reuse its results.
+//!
+//! A scan can also be a window of several passes, see [`super::window`], and
+//! Wi-Fi scans follow a [`ScanProfile`]. Cached results satisfy requests for
+//! fewer passes and fewer channels, not the other way round.
+
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::time::Instant;
+
+use super::{BleDevice, ScanProfile, WifiBssid, bluetooth, cell, wifi, window};
+use crate::config::{MAX_SCAN_WAITERS, SCAN_DURATION_SECS};
+use crate::error::{Error, Result};
+use crate::geosubmit::CellTower;
+use crate::server::metrics;
+use crate::server::status::{self, LastScan};
+use crate::time::unix_millis;
+
+static WIFI: Lazy<Slot<WifiBssid>> = Lazy::new(Slot::new);
+static BLE: Lazy<Slot<BleDevice>> = Lazy::new(Slot::new);
+static CELL: Lazy<Slot<CellTower>> = Lazy::new(Slot::new);
+static WAITERS: Semaphore = Semaphore::const_new(MAX_SCAN_WAITERS);
+
+/// Which radios to scan
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ScanKind {
+    Wifi,
+    Ble,
+    Cell,
+    #[default]
+    All,
+}
+
+impl ScanKind {
+    pub fn wifi(self) -> bool {
+        matches!(self, ScanKind::Wifi | ScanKind::All)
+    }
+
+    pub fn ble(self) -> bool {
+        matches!(self, ScanKind::Ble | ScanKind::All)
+    }
+
+    pub fn cell(self) -> bool {
+        matches!(self, ScanKind::Cell | ScanKind::All)
+    }
+}
+
+/// How to scan, beyond which radios
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanOptions {
+    pub passes: u32,          // see `scanner::window`
+    pub profile: ScanProfile, // Wi-Fi only
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            passes: 1,
+            profile: ScanProfile::default(),
+        }
+    }
+}
+
+impl ScanOptions {
+    /// Options from request parameters, checking the number of passes
+    pub fn new(passes: Option<u32>, profile: Option<ScanProfile>) -> Result<Self> {
+        Ok(ScanOptions {
+            passes: window::check_passes(passes.unwrap_or(1))?,
+            profile: profile.unwrap_or_default(),
+        })
+    }
+
+    /// The options for BLE, profiles don't apply to it
+    fn without_profile(self) -> Self {
+        ScanOptions {
+            profile: ScanProfile::default(),
+            ..self
+        }
+    }
+
+    /// Whether results scanned with these options will do for `wanted`
+    fn covers(&self, wanted: &ScanOptions) -> bool {
+        self.passes >= wanted.passes && self.profile.covers(wanted.profile)
+    }
+}
+
+/// Results of one completed scan
+#[derive(Debug, Clone)]
+pub struct Snapshot<T> {
+    pub timestamp: u128, // in milliseconds since Unix epoch
+    pub duration: Duration,
+    pub records: Vec<T>,
+    pub options: ScanOptions,
+    finished_at: Instant,
+}
+
+/// Wi-Fi, BLE and cell snapshots, present only for the radios that were asked for
+#[derive(Debug, Clone, Default)]
+pub struct Observations {
+    pub wifi: Option<Snapshot<WifiBssid>>,
+    pub ble: Option<Snapshot<BleDevice>>,
+    pub cell: Option<Snapshot<CellTower>>,
+}
+
+impl Observations {
+    /// Timestamp of the oldest snapshot, in milliseconds since Unix epoch
+    pub fn timestamp(&self) -> Option<u128> {
+        let wifi = self.wifi.as_ref().map(|s| s.timestamp);
+        let ble = self.ble.as_ref().map(|s| s.timestamp);
+        let cell = self.cell.as_ref().map(|s| s.timestamp);
+        wifi.into_iter().chain(ble).chain(cell).min()
+    }
+}
+
+/// Latest snapshot of one radio, and the lock that keeps its scans one at a time
+pub struct Slot<T> {
+    latest: Mutex<Option<Snapshot<T>>>,
+    scanning: tokio::sync::Mutex<()>,
+}
+
+impl<T: Clone> Default for Slot<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone> Slot<T> {
+    pub fn new() -> Self {
+        Slot {
+            latest: Mutex::new(None),
+            scanning: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Latest snapshot covering `options` that was no older than `max_age` at `at`
+    pub fn cached_at(
+        &self,
+        at: Instant,
+        max_age: Duration,
+        options: ScanOptions,
+    ) -> Option<Snapshot<T>> {
+        let latest = self.latest.lock().unwrap_or_else(|e| e.into_inner());
+        latest
+            .as_ref()
+            .filter(|s| s.finished_at + max_age >= at && s.options.covers(&options))
+            .cloned()
+    }
+
+    /// Return a snapshot no older than `max_age`, scanning if there is none,
+    /// and whether `scanner` ran for it.
+    /// A scan that was already in flight when we were called counts as fresh.
+    pub async fn scan<F, Fut>(
+        &self,
+        max_age: Duration,
+        options: ScanOptions,
+        scanner: F,
+    ) -> (Snapshot<T>, bool)
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Vec<T>>,
+    {
+        let requested_at = Instant::now();
+        if let Some(snapshot) = self.cached_at(requested_at, max_age, options) {
+            return (snapshot, false);
+        }
+
+        let _guard = self.scanning.lock().await;
+        if let Some(snapshot) = self.cached_at(requested_at, max_age, options) {
+            return (snapshot, false); // coalesced onto the scan we waited for
+        }
+
+        let started = Instant::now();
+        let records = scanner().await;
+        let snapshot = Snapshot {
+            timestamp: unix_millis(),
+            duration: started.elapsed(),
+            records,
+            options,
+            finished_at: Instant::now(),
+        };
+
+        *self.latest.lock().unwrap_or_else(|e| e.into_inner()) = Some(snapshot.clone());
+        (snapshot, true)
+    }
+}
+
+/// Cached observations no older than `max_age` that cover `options`, without scanning
+pub fn cached(kind: ScanKind, max_age: Duration, options: ScanOptions) -> Observations {
+    let now = Instant::now();
+    Observations {
+        wifi: kind
+            .wifi()
+            .then(|| WIFI.cached_at(now, max_age, options))
+            .flatten(),
+        ble: kind
+            .ble()
+            .then(|| BLE.cached_at(now, max_age, options.without_profile()))
+            .flatten(),
+        cell: kind
+            .cell()
+            .then(|| CELL.cached_at(now, max_age, ScanOptions::default()))
+            .flatten(),
+    }
+}
+
+/// Observations no older than `max_age`, scanning the radios whose cache is stale.
+/// Pass `Duration::ZERO` to require a scan that finishes after this call.
+pub async fn scan(kind: ScanKind, max_age: Duration) -> Observations {
+    scan_with(kind, max_age, ScanOptions::default()).await
+}
+
+/// Like [`scan`], but Wi-Fi and BLE results combine `options.passes` scans.
+/// Cell towers are always scanned once.
+pub async fn scan_with(kind: ScanKind, max_age: Duration, options: ScanOptions) -> Observations {
+    let ScanOptions { passes, profile } = options;
+    let radio = options.without_profile();
+    let (wifi, ble, cell) = tokio::join!(
+        // run simultaneously
+        async {
+            if kind.wifi() {
+                Some(
+                    WIFI.scan(max_age, options, || async move {
+                        if passes > 1 {
+                            wifi::fetch_wifi_window(passes, profile).await
+                        } else {
+                            wifi::fetch_wifi_stats(profile).await
+                        }
+                    })
+                    .await,
+                )
+            } else {
+                None
+            }
+        },
+        async {
+            if kind.ble() {
+                Some(
+                    BLE.scan(max_age, radio, || async move {
+                        if passes > 1 {
+                            let duration = Duration::from_secs(SCAN_DURATION_SECS) * passes;
+                            bluetooth::fetch_ble_window(duration).await
+                        } else {
+                            bluetooth::fetch_ble_devices().await
+                        }
+                    })
+                    .await,
+                )
+            } else {
+                None
+            }
+        },
+        async {
+            if kind.cell() {
+                Some(
+                    CELL.scan(max_age, ScanOptions::default(), cell::fetch_cell_towers)
+                        .await,
+                )
+            } else {
+                None
+            }
+        }
+    );
+
+    if let Some((snapshot, true)) = &wifi {
+        metrics::observe_scan("wifi", snapshot.duration, snapshot.records.len());
+        metrics::observe_bands(&snapshot.records);
+    }
+    if let Some((snapshot, true)) = &ble {
+        metrics::observe_scan("ble", snapshot.duration, snapshot.records.len());
+    }
+    if let Some((snapshot, true)) = &cell {
+        metrics::observe_scan("cell", snapshot.duration, snapshot.records.len());
+    }
+
+    let scanned = matches!(wifi, Some((_, true)))
+        || matches!(ble, Some((_, true)))
+        || matches!(cell, Some((_, true)));
+    let observations = Observations {
+        wifi: wifi.map(|(snapshot, _)| snapshot),
+        ble: ble.map(|(snapshot, _)| snapshot),
+        cell: cell.map(|(snapshot, _)| snapshot),
+    };
+
+    if scanned {
+        let wifi_duration = observations.wifi.as_ref().map(|s| s.duration);
+        let ble_duration = observations.ble.as_ref().map(|s| s.duration);
+        let cell_duration = observations.cell.as_ref().map(|s| s.duration);
+        tracing::debug!("WiFi scan duration: {:?}", wifi_duration);
+        tracing::debug!("BLE scan duration: {:?}", ble_duration);
+        tracing::debug!("Cell scan duration: {:?}", cell_duration);
+
+        status::record_scan(LastScan {
+            timestamp: observations.timestamp().unwrap_or_else(unix_millis),
+            duration_ms: wifi_duration
+                .max(ble_duration)
+                .max(cell_duration)
+                .unwrap_or_default()
+                .as_millis(),
+            access_points: observations.wifi.as_ref().map_or(0, |s| s.records.len()),
+            beacons: observations.ble.as_ref().map_or(0, |s| s.records.len()),
+            cells: observations.cell.as_ref().map_or(0, |s| s.records.len()),
+        });
+    }
+
+    observations
+}
+
+/// Like [`scan_with`], but refuses once too many callers are already waiting on scans
+pub async fn try_scan(
+    kind: ScanKind,
+    max_age: Duration,
+    options: ScanOptions,
+) -> Result<Observations> {
+    let _permit = WAITERS.try_acquire().map_err(|_| Error::RateLimited {
+        retry_after: Duration::from_secs(SCAN_DURATION_SECS),
+    })?;
+    Ok(scan_with(kind, max_age, options).await)
+}
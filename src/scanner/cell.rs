@@ -0,0 +1,305 @@
+//! Cell tower observations from local WWAN modems via ModemManager
+//!
+//! The serving cell comes from the Location interface (3GPP LAC/CI source)
+//! and its signal from the Signal interface. Neighbour cells come from
+//! `Modem.GetCellInfo` on ModemManager 1.20 and newer.
+
+use dbus::arg::{PropMap, RefArg, prop_cast};
+use dbus::blocking::stdintf::org_freedesktop_dbus::{ObjectManager, Properties};
+use dbus::blocking::{Connection, Proxy};
+use dbus::channel::Channel;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::error::{Error, Result};
+use crate::geosubmit::payload::{CellTower, RadioType};
+use crate::server::status::{self, AdapterState};
+
+pub const MM_SERVICE: &str = "org.freedesktop.ModemManager1";
+pub const MM_PATH: &str = "/org/freedesktop/ModemManager1";
+pub const MM_MODEM: &str = "org.freedesktop.ModemManager1.Modem";
+pub const MM_LOCATION: &str = "org.freedesktop.ModemManager1.Modem.Location";
+pub const MM_SIGNAL: &str = "org.freedesktop.ModemManager1.Modem.Signal";
+
+const DBUS_TIMEOUT: Duration = Duration::from_secs(5);
+const LOCATION_SOURCE_3GPP_LAC_CI: u32 = 1; // MM_MODEM_LOCATION_SOURCE_3GPP_LAC_CI
+const SIGNAL_REFRESH_SECS: u32 = 5;
+
+// MMModemAccessTechnology bits
+const ACCESS_GSM: u32 = 0b11110; // GSM, GSM compact, GPRS, EDGE
+const ACCESS_UMTS: u32 = 0b11111 << 5; // UMTS, HSDPA, HSUPA, HSPA, HSPA+
+const ACCESS_CDMA: u32 = 0b1111 << 10; // 1xRTT, EVDO rev 0/A/B
+const ACCESS_LTE: u32 = (1 << 14) | (1 << 16) | (1 << 17); // LTE, LTE Cat-M, NB-IoT
+const ACCESS_5GNR: u32 = 1 << 15;
+
+/// Read cells from every modem on the system bus
+pub async fn fetch_cell_towers() -> Vec<CellTower> {
+    let result = tokio::task::spawn_blocking(|| {
+        let conn = Connection::new_system().map_err(|e| Error::CellScan(e.to_string()))?;
+        read_cell_towers(&conn)
+    })
+    .await
+    .unwrap_or_else(|e| Err(Error::CellScan(e.to_string())));
+
+    match result {
+        Ok(cells) => {
+            status::record_cell_adapter(AdapterState::Available);
+            println!("[Cell] Total cells: {}", cells.len());
+            cells
+        }
+        Err(e) => {
+            tracing::debug!("[Cell] {}", e);
+            status::record_cell_adapter(AdapterState::Unavailable(e.to_string()));
+            Vec::new()
+        }
+    }
+}
+
+/// Read cells from a ModemManager reachable at a specific bus address
+pub fn read_cell_towers_at(bus_address: &str) -> Result<Vec<CellTower>> {
+    let mut channel =
+        Channel::open_private(bus_address).map_err(|e| Error::CellScan(e.to_string()))?;
+    channel
+        .register()
+        .map_err(|e| Error::CellScan(e.to_string()))?;
+    read_cell_towers(&Connection::from(channel))
+}
+
+/// Read serving and neighbour cells from every modem ModemManager knows about
+pub fn read_cell_towers(conn: &Connection) -> Result<Vec<CellTower>> {
+    let manager = conn.with_proxy(MM_SERVICE, MM_PATH, DBUS_TIMEOUT);
+    let objects = manager
+        .get_managed_objects()
+        .map_err(|e| Error::CellScan(format!("ModemManager unavailable: {}", e)))?;
+
+    let modems: Vec<_> = objects
+        .into_iter()
+        .filter(|(_, interfaces)| interfaces.contains_key(MM_MODEM))
+        .collect();
+    if modems.is_empty() {
+        return Err(Error::CellScan("no modems found".into()));
+    }
+
+    let mut cells = Vec::new();
+    for (path, interfaces) in modems {
+        let modem = conn.with_proxy(MM_SERVICE, path.clone(), DBUS_TIMEOUT);
+        match read_modem(&modem, &interfaces) {
+            Ok(found) => cells.extend(found),
+            Err(e) => println!("[Cell] Skipping modem {}: {}", path, e),
+        }
+    }
+    Ok(cells)
+}
+
+fn read_modem(
+    modem: &Proxy<&Connection>,
+    interfaces: &HashMap<String, PropMap>,
+) -> Result<Vec<CellTower>> {
+    let access = interfaces
+        .get(MM_MODEM)
+        .and_then(|props| prop_cast::<u32>(props, "AccessTechnologies"))
+        .copied()
+        .unwrap_or(0);
+
+    let serving = if interfaces.contains_key(MM_LOCATION) {
+        serving_cell(modem, access)?
+    } else {
+        None
+    };
+
+    let signal_dbm = if interfaces.contains_key(MM_SIGNAL) {
+        serving_signal(modem, serving.as_ref().and_then(|c| c.radioType))
+    } else {
+        None
+    };
+
+    let mut cells = neighbour_cells(modem).unwrap_or_default();
+
+    // Prefer the serving cell reported by GetCellInfo, but fill in what it lacks
+    match cells.iter_mut().find(|c| c.serving == Some(1)) {
+        Some(cell) => {
+            if cell.signalStrength.is_none() {
+                cell.signalStrength = signal_dbm;
+            }
+        }
+        None => {
+            if let Some(mut cell) = serving {
+                cell.signalStrength = signal_dbm;
+                cells.insert(0, cell);
+            }
+        }
+    }
+
+    Ok(cells)
+}
+
+/// Serving cell from the 3GPP LAC/CI location source
+fn serving_cell(modem: &Proxy<&Connection>, access: u32) -> Result<Option<CellTower>> {
+    let enabled: u32 = modem.get(MM_LOCATION, "Enabled").unwrap_or(0);
+    if enabled & LOCATION_SOURCE_3GPP_LAC_CI == 0 {
+        // best effort, needs permission to change the modem's location setup
+        let _: std::result::Result<(), _> = modem.method_call(
+            MM_LOCATION,
+            "Setup",
+            (enabled | LOCATION_SOURCE_3GPP_LAC_CI, false),
+        );
+    }
+
+    let (location,): (HashMap<u32, dbus::arg::Variant<Box<dyn RefArg>>>,) = modem
+        .method_call(MM_LOCATION, "GetLocation", ())
+        .map_err(|e| Error::CellScan(format!("GetLocation failed: {}", e)))?;
+
+    let Some(lac_ci) = location
+        .get(&LOCATION_SOURCE_3GPP_LAC_CI)
+        .and_then(|v| v.0.as_str())
+    else {
+        return Ok(None);
+    };
+
+    Ok(parse_3gpp_location(lac_ci, radio_from_access(access)))
+}
+
+/// Parse ModemManager's "MCC,MNC,LAC,CI,TAC" string (LAC, CI and TAC in hex)
+pub fn parse_3gpp_location(value: &str, radio: Option<RadioType>) -> Option<CellTower> {
+    let fields: Vec<&str> = value.split(',').map(str::trim).collect();
+    let [mcc, mnc, lac, ci, rest @ ..] = fields.as_slice() else {
+        return None;
+    };
+    let tac = rest.first().and_then(|t| u32::from_str_radix(t, 16).ok());
+    let lac = u32::from_str_radix(lac, 16).ok();
+
+    let area_code = match radio {
+        Some(RadioType::Lte | RadioType::Nr) => tac.filter(|&t| t != 0).or(lac),
+        _ => lac.filter(|&l| l != 0).or(tac),
+    }?;
+
+    Some(CellTower {
+        radioType: radio,
+        mobileCountryCode: mcc.parse().ok()?,
+        mobileNetworkCode: mnc.parse().ok()?,
+        locationAreaCode: area_code,
+        cellId: u64::from_str_radix(ci, 16).ok()?,
+        age: None,
+        asu: None,
+        psc: None,
+        serving: Some(1),
+        signalStrength: None,
+        timingAdvance: None,
+        arfcn: None,
+    })
+}
+
+/// Signal strength in dBm of the serving radio, from the Signal interface
+fn serving_signal(modem: &Proxy<&Connection>, radio: Option<RadioType>) -> Option<i32> {
+    let rate: u32 = modem.get(MM_SIGNAL, "Rate").unwrap_or(0);
+    if rate == 0 {
+        let _: std::result::Result<(), _> =
+            modem.method_call(MM_SIGNAL, "Setup", (SIGNAL_REFRESH_SECS,));
+    }
+
+    let (property, keys): (&str, &[&str]) = match radio? {
+        RadioType::Nr => ("Nr5g", &["rsrp"]),
+        RadioType::Lte => ("Lte", &["rsrp", "rssi"]),
+        RadioType::Wcdma => ("Umts", &["rscp", "rssi"]),
+        RadioType::Gsm => ("Gsm", &["rssi"]),
+        RadioType::Cdma => ("Cdma", &["rssi"]),
+    };
+
+    let values: PropMap = modem.get(MM_SIGNAL, property).ok()?;
+    keys.iter()
+        .find_map(|key| prop_cast::<f64>(&values, key))
+        .filter(|dbm| dbm.is_finite() && **dbm < 0.0) // MM reports unknown values as -inf or 0
+        .map(|dbm| dbm.round() as i32)
+}
+
+/// Serving and neighbour cells from `Modem.GetCellInfo`
+fn neighbour_cells(modem: &Proxy<&Connection>) -> Result<Vec<CellTower>> {
+    let (infos,): (Vec<PropMap>,) = modem
+        .method_call(MM_MODEM, "GetCellInfo", ())
+        .map_err(|e| Error::CellScan(format!("GetCellInfo failed: {}", e)))?;
+
+    Ok(infos.iter().filter_map(cell_from_info).collect())
+}
+
+fn cell_from_info(info: &PropMap) -> Option<CellTower> {
+    let radio = match prop_cast::<u32>(info, "cell-type")? {
+        1 => RadioType::Cdma,
+        2 => RadioType::Gsm,
+        3 => RadioType::Wcdma,
+        5 => RadioType::Lte,
+        6 => RadioType::Nr,
+        _ => return None, // unknown and TD-SCDMA cells can't be submitted
+    };
+
+    let text = |key: &str| prop_cast::<String>(info, key).map(String::as_str);
+    let hex = |key: &str| text(key).and_then(|v| u64::from_str_radix(v, 16).ok());
+    let dbm = |key: &str| {
+        prop_cast::<f64>(info, key)
+            .filter(|v| v.is_finite() && **v < 0.0)
+            .map(|v| v.round() as i32)
+    };
+
+    // operator-id is MCC followed by a 2 or 3 digit MNC
+    let operator = text("operator-id")?;
+    if operator.len() < 5 {
+        return None;
+    }
+    let (mcc, mnc) = operator.split_at(3);
+
+    let area_code = match radio {
+        RadioType::Lte | RadioType::Nr => hex("tac"),
+        _ => hex("lac"),
+    }?;
+
+    let (psc, arfcn, signal) = match radio {
+        RadioType::Gsm => (
+            hex("base-station-id"),
+            prop_cast::<u32>(info, "arfcn"),
+            dbm("rssi"),
+        ),
+        RadioType::Wcdma => (hex("psc"), prop_cast::<u32>(info, "uarfcn"), dbm("rscp")),
+        RadioType::Lte => (
+            hex("physical-ci"),
+            prop_cast::<u32>(info, "earfcn"),
+            dbm("rsrp"),
+        ),
+        RadioType::Nr => (
+            hex("physical-ci"),
+            prop_cast::<u32>(info, "nrarfcn"),
+            dbm("rsrp"),
+        ),
+        RadioType::Cdma => (None, None, None),
+    };
+
+    Some(CellTower {
+        radioType: Some(radio),
+        mobileCountryCode: mcc.parse().ok()?,
+        mobileNetworkCode: mnc.parse().ok()?,
+        locationAreaCode: area_code as u32,
+        cellId: hex("ci")?,
+        age: None,
+        asu: None,
+        psc: psc.map(|v| v as u16),
+        serving: Some(*prop_cast::<bool>(info, "serving").unwrap_or(&false) as u8),
+        signalStrength: signal,
+        timingAdvance: prop_cast::<u32>(info, "timing-advance").copied(),
+        arfcn: arfcn.copied(),
+    })
+}
+
+/// Radio type of the serving cell; NSA 5G reports LTE as the anchor cell
+fn radio_from_access(access: u32) -> Option<RadioType> {
+    if access & ACCESS_LTE != 0 {
+        Some(RadioType::Lte)
+    } else if access & ACCESS_5GNR != 0 {
+        Some(RadioType::Nr)
+    } else if access & ACCESS_UMTS != 0 {
+        Some(RadioType::Wcdma)
+    } else if access & ACCESS_GSM != 0 {
+        Some(RadioType::Gsm)
+    } else if access & ACCESS_CDMA != 0 {
+        Some(RadioType::Cdma)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,318 @@
+//! 802.11 information elements from beacons and probe responses
+//!
+//! Works on the raw elements, as handed over by nl80211 or printed in hex by
+//! iw for the elements it can't decode itself, and reduces them to what is
+//! worth keeping about an access point.
+
+use serde::{Deserialize, Serialize};
+
+use super::band::Band;
+use super::wifi::PhyType;
+
+// element ids
+const DSSS_PARAMETERS: u8 = 3;
+const COUNTRY: u8 = 7;
+const HT_CAPABILITIES: u8 = 45;
+const RSN: u8 = 48;
+const HT_OPERATION: u8 = 61;
+const VHT_CAPABILITIES: u8 = 191;
+const VHT_OPERATION: u8 = 192;
+const VENDOR_SPECIFIC: u8 = 221;
+const EXTENSION: u8 = 255;
+
+// element id extensions
+const HE_CAPABILITIES: u8 = 35;
+const HE_OPERATION: u8 = 36;
+const MU_EDCA: u8 = 38;
+const SPATIAL_REUSE: u8 = 39;
+const EHT_OPERATION: u8 = 106;
+const MULTI_LINK: u8 = 107;
+const EHT_CAPABILITIES: u8 = 108;
+
+const WFA_OUI: [u8; 3] = [0x00, 0x0f, 0xac]; // RSN suites
+const MICROSOFT_OUI: [u8; 3] = [0x00, 0x50, 0xf2]; // WPA before RSN
+
+/// One element, with `extension` set for elements behind id 255
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Element<'a> {
+    pub id: u8,
+    pub extension: Option<u8>,
+    pub data: &'a [u8],
+}
+
+/// Walk the elements in `ies`, stopping at the first truncated one
+pub fn elements(ies: &[u8]) -> impl Iterator<Item = Element<'_>> {
+    let mut rest = ies;
+    std::iter::from_fn(move || {
+        let [id, length, tail @ ..] = rest else {
+            return None;
+        };
+        let data = tail.get(..*length as usize)?;
+        rest = &tail[*length as usize..];
+        Some(match (*id, data) {
+            (EXTENSION, [extension, data @ ..]) => Element {
+                id: *id,
+                extension: Some(*extension),
+                data,
+            },
+            _ => Element {
+                id: *id,
+                extension: None,
+                data,
+            },
+        })
+    })
+}
+
+/// Bytes from hex as printed by iw, `dd 18 00 50 f2`, with or without separators
+pub fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    let digits: String = text
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != ':')
+        .collect();
+    hex::decode(digits).ok()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Security {
+    Wep,
+    Wpa, // the pre-RSN vendor element
+    Psk, // WPA2-Personal
+    Eap, // Enterprise
+    Sae, // WPA3-Personal
+    Owe, // Enhanced Open
+}
+
+/// Wi-Fi 6 and 7 features an access point advertises
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub enum Feature {
+    TwtResponder,
+    BssColor,
+    SpatialReuse,
+    MuEdca,
+    MultiLink,
+    Puncturing,
+}
+
+/// What the elements say about an access point, beyond its PHY
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BssInfo {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u16>, // in MHz, 80+80 counts as 160
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub center_frequencies: Vec<u32>, // in MHz, two for 80+80
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub security: Vec<Security>, // empty for open networks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<Feature>,
+}
+
+/// Everything decoded from one set of elements
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Decoded {
+    pub phy: Option<PhyType>, // newest generation advertised
+    pub channel: Option<u8>,  // primary
+    pub info: BssInfo,
+}
+
+impl Decoded {
+    /// Decode `ies` of an access point on `frequency` MHz
+    pub fn decode(ies: &[u8], frequency: u32) -> Self {
+        let mut decoded = Decoded::default();
+        for element in elements(ies) {
+            decoded.add(element, frequency);
+        }
+        decoded.info.security.sort();
+        decoded.info.security.dedup();
+        decoded.info.features.sort();
+        decoded.info.features.dedup();
+        decoded
+    }
+
+    /// Account for the privacy bit of the capability field, which without
+    /// WPA or RSN elements means WEP
+    pub fn privacy(&mut self, privacy: bool) {
+        if privacy && self.info.security.is_empty() {
+            self.info.security.push(Security::Wep);
+        }
+    }
+
+    pub(crate) fn phy(&mut self, phy: PhyType) {
+        if self
+            .phy
+            .as_ref()
+            .is_none_or(|p| phy.generation() > p.generation())
+        {
+            self.phy = Some(phy);
+        }
+    }
+
+    /// Keep the widest channel any operation element describes
+    fn width(&mut self, width: u16, centers: &[u8], frequency: u32) {
+        if self.info.width.is_some_and(|w| w > width) {
+            return;
+        }
+        // center channels are numbered like the primary one
+        let Some(band) = Band::of(frequency) else {
+            return;
+        };
+        self.info.width = Some(width);
+        self.info.center_frequencies = centers.iter().filter_map(|&c| band.frequency(c)).collect();
+    }
+
+    /// VHT operation information, also carried by HE operation elements
+    fn vht_operation(&mut self, data: &[u8], frequency: u32) {
+        let &[width, first, second, ..] = data else {
+            return;
+        };
+        match (width, second) {
+            (1, 0) => self.width(80, &[first], frequency),
+            (1, _) if first.abs_diff(second) == 8 => self.width(160, &[second], frequency),
+            (1, _) => self.width(160, &[first, second], frequency),
+            (2, _) => self.width(160, &[first], frequency), // deprecated encodings
+            (3, _) => self.width(160, &[first, second], frequency),
+            _ => {}
+        }
+    }
+
+    fn add(&mut self, element: Element, frequency: u32) {
+        let data = element.data;
+        match (element.id, element.extension) {
+            (DSSS_PARAMETERS, _) => self.channel = self.channel.or(data.first().copied()),
+            (COUNTRY, _) => {
+                if let Some(code) = data.get(..2)
+                    && code.iter().all(u8::is_ascii_alphabetic)
+                {
+                    self.info.country = Some(String::from_utf8_lossy(code).to_uppercase());
+                }
+            }
+            (HT_CAPABILITIES, _) => self.phy(PhyType::Ht),
+            (HT_OPERATION, _) => {
+                let &[primary, parameters, ..] = data else {
+                    return;
+                };
+                self.channel = Some(primary);
+                let any_width = parameters & 0x04 != 0;
+                match parameters & 0x03 {
+                    1 if any_width => {
+                        // no secondary channel above 253, leave the width unknown
+                        if let Some(secondary) = primary.checked_add(2) {
+                            self.width(40, &[secondary], frequency)
+                        }
+                    }
+                    3 if any_width => self.width(40, &[primary.saturating_sub(2)], frequency),
+                    _ => self.width(20, &[primary], frequency),
+                }
+            }
+            (VHT_CAPABILITIES, _) => self.phy(PhyType::Vht),
+            (VHT_OPERATION, _) => self.vht_operation(data, frequency),
+            (RSN, _) => self.rsn(data),
+            (VENDOR_SPECIFIC, _) if data.starts_with(&MICROSOFT_OUI) && data.get(3) == Some(&1) => {
+                self.info.security.push(Security::Wpa)
+            }
+            (EXTENSION, Some(HE_CAPABILITIES)) => {
+                self.phy(PhyType::He);
+                if data.first().is_some_and(|mac| mac & 0x04 != 0) {
+                    self.info.features.push(Feature::TwtResponder);
+                }
+            }
+            (EXTENSION, Some(HE_OPERATION)) => self.he_operation(data, frequency),
+            (EXTENSION, Some(MU_EDCA)) => self.info.features.push(Feature::MuEdca),
+            (EXTENSION, Some(SPATIAL_REUSE)) => self.info.features.push(Feature::SpatialReuse),
+            (EXTENSION, Some(EHT_CAPABILITIES)) => self.phy(PhyType::Eht),
+            (EXTENSION, Some(EHT_OPERATION)) => self.eht_operation(data, frequency),
+            (EXTENSION, Some(MULTI_LINK)) => self.info.features.push(Feature::MultiLink),
+            _ => {}
+        }
+    }
+
+    fn rsn(&mut self, data: &[u8]) {
+        // version, group cipher, then counted lists of pairwise ciphers and AKMs
+        let Some(&[low, high]) = data.get(6..8) else {
+            return;
+        };
+        let akms = 8 + 4 * u16::from_le_bytes([low, high]) as usize;
+        let Some(&[low, high]) = data.get(akms..akms + 2) else {
+            return;
+        };
+        let count = u16::from_le_bytes([low, high]) as usize;
+        let Some(suites) = data.get(akms + 2..akms + 2 + 4 * count) else {
+            return;
+        };
+        for suite in suites.chunks_exact(4) {
+            if suite[..3] != WFA_OUI {
+                continue;
+            }
+            let security = match suite[3] {
+                1 | 3 | 5 | 11 | 12 | 13 => Security::Eap,
+                2 | 4 | 6 => Security::Psk,
+                8 | 9 | 24 | 25 => Security::Sae,
+                18 => Security::Owe,
+                _ => continue,
+            };
+            self.info.security.push(security);
+        }
+    }
+
+    fn he_operation(&mut self, data: &[u8], frequency: u32) {
+        let Some(&[low, middle, high, color]) = data.get(..4) else {
+            return;
+        };
+        let parameters = u32::from_le_bytes([low, middle, high, 0]);
+        if color & 0x80 == 0 {
+            self.info.features.push(Feature::BssColor);
+        }
+
+        let mut offset = 6; // past the basic HE-MCS set
+        if parameters & (1 << 14) != 0 {
+            if let Some(vht) = data.get(offset..offset + 3) {
+                self.vht_operation(vht, frequency);
+            }
+            offset += 3;
+        }
+        if parameters & (1 << 15) != 0 {
+            offset += 1; // co-hosted BSS
+        }
+        if parameters & (1 << 17) != 0
+            && let Some(&[primary, control, first, second, ..]) = data.get(offset..offset + 5)
+        {
+            self.channel = Some(primary);
+            match control & 0x03 {
+                0 => self.width(20, &[first], frequency),
+                1 => self.width(40, &[first], frequency),
+                2 => self.width(80, &[first], frequency),
+                _ if second == 0 => self.width(160, &[first], frequency),
+                _ if first.abs_diff(second) == 8 => self.width(160, &[second], frequency),
+                _ => self.width(160, &[first, second], frequency),
+            }
+        }
+    }
+
+    fn eht_operation(&mut self, data: &[u8], frequency: u32) {
+        let Some(&parameters) = data.first() else {
+            return;
+        };
+        if parameters & 0x01 == 0 {
+            return; // no operation information
+        }
+        let Some(&[control, first, second]) = data.get(5..8) else {
+            return;
+        };
+        match control & 0x07 {
+            0 => self.width(20, &[first], frequency),
+            1 => self.width(40, &[first], frequency),
+            2 => self.width(80, &[first], frequency),
+            3 => self.width(160, &[second], frequency),
+            4 => self.width(320, &[second], frequency),
+            _ => {}
+        }
+        if parameters & 0x02 != 0 && data.get(8..10).is_some_and(|b| b != [0, 0]) {
+            self.info.features.push(Feature::Puncturing);
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//! Vendors of access points and beacons, from the IEEE MA-L registry
+//!
+//! An excerpt of the registry is built in. The full `oui.csv` published by
+//! the IEEE can be installed with `serviceberry update-oui <file>`, which
+//! copies it into the config directory where it takes precedence.
+
+use btleplug::api::BDAddr;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use super::address::AddressType;
+use crate::config::{OUI_FILE, config_dir};
+use crate::error::{Error, Result};
+
+const BUNDLED: &str = include_str!("../../data/oui.csv");
+
+static DATABASE: Lazy<Mutex<Arc<OuiDatabase>>> = Lazy::new(|| Mutex::new(Arc::new(load())));
+
+/// Organizations by their 24-bit MA-L assignment
+#[derive(Debug, Clone, Default)]
+pub struct OuiDatabase {
+    vendors: HashMap<u32, String>,
+}
+
+/// Split a CSV line, honoring double quotes
+fn fields(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+impl OuiDatabase {
+    /// Parse the IEEE CSV export: `Registry,Assignment,Organization Name,...`
+    pub fn parse(csv: &str) -> Result<Self> {
+        let mut vendors = HashMap::new();
+        for line in csv.lines().skip(1) {
+            let fields = fields(line);
+            let [registry, assignment, name, ..] = fields.as_slice() else {
+                continue;
+            };
+            // MA-M and MA-S blocks share their first 24 bits with others
+            if registry != "MA-L" || assignment.len() != 6 {
+                continue;
+            }
+            if let Ok(prefix) = u32::from_str_radix(assignment, 16) {
+                vendors.insert(prefix, name.trim().to_string());
+            }
+        }
+        if vendors.is_empty() {
+            return Err(Error::Config("no MA-L assignments in OUI file".into()));
+        }
+        Ok(OuiDatabase { vendors })
+    }
+
+    /// The excerpt compiled into the binary
+    pub fn bundled() -> Self {
+        Self::parse(BUNDLED).expect("bundled OUI data is valid")
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn len(&self) -> usize {
+        self.vendors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vendors.is_empty()
+    }
+
+    pub fn lookup(&self, address: &BDAddr) -> Option<&str> {
+        let [a, b, c, ..] = address.into_inner();
+        let prefix = u32::from_be_bytes([0, a, b, c]);
+        self.vendors.get(&prefix).map(String::as_str)
+    }
+}
+
+fn load() -> OuiDatabase {
+    let path = config_dir().join(OUI_FILE);
+    if path.exists() {
+        match OuiDatabase::from_file(&path) {
+            Ok(database) => return database,
+            Err(e) => tracing::warn!("Ignoring {}: {}", path.display(), e),
+        }
+    }
+    OuiDatabase::bundled()
+}
+
+/// The installed database, or the bundled one
+pub fn database() -> Arc<OuiDatabase> {
+    DATABASE.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Check the CSV at `path` and install it in the config directory.
+/// Returns how many assignments it holds.
+pub fn update_from_file(path: impl AsRef<Path>) -> Result<usize> {
+    let database = OuiDatabase::from_file(path.as_ref())?;
+    let installed = config_dir().join(OUI_FILE);
+    let temporary = installed.with_extension("tmp");
+    fs::copy(path, &temporary)?;
+    fs::rename(temporary, installed)?;
+
+    let count = database.len();
+    *DATABASE.lock().unwrap_or_else(|e| e.into_inner()) = Arc::new(database);
+    Ok(count)
+}
+
+/// What the address itself says about a device
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct MacInfo {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vendor: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub locally_administered: bool, // randomized or assigned by software, no vendor
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub multicast: bool,
+}
+
+impl MacInfo {
+    pub fn with(database: &OuiDatabase, address: &BDAddr) -> Self {
+        let info = Self::bits(address);
+        MacInfo {
+            // the first three bytes are not an OUI then
+            vendor: (!info.locally_administered)
+                .then(|| database.lookup(address).map(str::to_string))
+                .flatten(),
+            ..info
+        }
+    }
+
+    /// Like [`MacInfo::with`] for a Bluetooth address of type `kind`.
+    /// Random addresses never carry an OUI, whatever their first bits say.
+    pub fn with_type(database: &OuiDatabase, address: &BDAddr, kind: Option<AddressType>) -> Self {
+        match kind {
+            None | Some(AddressType::Public) => Self::with(database, address),
+            Some(_) => Self::bits(address),
+        }
+    }
+
+    /// Look up `address` in the installed database
+    pub fn of(address: &BDAddr) -> Self {
+        Self::with(&database(), address)
+    }
+
+    /// Look up a Bluetooth `address` of type `kind` in the installed database
+    pub fn of_type(address: &BDAddr, kind: Option<AddressType>) -> Self {
+        Self::with_type(&database(), address, kind)
+    }
+
+    fn bits(address: &BDAddr) -> Self {
+        let first = address.into_inner()[0];
+        MacInfo {
+            vendor: None,
+            locally_administered: first & 0x02 != 0,
+            multicast: first & 0x01 != 0,
+        }
+    }
+}
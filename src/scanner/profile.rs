@@ -0,0 +1,177 @@
+//! Named Wi-Fi scan profiles
+//!
+//! A profile decides which channels `iw scan trigger` visits, how long it
+//! stays on each, and whether it may send probe requests. Passive scans only
+//! listen for beacons, so nearby access points never learn we were there.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use super::band::Band;
+use crate::error::{Error, Result};
+
+/// 2.4 GHz channels 1, 6 and 11, and the 5 GHz channels outside DFS
+const COMMON_CHANNELS: [(Band, u8); 11] = [
+    (Band::TwoPointFour, 1),
+    (Band::TwoPointFour, 6),
+    (Band::TwoPointFour, 11),
+    (Band::Five, 36),
+    (Band::Five, 40),
+    (Band::Five, 44),
+    (Band::Five, 48),
+    (Band::Five, 149),
+    (Band::Five, 153),
+    (Band::Five, 157),
+    (Band::Five, 161),
+];
+
+/// Every 20 MHz channel, 6 GHz included. The kernel rejects the whole scan
+/// if the card doesn't know one of them, so this needs a 6 GHz card.
+fn twenty_mhz_channels() -> Vec<u32> {
+    let channels = (1..=13)
+        .map(|ch| (Band::TwoPointFour, ch))
+        .chain((36..=64).step_by(4).map(|ch| (Band::Five, ch)))
+        .chain((100..=144).step_by(4).map(|ch| (Band::Five, ch)))
+        .chain((149..=165).step_by(4).map(|ch| (Band::Five, ch)))
+        .chain((1..=233).step_by(4).map(|ch| (Band::Six, ch)));
+    channels
+        .filter_map(|(band, channel)| band.frequency(channel))
+        .collect()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ScanProfile {
+    Fast, // common channels only
+    #[default]
+    Full, // every channel the driver scans by default
+    Passive, // every channel, without probe requests
+    #[serde(rename = "6ghz")]
+    SixGhz, // every 2.4, 5 and 6 GHz channel, asked for explicitly
+}
+
+/// What a profile asks of `iw scan trigger`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanSettings {
+    pub frequencies: Vec<u32>, // in MHz, empty for the driver's default
+    pub dwell_tu: Option<u16>, // time on each channel in 1024 µs units, `None` for the driver's default
+    pub passive: bool,
+    pub flush: bool, // drop cached entries before scanning
+}
+
+impl ScanProfile {
+    pub const ALL: [ScanProfile; 4] = [
+        ScanProfile::Fast,
+        ScanProfile::Full,
+        ScanProfile::Passive,
+        ScanProfile::SixGhz,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ScanProfile::Fast => "fast",
+            ScanProfile::Full => "full",
+            ScanProfile::Passive => "passive",
+            ScanProfile::SixGhz => "6ghz",
+        }
+    }
+
+    pub fn settings(self) -> ScanSettings {
+        match self {
+            ScanProfile::Fast => ScanSettings {
+                frequencies: COMMON_CHANNELS
+                    .iter()
+                    .filter_map(|&(band, channel)| band.frequency(channel))
+                    .collect(),
+                dwell_tu: Some(40),
+                passive: false,
+                flush: true,
+            },
+            ScanProfile::Full => ScanSettings {
+                frequencies: Vec::new(),
+                dwell_tu: None,
+                passive: false,
+                flush: true,
+            },
+            ScanProfile::Passive => ScanSettings {
+                frequencies: Vec::new(),
+                dwell_tu: Some(110), // a little over the usual 100 TU beacon interval
+                passive: true,
+                flush: true,
+            },
+            ScanProfile::SixGhz => ScanSettings {
+                frequencies: twenty_mhz_channels(),
+                dwell_tu: None,
+                passive: false,
+                flush: true,
+            },
+        }
+    }
+
+    /// Whether a scan with this profile saw every channel `other` would have
+    pub fn covers(self, other: ScanProfile) -> bool {
+        self.coverage() >= other.coverage()
+    }
+
+    fn coverage(self) -> u8 {
+        match self {
+            ScanProfile::Fast => 0,
+            ScanProfile::Full | ScanProfile::Passive => 1,
+            ScanProfile::SixGhz => 2,
+        }
+    }
+}
+
+impl ScanSettings {
+    /// The same scan at the driver's default dwell time. Drivers without
+    /// `NL80211_EXT_FEATURE_SET_SCAN_DWELL`, brcmfmac among them, reject
+    /// scans that set one.
+    pub fn without_dwell(&self) -> Option<ScanSettings> {
+        self.dwell_tu.map(|_| ScanSettings {
+            dwell_tu: None,
+            ..self.clone()
+        })
+    }
+
+    /// Arguments following `iw dev <interface> scan trigger`
+    pub fn trigger_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if !self.frequencies.is_empty() {
+            args.push("freq".to_string());
+            args.extend(self.frequencies.iter().map(u32::to_string));
+        }
+        if let Some(dwell) = self.dwell_tu {
+            args.extend(["duration".to_string(), dwell.to_string()]);
+        }
+        if self.flush {
+            args.push("flush".to_string());
+        }
+        if self.passive {
+            args.push("passive".to_string());
+        }
+        args
+    }
+}
+
+impl fmt::Display for ScanProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ScanProfile {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        ScanProfile::ALL
+            .into_iter()
+            .find(|profile| profile.name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                Error::InvalidScan(format!(
+                    "unknown scan profile {}, expected fast, full, passive or 6ghz",
+                    name
+                ))
+            })
+    }
+}
@@ -1,12 +1,21 @@
-use core::panic;
+use std::process::Stdio;
 use std::time::Duration;
 
 use btleplug::api::BDAddr as mac_address;
 use once_cell::sync::Lazy;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, BufReader};
 
-use crate::config::SCAN_DURATION_SECS;
+use super::band::{self, Band};
+use super::ie::{self, BssInfo, Decoded};
+use super::oui::MacInfo;
+use super::profile::{ScanProfile, ScanSettings};
+use super::window::{Samples, Window};
+use crate::config::{WIFI_MAX_AGE_MS, WIFI_SCAN_TIMEOUT_SECS};
+use crate::server::events::{self, Event};
+use crate::server::status::{self, AdapterState};
+use crate::time::unix_millis;
 
 // oh my gosh I wrote all this code before discovering:
 // "Do NOT screenscrape this tool, we don't consider its output stable."
@@ -21,23 +30,51 @@ pub struct WifiBssid {
     pub age: Option<u64>, // in milliseconds since last seen
     #[serde(skip_serializing_if = "Option::is_none")]
     pub channel: Option<u8>,
-    pub frequency: u16, // in MHz
+    pub frequency: u32, // in MHz
     #[serde(rename = "radioType")]
     pub phy: PhyType, // physcial layer type, usually correlated with wifi versioning
     #[serde(rename = "signalStrength")]
     pub rssi: i32, // Signal Strength, in dBm
+    #[serde(flatten)]
+    pub mac_info: MacInfo,
+    #[serde(flatten)]
+    pub info: BssInfo, // from the information elements
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub samples: Option<Samples>, // set when combined over a scan window
 }
 
-#[derive(Serialize, Debug, Clone, Deserialize)]
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
 pub enum PhyType {
+    #[serde(rename = "802.11bn")]
     Uhr,
+    #[serde(rename = "802.11be")]
     Eht,
+    #[serde(rename = "802.11ax")]
     He,
+    #[serde(rename = "802.11ac")]
     Vht,
+    #[serde(rename = "802.11n")]
     Ht,
+    #[serde(rename = "legacy")]
     Legacy, // anything not matching above
 }
 
+impl PhyType {
+    /// Wi-Fi generation, 0 for legacy
+    pub fn generation(&self) -> u8 {
+        match self {
+            PhyType::Uhr => 8,
+            PhyType::Eht => 7,
+            PhyType::He => 6,
+            PhyType::Vht => 5,
+            PhyType::Ht => 4,
+            PhyType::Legacy => 0,
+        }
+    }
+}
+
+pub const INTERFACE: &str = "wlan0"; // scanned with iw
+
 // Hidden SSIDs: empty, spaces, or only \xNN escapes
 static RE_HIDDEN: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(?:\\x[0-9A-Fa-f]{2}| )*$").unwrap());
 
@@ -50,93 +87,377 @@ static RE_ESCAPE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\\x[0-9A-Fa-f]{2}").un
 // Valid UTF-8: at least one printable, no escapes
 static RE_VALID_UTF8: Lazy<Regex> = Lazy::new(|| Regex::new(r"[^\x00-\x1F\x7F]").unwrap());
 
-impl WifiBssid {
-    fn parse_ssid(&mut self, raw_ssid: &str) {
-        let ssid = raw_ssid.trim();
+/// The SSID as uploaded, `None` when it is hidden or nothing printable is left.
+/// `iw` escapes bytes it can't print as `\xNN`, those are dropped.
+fn clean_ssid(raw_ssid: &str) -> Option<String> {
+    let ssid = raw_ssid.trim_matches(|c: char| c == '\0' || c.is_whitespace());
 
-        // hidden SSID
-        if RE_HIDDEN.is_match(ssid) {
-            println!("[WiFi] Skipping hidden SSID");
-            self.ssid = None;
+    // hidden SSID
+    if RE_HIDDEN.is_match(ssid) {
+        println!("[WiFi] Skipping hidden SSID");
+        return None;
+    }
+
+    // fully invalid, pure escapes
+    if RE_INVALID.is_match(ssid) {
+        println!("[WiFi] Skipping invalid SSID: {}", ssid);
+        return None;
+    }
+
+    // Contains escapes > partial invalid > clean it
+    if RE_ESCAPE.is_match(ssid) {
+        let cleaned = RE_ESCAPE.replace_all(ssid, "").trim().to_string();
+        if cleaned.is_empty() {
+            tracing::warn!("SSID became empty after cleaning: {}", ssid);
+            return None;
         }
+        return Some(cleaned);
+    }
+
+    // no escapes,must be valid UTF-8
+    if RE_VALID_UTF8.is_match(ssid) {
+        return Some(ssid.to_string());
+    }
+
+    tracing::warn!("SSID did not match any known patterns: {:?}", ssid);
+    None
+}
 
-        // fully invalid, pure escapes
-        if RE_INVALID.is_match(ssid) {
-            println!("[WiFi] Skipping invalid SSID: {}", ssid);
-            self.ssid = None;
+impl WifiBssid {
+    /// A record with only the address and frequency known yet
+    pub fn new(bssid: mac_address, frequency: u32) -> Self {
+        WifiBssid {
+            ssid: None,
+            bssid,
+            age: None,
+            channel: None,
+            frequency,
+            phy: PhyType::Legacy,
+            rssi: 0,
+            mac_info: MacInfo::of(&bssid),
+            info: BssInfo::default(),
+            samples: None,
         }
+    }
+
+    /// Band of the frequency, `None` when it is unknown or in no Wi-Fi band
+    pub fn band(&self) -> Option<Band> {
+        Band::of(self.frequency)
+    }
+
+    /// Take the PHY, channel and the rest from decoded information elements.
+    /// The channel follows from the frequency, elements only fill in when it doesn't.
+    fn apply_elements(&mut self, decoded: Decoded) {
+        self.phy = decoded.phy.unwrap_or(PhyType::Legacy);
+        self.channel = match (band::channel(self.frequency), decoded.channel) {
+            (Some((band, channel)), Some(advertised)) if advertised != channel => {
+                tracing::debug!(
+                    "{} advertises channel {} on {} channel {}",
+                    self.bssid,
+                    advertised,
+                    band,
+                    channel
+                );
+                Some(channel)
+            }
+            (Some((_, channel)), _) => Some(channel),
+            (None, advertised) => advertised,
+        };
+        self.info = decoded.info;
+    }
 
-        // Contains escapes > partial invalid > clean it
-        if RE_ESCAPE.is_match(ssid) {
-            let cleaned = RE_ESCAPE.replace_all(ssid, "").trim().to_string();
-            if cleaned.is_empty() {
-                panic!("[WiFi] Error: SSID became empty after cleaning: {}", ssid);
+    /// Build a record from the attributes nested in `NL80211_ATTR_BSS` of a scan dump
+    pub fn from_nl80211(attributes: &[u8]) -> Option<Self> {
+        let mut address = None;
+        let mut frequency = 0;
+        let mut capability = 0;
+        let mut rssi = 0;
+        let mut age = None;
+        let (mut ies, mut beacon_ies) = (None, None);
+        for (kind, value) in netlink_attributes(attributes) {
+            match kind {
+                NL80211_BSS_BSSID => address = <[u8; 6]>::try_from(value).ok(),
+                NL80211_BSS_FREQUENCY => frequency = netlink_u32(value)?,
+                NL80211_BSS_CAPABILITY => capability = netlink_u32(value)?,
+                NL80211_BSS_INFORMATION_ELEMENTS => ies = Some(value),
+                NL80211_BSS_SIGNAL_MBM => rssi = netlink_u32(value)? as i32 / 100,
+                NL80211_BSS_SEEN_MS_AGO => age = Some(netlink_u32(value)? as u64),
+                NL80211_BSS_BEACON_IES => beacon_ies = Some(value),
+                _ => {}
             }
-            self.ssid = Some(cleaned);
         }
 
-        // no escapes,must be valid UTF-8
-        if RE_VALID_UTF8.is_match(ssid) {
-            self.ssid = Some(ssid.to_string());
+        // probe responses carry more than beacons
+        let ies = ies.or(beacon_ies).unwrap_or_default();
+        let address = mac_address::from(address?);
+        let mut record = WifiBssid {
+            age,
+            rssi,
+            ..WifiBssid::new(address, frequency)
+        };
+        if let Some(ssid) = ie::elements(ies).find(|e| e.id == 0)
+            && let Ok(ssid) = std::str::from_utf8(ssid.data)
+        {
+            record.ssid = clean_ssid(ssid);
         }
+        let mut decoded = Decoded::decode(ies, frequency);
+        decoded.privacy(capability & 0x0010 != 0);
+        record.apply_elements(decoded);
+        Some(record)
+    }
+}
 
-        panic!("[WiFi] SSID did not match any known patterns: {}", ssid);
+// nl80211 BSS attributes, from linux/nl80211.h
+const NL80211_BSS_BSSID: u16 = 1;
+const NL80211_BSS_FREQUENCY: u16 = 2;
+const NL80211_BSS_CAPABILITY: u16 = 5;
+const NL80211_BSS_INFORMATION_ELEMENTS: u16 = 6;
+const NL80211_BSS_SIGNAL_MBM: u16 = 7;
+const NL80211_BSS_SEEN_MS_AGO: u16 = 10;
+const NL80211_BSS_BEACON_IES: u16 = 11;
+
+/// Netlink attributes in `payload` as (type, value), stopping at the first truncated one
+fn netlink_attributes(payload: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    let mut rest = payload;
+    std::iter::from_fn(move || {
+        let &[l0, l1, t0, t1, ..] = rest else {
+            return None;
+        };
+        let length = u16::from_ne_bytes([l0, l1]) as usize;
+        let kind = u16::from_ne_bytes([t0, t1]) & 0x3fff; // without the nested and byte order flags
+        let value = rest.get(4..length)?;
+        rest = rest.get(length.next_multiple_of(4)..).unwrap_or_default();
+        Some((kind, value))
+    })
+}
+
+/// u16 and u32 attributes alike
+fn netlink_u32(value: &[u8]) -> Option<u32> {
+    match *value {
+        [a, b] => Some(u16::from_ne_bytes([a, b]) as u32),
+        [a, b, c, d] => Some(u32::from_ne_bytes([a, b, c, d])),
+        _ => None,
     }
 }
 
-pub async fn fetch_wifi_stats() -> Vec<WifiBssid> {
-    println!("[WiFi] Running scan...");
-    let _ = tokio::process::Command::new("sudo")
-        .args(["iw", "dev", "wlan0", "scan", "trigger"])
-        .output()
-        .await
-        .expect("[WiFi] Failed to trigger scan - Is IW installed?"); // Wait 10 seconds for scan to complete 
+static RE_FIELD: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\* ([^:]+):\s*(.*)$").unwrap());
+static RE_UNKNOWN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^Unknown (IE|Extension ID) \((\d+)\):(.*)$").unwrap());
 
-    tokio::time::sleep(Duration::from_secs(SCAN_DURATION_SECS)).await; // Dump the scan results 
-    let output = tokio::process::Command::new("sudo")
-        .args(["iw", "dev", "wlan0", "scan", "dump"])
-        .output()
-        .await
-        .expect("[WiFi] Failed to dump scan results");
-    let stdout = String::from_utf8_lossy(&output.stdout);
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    #[default]
+    Other,
+    HtOperation,
+    VhtOperation,
+    Rsn,
+}
+
+/// Information elements of one BSS, rebuilt from iw's output. Elements iw
+/// decodes are re-encoded from the fields we need, the others are copied
+/// from the hex it prints for them.
+#[derive(Debug, Default)]
+struct IwElements {
+    ies: Vec<u8>,
+    section: Section,
+    ht_operation: Option<[u8; 2]>,  // primary channel, parameters
+    vht_operation: Option<[u8; 3]>, // width, center segments
+    akms: Option<Vec<u8>>,          // RSN authentication suites
+    privacy: bool,
+    uhr: bool, // no element id assigned yet, only iw knows
+}
+
+impl IwElements {
+    fn push(&mut self, id: u8, data: &[u8]) {
+        let Ok(length) = u8::try_from(data.len()) else {
+            tracing::debug!("Skipping element {} of {} bytes", id, data.len());
+            return;
+        };
+        self.ies.push(id);
+        self.ies.push(length);
+        self.ies.extend_from_slice(data);
+    }
 
+    fn line(&mut self, line: &str) {
+        let line = line.trim();
+        if self.section != Section::Other
+            && let Some(caps) = RE_FIELD.captures(line)
+        {
+            self.field(&caps[1], caps[2].trim());
+            return;
+        }
+
+        self.section = Section::Other;
+        if let Some(rest) = line.strip_prefix("capability:") {
+            self.privacy = rest.split_whitespace().any(|flag| flag == "Privacy");
+        } else if let Some(rest) = line.strip_prefix("Country:") {
+            if let Some(code) = rest.split_whitespace().next()
+                && code.len() == 2
+            {
+                let code = code.as_bytes();
+                self.push(7, &[code[0], code[1], b' ']);
+            }
+        } else if let Some(rest) = line.strip_prefix("DS Parameter set: channel") {
+            if let Ok(channel) = rest.trim().parse() {
+                self.push(3, &[channel]);
+            }
+        } else if line.starts_with("HT capabilities:") {
+            self.push(45, &[]);
+        } else if line.starts_with("VHT capabilities:") {
+            self.push(191, &[]);
+        } else if line.starts_with("HE capabilities:") {
+            self.push(255, &[35]);
+        } else if line.starts_with("EHT capabilities:") {
+            self.push(255, &[108]);
+        } else if line.starts_with("UHR capabilities:") {
+            self.uhr = true;
+        } else if line.starts_with("HT operation:") {
+            self.section = Section::HtOperation;
+            self.ht_operation = Some([0; 2]);
+        } else if line.starts_with("VHT operation:") {
+            self.section = Section::VhtOperation;
+            self.vht_operation = Some([0; 3]);
+        } else if line.starts_with("RSN:") {
+            self.section = Section::Rsn;
+            self.akms.get_or_insert_default();
+        } else if line.starts_with("WPA:") {
+            self.push(221, &[0x00, 0x50, 0xf2, 1]);
+        } else if let Some(caps) = RE_UNKNOWN.captures(line)
+            && let Ok(id) = caps[2].parse::<u8>()
+            && let Some(data) = ie::parse_hex(&caps[3])
+        {
+            if &caps[1] == "IE" {
+                self.push(id, &data);
+            } else {
+                self.push(255, &[&[id], data.as_slice()].concat());
+            }
+        }
+    }
+
+    fn field(&mut self, name: &str, value: &str) {
+        let number = || value.split_whitespace().next()?.parse::<u8>().ok();
+        match (self.section, name) {
+            (Section::HtOperation, "primary channel") => {
+                if let Some(ht) = &mut self.ht_operation {
+                    ht[0] = number().unwrap_or(0);
+                }
+            }
+            (Section::HtOperation, "secondary channel offset") => {
+                if let Some(ht) = &mut self.ht_operation {
+                    ht[1] |= match value {
+                        "above" => 1,
+                        "below" => 3,
+                        _ => 0,
+                    };
+                }
+            }
+            (Section::HtOperation, "STA channel width") => {
+                if let Some(ht) = &mut self.ht_operation
+                    && value.starts_with("any")
+                {
+                    ht[1] |= 0x04;
+                }
+            }
+            (
+                Section::VhtOperation,
+                "channel width" | "center freq segment 1" | "center freq segment 2",
+            ) => {
+                let index = match name {
+                    "channel width" => 0,
+                    "center freq segment 1" => 1,
+                    _ => 2,
+                };
+                if let Some(vht) = &mut self.vht_operation {
+                    vht[index] = number().unwrap_or(0);
+                }
+            }
+            (Section::Rsn, "Authentication suites") => {
+                let akms = self.akms.get_or_insert_default();
+                // iw's names for the suites, some of them with a space
+                for suite in value.replace("IEEE ", "IEEE_").split_whitespace() {
+                    let akm = match suite {
+                        "IEEE_802.1X" => 1,
+                        "PSK" => 2,
+                        "FT/IEEE_802.1X" => 3,
+                        "FT/PSK" => 4,
+                        "IEEE_802.1X/SHA-256" => 5,
+                        "PSK/SHA-256" => 6,
+                        "SAE" => 8,
+                        "FT/SAE" => 9,
+                        "IEEE_802.1X/SUITE-B" => 11,
+                        "IEEE_802.1X/SUITE-B-192" => 12,
+                        "FT/IEEE_802.1X/SHA-384" => 13,
+                        "OWE" => 18,
+                        other => match other.strip_prefix("00-0f-ac:") {
+                            Some(akm) => akm.parse().unwrap_or(0),
+                            None => continue,
+                        },
+                    };
+                    akms.push(akm);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn finish(mut self, frequency: u32) -> Decoded {
+        if let Some(ht) = self.ht_operation {
+            self.push(61, &ht);
+        }
+        if let Some(vht) = self.vht_operation {
+            self.push(192, &vht);
+        }
+        if let Some(akms) = self.akms.take() {
+            // version 1, CCMP group and pairwise ciphers, then the suites
+            let mut rsn = vec![1, 0, 0x00, 0x0f, 0xac, 4, 1, 0, 0x00, 0x0f, 0xac, 4];
+            rsn.extend_from_slice(&(akms.len() as u16).to_le_bytes());
+            for akm in akms {
+                rsn.extend_from_slice(&[0x00, 0x0f, 0xac, akm]);
+            }
+            self.push(48, &rsn);
+        }
+
+        let mut decoded = Decoded::decode(&self.ies, frequency);
+        decoded.privacy(self.privacy);
+        if self.uhr {
+            decoded.phy(PhyType::Uhr);
+        }
+        decoded
+    }
+}
+
+/// Parse the output of `iw dev <device> scan dump -u`
+pub fn parse_scan_dump(stdout: &str) -> Vec<WifiBssid> {
     let re_bssid = Regex::new(r"^BSS ([0-9a-f:]{17})").unwrap(); // match for access point mac address
     let re_ssid = Regex::new(r"^\s*SSID:(.*)$").unwrap();
     let re_freq = Regex::new(r"^\s*freq: (\d+)").unwrap();
-    let re_channel = Regex::new(r"^\s*\* primary channel: (\d+)").unwrap();
     let re_signal = Regex::new(r"signal:\s*([-]?\d+(?:\.\d+)?) dBm").unwrap(); // in dBm
     let re_last_seen = Regex::new(r"^\s*last seen: (\d+)\s*ms").unwrap(); // in milliseconds
 
-    let re_uhr_caps = Regex::new(r"^\s*UHR capabilities:").unwrap(); // Ultra High Rate Wifi 8 802.11bn
-    let re_eht_caps = Regex::new(r"^\s*EHT capabilities:").unwrap(); // Extremely High Throughput Wifi 7 802.11be
-    let re_he_caps = Regex::new(r"^\s*HE capabilities:").unwrap(); // High Efficiency Wifi 6 802.11ax
-    let re_vht_caps = Regex::new(r"^\s*VHT capabilities:").unwrap(); // Very High Throughput Wifi 5 802.11ac
-    let re_ht_caps = Regex::new(r"^\s*HT capabilities:").unwrap(); // High Throughput Wifi 4 802.11n
-
     let mut bssid_records = Vec::new();
-    let mut current_bssid: Option<WifiBssid> = None;
+    let mut current_bssid: Option<(WifiBssid, IwElements)> = None;
+    let mut finish = |(mut bssid, elements): (WifiBssid, IwElements)| {
+        bssid.apply_elements(elements.finish(bssid.frequency));
+        bssid_records.push(bssid);
+    };
 
     for line in stdout.lines() {
         if let Some(caps) = re_bssid.captures(line) {
             // if new AP is found
             if let Some(ap) = current_bssid.take() {
                 // check if there was a AP being built
-                bssid_records.push(ap); // if so, push it to the vec
-            }
-
-            current_bssid = Some(WifiBssid {
-                ssid: None,
-                bssid: caps[1].parse().unwrap_or_default(),
-                age: None,
-                channel: None,
-                frequency: 0,
-                phy: PhyType::Legacy,
-                rssi: 0,
-            });
-        } else if let Some(bssid) = current_bssid.as_mut() {
+                finish(ap); // if so, push it to the vec
+            }
+
+            let bssid = WifiBssid::new(caps[1].parse().unwrap_or_default(), 0);
+            current_bssid = Some((bssid, IwElements::default()));
+        } else if let Some((bssid, elements)) = current_bssid.as_mut() {
+            // PHY, channel, security and the like
+            elements.line(line);
+
             // SSID
             if let Some(caps) = re_ssid.captures(line) {
-                WifiBssid::parse_ssid(bssid, &caps[1]);
+                bssid.ssid = clean_ssid(&caps[1]);
                 continue;
             }
 
@@ -146,12 +467,6 @@ pub async fn fetch_wifi_stats() -> Vec<WifiBssid> {
                 continue;
             }
 
-            // Channel
-            if let Some(caps) = re_channel.captures(line) {
-                bssid.channel = caps[1].parse().ok();
-                continue;
-            }
-
             // Signal strength
             if let Some(caps) = re_signal.captures(line) {
                 bssid.rssi = caps[1].parse::<f64>().unwrap_or(0.0) as i32;
@@ -160,35 +475,196 @@ pub async fn fetch_wifi_stats() -> Vec<WifiBssid> {
 
             // Last seen age
             if let Some(caps) = re_last_seen.captures(line) {
-                let age_ms = caps[1].parse::<f64>().unwrap_or(0.0) * 1000.0;
-                bssid.age = Some(age_ms as u64);
+                bssid.age = caps[1].parse::<u64>().ok();
                 continue;
             }
+        }
+    }
 
-            // PHY type detection
-            bssid.phy = if re_uhr_caps.is_match(line) {
-                PhyType::Uhr
-            } else if re_eht_caps.is_match(line) {
-                PhyType::Eht
-            } else if re_he_caps.is_match(line) {
-                PhyType::He
-            } else if re_vht_caps.is_match(line) {
-                PhyType::Vht
-            } else if re_ht_caps.is_match(line) {
-                PhyType::Ht
-            } else {
-                PhyType::Legacy
-            };
+    if let Some(ap) = current_bssid {
+        finish(ap);
+    }
+    bssid_records
+}
+
+/// How a scan on [`INTERFACE`] ended, from a line of `iw event`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanEnd {
+    Finished,
+    Aborted,
+}
+
+impl ScanEnd {
+    /// Parse lines like `wlan0 (phy #0): scan finished: 2412 2417, ""`,
+    /// optionally behind the timestamp `iw event -t` prints
+    pub fn parse(line: &str, interface: &str) -> Option<ScanEnd> {
+        let (_, event) = line.split_once(&format!("{} (phy #", interface))?;
+        let (_, event) = event.split_once("): ")?;
+        if event.starts_with("scan finished") {
+            Some(ScanEnd::Finished)
+        } else if event.starts_with("scan aborted") {
+            Some(ScanEnd::Aborted)
+        } else {
+            None
         }
     }
+}
 
-    if let Some(bssid) = current_bssid {
-        bssid_records.push(bssid);
+/// Start listening for the end of a scan. Must run before the trigger, or the
+/// event can come and go before we're listening.
+fn watch_scan() -> Option<tokio::process::Child> {
+    match tokio::process::Command::new("iw")
+        .arg("event")
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+    {
+        Ok(child) => Some(child),
+        Err(e) => {
+            println!("[WiFi] Can't watch for scan completion: {}", e);
+            None
+        }
+    }
+}
+
+/// Wait for the scan to end, or for [`WIFI_SCAN_TIMEOUT_SECS`]
+async fn wait_for_scan(mut watcher: Option<tokio::process::Child>) -> Option<ScanEnd> {
+    let timeout = Duration::from_secs(WIFI_SCAN_TIMEOUT_SECS);
+    let Some(stdout) = watcher.as_mut().and_then(|child| child.stdout.take()) else {
+        tokio::time::sleep(timeout).await; // no way to tell, give it the whole time
+        return None;
+    };
+    let mut lines = BufReader::new(stdout).lines();
+    let end = tokio::time::timeout(timeout, async {
+        while let Ok(Some(line)) = lines.next_line().await {
+            if let Some(end) = ScanEnd::parse(&line, INTERFACE) {
+                return Some(end);
+            }
+        }
+        None
+    })
+    .await;
+    end.ok().flatten()
+}
+
+/// Split off entries older than `max_age_ms`, returning how many were dropped.
+/// Entries without an age are kept.
+pub fn drop_stale(records: &mut Vec<WifiBssid>, max_age_ms: u64) -> usize {
+    let before = records.len();
+    records.retain(|ap| ap.age.is_none_or(|age| age <= max_age_ms));
+    before - records.len()
+}
+
+async fn trigger_scan(settings: &ScanSettings) -> std::io::Result<std::process::Output> {
+    tokio::process::Command::new("sudo")
+        .args(["iw", "dev", INTERFACE, "scan", "trigger"])
+        .args(settings.trigger_args())
+        .output()
+        .await
+}
+
+pub async fn fetch_wifi_stats(profile: ScanProfile) -> Vec<WifiBssid> {
+    println!("[WiFi] Running {} scan...", profile);
+    let watcher = watch_scan();
+    let settings = profile.settings();
+    let mut trigger = trigger_scan(&settings).await;
+    if let Ok(out) = &trigger
+        && !out.status.success()
+        && out.status.code() != Some(240)
+        && let Some(fallback) = settings.without_dwell()
+    {
+        println!("[WiFi] Driver refused the dwell time, scanning with its default");
+        trigger = trigger_scan(&fallback).await;
+    }
+
+    match trigger {
+        Ok(out) if out.status.success() => status::record_wifi_adapter(AdapterState::Available),
+        Ok(out) if out.status.code() == Some(240) => {
+            // -EBUSY, someone else's scan is running and its results will do
+            println!("[WiFi] Scan already in progress, waiting for it");
+            status::record_wifi_adapter(AdapterState::Available);
+        }
+        Ok(out) => {
+            let reason = String::from_utf8_lossy(&out.stderr).trim().to_string();
+            println!("[WiFi] Failed to trigger scan: {}", reason);
+            status::record_wifi_adapter(AdapterState::Unavailable(reason));
+            return Vec::new(); // the dump would only hold cached entries
+        }
+        Err(e) => {
+            println!("[WiFi] Failed to trigger scan - Is IW installed? {}", e);
+            status::record_wifi_adapter(AdapterState::Unavailable(e.to_string()));
+            return Vec::new();
+        }
+    }
+
+    match wait_for_scan(watcher).await {
+        Some(ScanEnd::Finished) => {}
+        Some(ScanEnd::Aborted) => println!("[WiFi] Scan was aborted, results may be incomplete"),
+        None => println!("[WiFi] No scan completion seen, dumping what there is"),
+    }
+    let output = match tokio::process::Command::new("sudo") // Dump the scan results
+        .args(["iw", "dev", INTERFACE, "scan", "dump", "-u"]) // -u prints unknown elements in hex
+        .output()
+        .await
+    {
+        Ok(out) => out,
+        Err(e) => {
+            println!("[WiFi] Failed to dump scan results: {}", e);
+            status::record_wifi_adapter(AdapterState::Unavailable(e.to_string()));
+            return Vec::new();
+        }
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut bssid_records = parse_scan_dump(&stdout);
+    let stale = drop_stale(&mut bssid_records, WIFI_MAX_AGE_MS);
+    if stale > 0 {
+        println!(
+            "[WiFi] Dropped {} of {} networks last seen over {} ms ago",
+            stale,
+            stale + bssid_records.len(),
+            WIFI_MAX_AGE_MS
+        );
     }
 
     println!(
         "[WiFi] Finished scanning. Total Networks: {}",
         bssid_records.len()
     );
+    events::publish(Event::WifiScan {
+        timestamp: unix_millis(),
+        access_points: bssid_records.clone(),
+    });
     bssid_records
 }
+
+/// Scan `passes` times and combine the results, see [`Window`]
+pub async fn fetch_wifi_window(passes: u32, profile: ScanProfile) -> Vec<WifiBssid> {
+    let mut window = Window::new();
+    for pass in 1..=passes {
+        let started = unix_millis();
+        let records = fetch_wifi_stats(profile).await;
+        let dumped = unix_millis();
+        for record in records {
+            let seen_at = dumped.saturating_sub(record.age.unwrap_or(0) as u128);
+            if seen_at >= started {
+                window.add(record, seen_at); // older entries are left over from earlier scans
+            }
+        }
+        tracing::debug!(
+            "WiFi window pass {}/{}: {} networks so far",
+            pass,
+            passes,
+            window.len()
+        );
+    }
+
+    let seen = window.len();
+    let records = window.finish(unix_millis());
+    println!(
+        "[WiFi] Window of {} passes: {} networks, {} seen only once",
+        passes,
+        records.len(),
+        seen - records.len()
+    );
+    records
+}
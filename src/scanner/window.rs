@@ -0,0 +1,172 @@
+//! Combining repeated sightings of the same devices into one result
+//!
+//! A single scan gives one noisy RSSI reading per device. Over a scan window
+//! every sighting is kept, and each device is reported once with the median
+//! of its readings. Devices seen only once in the window are dropped, they
+//! were probably at the edge of range or just passing by.
+
+use btleplug::api::BDAddr;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::{BleDevice, WifiBssid};
+use crate::config::SCAN_WINDOW_MAX_PASSES;
+use crate::error::{Error, Result};
+
+/// Signal readings of one device over a scan window
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Samples {
+    pub count: u32,
+    pub median_rssi: i32, // in dBm
+    pub max_rssi: i32,    // in dBm
+    pub first_seen: u128, // in milliseconds since Unix epoch
+    pub last_seen: u128,  // in milliseconds since Unix epoch
+}
+
+/// Records whose signal strength can be aggregated
+pub trait Sampled: Clone {
+    fn address(&self) -> BDAddr;
+    fn signal(&self) -> Option<i32>;
+    /// Report `samples` in place of the single reading
+    fn summarize(&mut self, samples: Samples, age: u64);
+}
+
+impl Sampled for WifiBssid {
+    fn address(&self) -> BDAddr {
+        self.bssid
+    }
+
+    fn signal(&self) -> Option<i32> {
+        Some(self.rssi)
+    }
+
+    fn summarize(&mut self, samples: Samples, age: u64) {
+        self.rssi = samples.median_rssi;
+        self.age = Some(age);
+        self.samples = Some(samples);
+    }
+}
+
+impl Sampled for BleDevice {
+    fn address(&self) -> BDAddr {
+        self.mac_address
+    }
+
+    fn signal(&self) -> Option<i32> {
+        self.rssi.map(i32::from)
+    }
+
+    fn summarize(&mut self, samples: Samples, age: u64) {
+        self.rssi = Some(samples.median_rssi as i16);
+        self.age = Some(age);
+        self.samples = Some(samples);
+    }
+}
+
+/// Sightings collected during one scan window
+#[derive(Debug)]
+pub struct Window<T> {
+    devices: HashMap<BDAddr, (T, Vec<(i32, u128)>)>,
+    order: Vec<BDAddr>, // first sighting first
+}
+
+impl<T> Default for Window<T> {
+    fn default() -> Self {
+        Window {
+            devices: HashMap::new(),
+            order: Vec::new(),
+        }
+    }
+}
+
+impl<T: Sampled> Window<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a sighting at `seen_at`, in milliseconds since Unix epoch.
+    /// Records without a signal strength are not samples and are ignored.
+    pub fn add(&mut self, record: T, seen_at: u128) {
+        let Some(rssi) = record.signal() else {
+            return;
+        };
+        let address = record.address();
+        match self.devices.get_mut(&address) {
+            Some((latest, sightings)) => {
+                if sightings.iter().any(|&(_, at)| at == seen_at) {
+                    return; // the same sighting reported twice
+                }
+                if sightings.iter().all(|&(_, at)| at < seen_at) {
+                    *latest = record;
+                }
+                sightings.push((rssi, seen_at));
+            }
+            None => {
+                self.devices
+                    .insert(address, (record, vec![(rssi, seen_at)]));
+                self.order.push(address);
+            }
+        }
+    }
+
+    /// Number of distinct devices sighted so far
+    pub fn len(&self) -> usize {
+        self.devices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    /// One record per device seen at least twice, aged relative to `now`
+    pub fn finish(mut self, now: u128) -> Vec<T> {
+        let mut records = Vec::with_capacity(self.order.len());
+        for address in &self.order {
+            let Some((mut record, sightings)) = self.devices.remove(address) else {
+                continue;
+            };
+            let Some(samples) = summarize(&sightings) else {
+                continue;
+            };
+            let age = now.saturating_sub(samples.last_seen);
+            record.summarize(samples, u64::try_from(age).unwrap_or(u64::MAX));
+            records.push(record);
+        }
+        records
+    }
+}
+
+/// Median and maximum of at least two readings
+fn summarize(sightings: &[(i32, u128)]) -> Option<Samples> {
+    if sightings.len() < 2 {
+        return None;
+    }
+    let mut readings: Vec<i32> = sightings.iter().map(|&(rssi, _)| rssi).collect();
+    readings.sort_unstable();
+    let middle = readings.len() / 2;
+    let median = if readings.len().is_multiple_of(2) {
+        (readings[middle - 1] + readings[middle]) / 2
+    } else {
+        readings[middle]
+    };
+    Some(Samples {
+        count: sightings.len() as u32,
+        median_rssi: median,
+        max_rssi: readings[readings.len() - 1],
+        first_seen: sightings.iter().map(|&(_, at)| at).min()?,
+        last_seen: sightings.iter().map(|&(_, at)| at).max()?,
+    })
+}
+
+/// Check a requested number of scan passes
+pub fn check_passes(passes: u32) -> Result<u32> {
+    if (1..=SCAN_WINDOW_MAX_PASSES).contains(&passes) {
+        Ok(passes)
+    } else {
+        Err(Error::InvalidScan(format!(
+            "{} passes, expected 1 to {}",
+            passes, SCAN_WINDOW_MAX_PASSES
+        )))
+    }
+}
@@ -0,0 +1,69 @@
+//! Live event stream served on `/events` as Server-Sent Events
+//!
+//! Scanners, the geosubmit client and the GATT peripheral publish into a
+//! broadcast channel; every connected dashboard gets its own receiver.
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::config::EVENT_BUFFER_SIZE;
+use crate::scanner::{BleDevice, WifiBssid};
+
+static EVENTS: Lazy<broadcast::Sender<Event>> =
+    Lazy::new(|| broadcast::channel(EVENT_BUFFER_SIZE).0);
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub enum PairingState {
+    Subscribed,
+    Unsubscribed,
+    PayloadReceived,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum Event {
+    WifiScan {
+        timestamp: u128, // in milliseconds since Unix epoch
+        access_points: Vec<WifiBssid>,
+    },
+    BleScan {
+        timestamp: u128, // in milliseconds since Unix epoch
+        beacons: Vec<BleDevice>,
+    },
+    Submission {
+        provider: String,
+        status: Option<u16>, // None when no response arrived
+        error: Option<String>,
+    },
+    Pairing {
+        client: String,
+        state: PairingState,
+    },
+}
+
+impl Event {
+    /// SSE event name, matching the `type` field of the JSON data
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::WifiScan { .. } => "wifiScan",
+            Event::BleScan { .. } => "bleScan",
+            Event::Submission { .. } => "submission",
+            Event::Pairing { .. } => "pairing",
+        }
+    }
+}
+
+/// Send an event to every connected listener
+pub fn publish(event: Event) {
+    let _ = EVENTS.send(event); // errors only when nobody is listening
+}
+
+pub fn subscribe() -> broadcast::Receiver<Event> {
+    EVENTS.subscribe()
+}
@@ -1,25 +1,55 @@
-use axum::http::StatusCode;
+use axum::Json;
+use axum::extract::{ConnectInfo, Path, Query};
+use axum::http::{StatusCode, header};
+use axum::response::IntoResponse;
+use axum::response::sse::{self, KeepAlive, Sse};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
 use std::time::Duration;
 use tokio::time::timeout;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
 use tracing::{error, info};
 
-use crate::geosubmit::{self, items};
+use crate::collect::mobility::{Classification, DeviceKind, MobileDevice, MobileStore};
+use crate::collect::privacy::{DeviceRuleStore, DeviceRules, NewZone, ZoneStore, ZoneSummary};
+use crate::collect::runner::blocking;
+use crate::collect::{Policy, Report};
+use crate::config::REQUEST_MAX_AGE_MS;
+use crate::geosubmit::{self, CellTower, Position, items};
+use crate::position::filter;
+use crate::scanner::cache::{self, ScanKind, ScanOptions};
+use crate::scanner::{Band, BleDevice, ScanProfile, WifiBssid};
+use crate::server::status::{self, StatusReport};
+use crate::server::{events, metrics, rate_limit};
+use crate::time::unix_millis;
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct PartialPayload {
     pub position: serde_json::Value,
     pub cell_towers: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub passes: Option<u32>, // combine this many scans, see `scanner::window`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<ScanProfile>,
+    #[serde(skip)]
+    pub client: String, // rate limiting and position filtering key, set by the transport
     #[serde(flatten)]
     pub extra: HashMap<String, serde_json::Value>,
 }
 
 pub async fn process_submit_http(
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
     axum::Json(value): axum::Json<serde_json::Value>,
 ) -> Result<String, crate::error::Error> {
-    let payload: PartialPayload = serde_json::from_value(value)
+    let client = format!("http:{}", peer.ip());
+    rate_limit::SUBMISSIONS.check(&client)?;
+
+    let mut payload: PartialPayload = serde_json::from_value(value)
         .map_err(|e| crate::error::Error::Other(format!("JSON Parse Error: {}", e)))?;
+    payload.client = client;
 
     process_submit(payload).await
 }
@@ -27,9 +57,64 @@ pub async fn process_submit_http(
 pub async fn process_submit(payload: PartialPayload) -> Result<String, crate::error::Error> {
     info!("[Server] Processing submission...");
 
-    let geo_items: items = geosubmit::assemble_geo_payload(payload.position, payload.cell_towers)
+    let position: Position = serde_json::from_value(payload.position)
+        .map_err(|e| crate::error::Error::Serialization(e.to_string()))?;
+    let options = ScanOptions::new(payload.passes, payload.profile)?;
+    let measured_at = unix_millis().saturating_sub(position.age.unwrap_or(0) as u128);
+    let position =
+        filter::process(&payload.client, measured_at, position).map_err(|rejection| {
+            info!(
+                "[Server] Rejected position from {}: {}",
+                payload.client, rejection
+            );
+            crate::error::Error::PositionRejected(rejection)
+        })?;
+
+    let policy = Policy::local();
+    let checked = position.clone();
+    if blocking(move || policy.zones.check(&checked))
+        .await
+        .is_some()
+    {
+        // nothing is uploaded from inside a zone, whatever its action
+        info!("[Server] Submission made inside a privacy zone, dropped");
+        return Ok(String::from("Suppressed"));
+    }
+
+    let assembly_error = |e: crate::error::Error| match e {
+        crate::error::Error::RateLimited { .. } => e,
+        e => crate::error::Error::Other(format!("Assembly Error: {}", e)),
+    };
+    let cell_towers: Vec<CellTower> = match payload.cell_towers {
+        Some(value) => serde_json::from_value(value)
+            .map_err(|e| assembly_error(crate::error::Error::Serialization(e.to_string())))?,
+        None => Vec::new(),
+    };
+    let observations = cache::try_scan(ScanKind::All, Duration::ZERO, options)
         .await
-        .map_err(|e| crate::error::Error::Other(format!("Assembly Error: {}", e)))?;
+        .map_err(assembly_error)?;
+    let mut report = Report::new(observations, Some(position.clone()));
+    report.cell_towers = geosubmit::client::merge_cells(cell_towers, report.cell_towers);
+
+    // the stores read and write files
+    let (report, dropped) = blocking(move || {
+        if let Err(e) = policy.mobiles.observe(&report) {
+            tracing::warn!("Failed to update mobile devices: {}", e);
+        }
+        let dropped = policy.strip(&mut report);
+        (report, dropped)
+    })
+    .await;
+    metrics::observe_dropped(&dropped);
+    status::record_dropped(&dropped);
+
+    let geo_items: items = geosubmit::assemble_geo_payload(
+        report.timestamp,
+        position,
+        &report.wifi_access_points,
+        &report.bluetooth_beacons,
+        report.cell_towers,
+    );
 
     let handle = tokio::spawn(async move { geosubmit::submit_geo_payload(geo_items).await });
 
@@ -53,10 +138,177 @@ pub async fn process_submit(payload: PartialPayload) -> Result<String, crate::er
     Ok(String::from("Successful"))
 }
 
-pub async fn handle_status() -> (StatusCode, String) {
-    (StatusCode::OK, "ok".to_string())
+pub async fn handle_status() -> Json<StatusReport> {
+    Json(status::report())
+}
+
+pub async fn handle_events() -> Sse<impl Stream<Item = Result<sse::Event, Infallible>>> {
+    let stream = BroadcastStream::new(events::subscribe()).filter_map(|received| match received {
+        Ok(event) => sse::Event::default()
+            .event(event.name())
+            .json_data(&event)
+            .ok()
+            .map(Ok),
+        Err(lagged) => {
+            tracing::warn!("Event listener fell behind: {}", lagged);
+            None
+        }
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+pub async fn handle_metrics() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+        metrics::render(),
+    )
+}
+
+/// Query parameters accepted by `/request`
+#[derive(Deserialize, Debug)]
+pub struct RequestParams {
+    #[serde(rename = "type", default)]
+    pub kind: ScanKind,
+    pub max_age: Option<u64>, // in milliseconds
+    #[serde(default)]
+    pub wait: bool, // block until a fresh scan finishes instead of returning the cache
+    pub band: Option<Band>,   // only access points in this band
+    pub passes: Option<u32>,  // combine this many scans, see `scanner::window`
+    pub profile: Option<ScanProfile>,
+}
+
+/// Observations in geosubmit format, without a position
+#[derive(Serialize, Debug)]
+#[allow(non_snake_case)]
+pub struct ScanItem {
+    pub timestamp: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wifiAccessPoints: Option<Vec<WifiBssid>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bluetoothBeacons: Option<Vec<BleDevice>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cellTowers: Option<Vec<CellTower>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ScanResponse {
+    pub items: Vec<ScanItem>,
+}
+
+pub async fn handle_request(
+    Query(params): Query<RequestParams>,
+) -> Result<Json<ScanResponse>, crate::error::Error> {
+    let max_age = Duration::from_millis(params.max_age.unwrap_or(REQUEST_MAX_AGE_MS));
+    let options = ScanOptions::new(params.passes, params.profile)?;
+
+    let observations = if params.wait {
+        cache::try_scan(params.kind, max_age, options).await?
+    } else {
+        let cached = cache::cached(params.kind, max_age, options);
+        let stale = (params.kind.wifi() && cached.wifi.is_none())
+            || (params.kind.ble() && cached.ble.is_none())
+            || (params.kind.cell() && cached.cell.is_none());
+        if stale {
+            // refresh in the background so the next request finds something
+            let kind = params.kind;
+            tokio::spawn(async move { cache::try_scan(kind, max_age, options).await });
+        }
+        cached
+    };
+
+    let items = match observations.timestamp() {
+        Some(timestamp) => vec![ScanItem {
+            timestamp,
+            wifiAccessPoints: observations.wifi.map(|s| {
+                let mut records = s.records;
+                if let Some(band) = params.band {
+                    records.retain(|ap| ap.band() == Some(band));
+                }
+                records
+            }),
+            bluetoothBeacons: observations.ble.map(|s| s.records),
+            cellTowers: observations.cell.map(|s| s.records),
+        }],
+        None => Vec::new(),
+    };
+
+    Ok(Json(ScanResponse { items }))
+}
+
+/// `GET /zones`: ids and kinds only, the coordinates never leave the machine
+pub async fn handle_list_zones() -> Result<Json<Vec<ZoneSummary>>, crate::error::Error> {
+    Ok(Json(blocking(|| ZoneStore::local().list()).await?))
+}
+
+#[derive(Serialize, Debug)]
+pub struct ZoneCreated {
+    pub id: String,
+}
+
+pub async fn handle_add_zone(
+    Json(zone): Json<NewZone>,
+) -> Result<(StatusCode, Json<ZoneCreated>), crate::error::Error> {
+    let id = blocking(move || ZoneStore::local().add(zone)).await?;
+    info!("[Server] Added privacy zone {}", id);
+    Ok((StatusCode::CREATED, Json(ZoneCreated { id })))
+}
+
+pub async fn handle_remove_zone(Path(id): Path<String>) -> Result<StatusCode, crate::error::Error> {
+    let removed = {
+        let id = id.clone();
+        blocking(move || ZoneStore::local().remove(&id)).await?
+    };
+    if removed {
+        info!("[Server] Removed privacy zone {}", id);
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Ok(StatusCode::NOT_FOUND)
+    }
+}
+
+pub async fn handle_list_mobile() -> Result<Json<Vec<MobileDevice>>, crate::error::Error> {
+    Ok(Json(blocking(|| MobileStore::local().list()).await?))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ClassifyRequest {
+    pub classification: Classification,
+}
+
+/// `PUT /mobile/{kind}/{address}`: override what detection decided
+pub async fn handle_classify_mobile(
+    Path((kind, address)): Path<(DeviceKind, String)>,
+    Json(request): Json<ClassifyRequest>,
+) -> Result<Json<MobileDevice>, crate::error::Error> {
+    let device =
+        blocking(move || MobileStore::local().classify(kind, &address, request.classification))
+            .await?;
+    Ok(Json(device))
+}
+
+pub async fn handle_forget_mobile(
+    Path((kind, address)): Path<(DeviceKind, String)>,
+) -> Result<StatusCode, crate::error::Error> {
+    if blocking(move || MobileStore::local().forget(kind, &address)).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Ok(StatusCode::NOT_FOUND)
+    }
+}
+
+/// `GET /privacy/devices`: vendors and address types never uploaded
+pub async fn handle_get_device_rules() -> Result<Json<DeviceRules>, crate::error::Error> {
+    Ok(Json(blocking(|| DeviceRuleStore::local().get()).await?))
 }
 
-pub async fn handle_request() -> (StatusCode, String) {
-    (StatusCode::OK, "ok".to_string())
+pub async fn handle_set_device_rules(
+    Json(rules): Json<DeviceRules>,
+) -> Result<Json<DeviceRules>, crate::error::Error> {
+    let rules = blocking(move || DeviceRuleStore::local().set(rules)).await?;
+    info!(
+        "[Server] Updated device rules, {} vendors",
+        rules.vendors.len()
+    );
+    Ok(Json(rules))
 }
@@ -16,11 +16,11 @@ pub fn register_mdns_service(
 
     let properties = HashMap::from([
         ("version".into(), version.into()),
-        ("paths".into(), "/submit, /status, /request".into()),
         (
-            "cert_fingerprint".into(),
-            hex::encode(cert_fingerprint).into(),
+            "paths".into(),
+            "/submit, /status, /request, /metrics, /events".into(),
         ),
+        ("cert_fingerprint".into(), hex::encode(cert_fingerprint)),
     ]);
 
     let hostname = format!("serviceberry-{}.local.", username.to_lowercase());
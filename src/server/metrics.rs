@@ -0,0 +1,213 @@
+//! Prometheus metrics exported on `/metrics`
+
+use axum::http::Extensions;
+use once_cell::sync::Lazy;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts,
+    Registry, TextEncoder,
+};
+use reqwest_middleware::Next;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::config::GEOSUBMIT_PROVIDER;
+use crate::scanner::{Band, WifiBssid};
+use crate::server::status;
+
+static REGISTRY: Lazy<Registry> = Lazy::new(Registry::new);
+
+fn register<T: prometheus::core::Collector + Clone + 'static>(metric: T) -> T {
+    REGISTRY
+        .register(Box::new(metric.clone()))
+        .expect("metric registered twice");
+    metric
+}
+
+/// Scan duration by scanner ("wifi" or "ble")
+pub static SCAN_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    let opts = HistogramOpts::new(
+        "serviceberry_scan_duration_seconds",
+        "Time taken by a single radio scan",
+    )
+    .buckets(vec![0.5, 1.0, 2.5, 5.0, 10.0, 12.5, 15.0, 20.0, 30.0]);
+    register(HistogramVec::new(opts, &["scanner"]).unwrap())
+});
+
+pub static SCAN_ACCESS_POINTS: Lazy<Histogram> = Lazy::new(|| {
+    let opts = HistogramOpts::new(
+        "serviceberry_scan_access_points",
+        "Wi-Fi access points found per scan",
+    )
+    .buckets(vec![0.0, 1.0, 5.0, 10.0, 20.0, 50.0, 100.0, 200.0]);
+    register(Histogram::with_opts(opts).unwrap())
+});
+
+pub static SCAN_BEACONS: Lazy<Histogram> = Lazy::new(|| {
+    let opts = HistogramOpts::new(
+        "serviceberry_scan_beacons",
+        "Bluetooth beacons found per scan",
+    )
+    .buckets(vec![0.0, 1.0, 5.0, 10.0, 20.0, 50.0, 100.0, 200.0]);
+    register(Histogram::with_opts(opts).unwrap())
+});
+
+/// Access points seen by band ("2.4", "5", "6", "60" or "unknown")
+pub static ACCESS_POINTS_BY_BAND: Lazy<IntCounterVec> = Lazy::new(|| {
+    let opts = Opts::new(
+        "serviceberry_access_points_total",
+        "Wi-Fi access points found by scans, by band",
+    );
+    register(IntCounterVec::new(opts, &["band"]).unwrap())
+});
+
+/// Devices the device rules kept out of uploads, by reason
+pub static DEVICES_DROPPED: Lazy<IntCounterVec> = Lazy::new(|| {
+    let opts = Opts::new(
+        "serviceberry_devices_dropped_total",
+        "Access points and beacons left out of uploads by the device rules, by reason",
+    );
+    register(IntCounterVec::new(opts, &["reason"]).unwrap())
+});
+
+pub static SCAN_CELLS: Lazy<Histogram> = Lazy::new(|| {
+    let opts = HistogramOpts::new("serviceberry_scan_cells", "Cell towers found per scan")
+        .buckets(vec![0.0, 1.0, 2.0, 5.0, 10.0, 20.0]);
+    register(Histogram::with_opts(opts).unwrap())
+});
+
+/// Submissions by provider and HTTP status code ("error" when no response arrived)
+pub static SUBMISSIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    let opts = Opts::new(
+        "serviceberry_submissions_total",
+        "Geosubmit requests by provider and status code",
+    );
+    register(IntCounterVec::new(opts, &["provider", "status"]).unwrap())
+});
+
+pub static SUBMISSION_RETRIES: Lazy<IntCounterVec> = Lazy::new(|| {
+    let opts = Opts::new(
+        "serviceberry_submission_retries_total",
+        "Geosubmit requests retried after a transient failure",
+    );
+    register(IntCounterVec::new(opts, &["provider"]).unwrap())
+});
+
+pub static QUEUE_DEPTH: Lazy<IntGauge> = Lazy::new(|| {
+    register(
+        IntGauge::new(
+            "serviceberry_queue_depth",
+            "Payloads waiting for the submission worker",
+        )
+        .unwrap(),
+    )
+});
+
+pub static BLE_WRITE_FRAMES: Lazy<IntCounter> = Lazy::new(|| {
+    register(
+        IntCounter::new(
+            "serviceberry_ble_write_frames_total",
+            "GATT write requests received from phones",
+        )
+        .unwrap(),
+    )
+});
+
+pub static BLE_PARSE_FAILURES: Lazy<IntCounter> = Lazy::new(|| {
+    register(
+        IntCounter::new(
+            "serviceberry_ble_parse_failures_total",
+            "BLE payloads that could not be parsed",
+        )
+        .unwrap(),
+    )
+});
+
+/// Location updates by transport ("http" or "ble") and outcome
+pub static LOCATION_UPDATES: Lazy<IntCounterVec> = Lazy::new(|| {
+    let opts = Opts::new(
+        "serviceberry_location_updates_total",
+        "Streamed location updates by transport and outcome",
+    );
+    register(IntCounterVec::new(opts, &["transport", "outcome"]).unwrap())
+});
+
+pub static TLS_HANDSHAKE_ERRORS: Lazy<IntCounter> = Lazy::new(|| {
+    register(
+        IntCounter::new(
+            "serviceberry_tls_handshake_errors_total",
+            "Incoming connections that failed the TLS handshake",
+        )
+        .unwrap(),
+    )
+});
+
+/// Record one finished scan
+pub fn observe_scan(scanner: &str, duration: std::time::Duration, found: usize) {
+    SCAN_DURATION
+        .with_label_values(&[scanner])
+        .observe(duration.as_secs_f64());
+    match scanner {
+        "wifi" => SCAN_ACCESS_POINTS.observe(found as f64),
+        "cell" => SCAN_CELLS.observe(found as f64),
+        _ => SCAN_BEACONS.observe(found as f64),
+    }
+}
+
+/// Count devices the device rules kept out of an upload
+pub fn observe_dropped(dropped: &BTreeMap<&str, usize>) {
+    for (reason, count) in dropped {
+        DEVICES_DROPPED
+            .with_label_values(&[reason])
+            .inc_by(*count as u64);
+    }
+}
+
+/// Count the access points of one finished Wi-Fi scan by band
+pub fn observe_bands(access_points: &[WifiBssid]) {
+    for ap in access_points {
+        let band = ap.band().map_or("unknown", Band::label);
+        ACCESS_POINTS_BY_BAND.with_label_values(&[band]).inc();
+    }
+}
+
+/// Marks a request that has already been sent once
+#[derive(Clone)]
+struct Attempted;
+
+/// Middleware placed inside the retry middleware so it sees every attempt
+pub fn count_retries<'a>(
+    req: reqwest::Request,
+    extensions: &'a mut Extensions,
+    next: Next<'a>,
+) -> Pin<Box<dyn Future<Output = reqwest_middleware::Result<reqwest::Response>> + Send + 'a>> {
+    if extensions.insert(Attempted).is_some() {
+        SUBMISSION_RETRIES
+            .with_label_values(&[GEOSUBMIT_PROVIDER])
+            .inc();
+    }
+    Box::pin(next.run(req, extensions))
+}
+
+/// Render all metrics in the Prometheus text format
+pub fn render() -> String {
+    // register every metric so untouched ones are exported as zero
+    Lazy::force(&SCAN_DURATION);
+    Lazy::force(&SCAN_ACCESS_POINTS);
+    Lazy::force(&SCAN_BEACONS);
+    Lazy::force(&ACCESS_POINTS_BY_BAND);
+    Lazy::force(&SCAN_CELLS);
+    Lazy::force(&SUBMISSIONS);
+    Lazy::force(&SUBMISSION_RETRIES);
+    Lazy::force(&BLE_WRITE_FRAMES);
+    Lazy::force(&BLE_PARSE_FAILURES);
+    Lazy::force(&LOCATION_UPDATES);
+    Lazy::force(&TLS_HANDSHAKE_ERRORS);
+    QUEUE_DEPTH.set(status::queue_depth() as i64);
+
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&REGISTRY.gather(), &mut buffer)
+        .expect("text encoding cannot fail");
+    String::from_utf8(buffer).unwrap_or_default()
+}
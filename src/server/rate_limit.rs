@@ -0,0 +1,93 @@
+//! Per-client token bucket rate limiting for submissions and location streams
+
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::{
+    STREAM_RATE_BURST, STREAM_RATE_INTERVAL_SECS, SUBMIT_RATE_BURST, SUBMIT_RATE_INTERVAL_SECS,
+};
+use crate::error::{Error, Result};
+
+pub const MAX_TRACKED_CLIENTS: usize = 1024;
+
+/// Shared by HTTP (`http:<ip>`) and BLE (`ble:<client>`) submissions
+pub static SUBMISSIONS: Lazy<RateLimiter> = Lazy::new(|| {
+    RateLimiter::new(
+        SUBMIT_RATE_BURST,
+        Duration::from_secs(SUBMIT_RATE_INTERVAL_SECS),
+    )
+});
+
+/// `POST /location` requests, by `http:<ip>`
+pub static STREAMS: Lazy<RateLimiter> = Lazy::new(|| {
+    RateLimiter::new(
+        STREAM_RATE_BURST,
+        Duration::from_secs(STREAM_RATE_INTERVAL_SECS),
+    )
+});
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+pub struct RateLimiter {
+    burst: f64,
+    interval: Duration, // time to earn back one token
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(burst: u32, interval: Duration) -> Self {
+        RateLimiter {
+            burst: burst as f64,
+            interval,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Take one token for `client`, or fail with the time until one is available
+    pub fn check(&self, client: &str) -> Result<()> {
+        self.check_at(client, Instant::now())
+    }
+
+    /// Like [`RateLimiter::check`], at a given time
+    pub fn check_at(&self, client: &str, now: Instant) -> Result<()> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+
+        if !buckets.contains_key(client) && buckets.len() >= MAX_TRACKED_CLIENTS {
+            // forget clients whose bucket has refilled completely
+            buckets.retain(|_, b| self.refilled(b, now) < self.burst);
+            if buckets.len() >= MAX_TRACKED_CLIENTS {
+                // every tracked client is still busy, turn new ones away
+                return Err(Error::RateLimited {
+                    retry_after: self.interval,
+                });
+            }
+        }
+
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+
+        let missing = 1.0 - bucket.tokens;
+        Err(Error::RateLimited {
+            retry_after: self.interval.mul_f64(missing),
+        })
+    }
+
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let earned = now.duration_since(bucket.updated).as_secs_f64() / self.interval.as_secs_f64();
+        (bucket.tokens + earned).min(self.burst)
+    }
+}
@@ -0,0 +1,250 @@
+//! Runtime health and statistics reported by `/status`
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::config::Identity;
+use crate::time::unix_millis;
+
+static STATUS: Lazy<Mutex<Stats>> = Lazy::new(|| Mutex::new(Stats::new()));
+
+/// State of a radio adapter as seen by the most recent scan attempt
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(tag = "state", content = "reason", rename_all = "camelCase")]
+pub enum AdapterState {
+    #[default]
+    Unknown, // no scan attempted yet
+    Available,
+    Unavailable(String),
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LastScan {
+    pub timestamp: u128, // in milliseconds since Unix epoch
+    pub duration_ms: u128,
+    pub access_points: usize,
+    pub beacons: usize,
+    pub cells: usize,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderStats {
+    pub succeeded: u64,
+    pub failed: u64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LastError {
+    pub provider: String,
+    pub message: String,
+    pub timestamp: u128, // in milliseconds since Unix epoch
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PeripheralState {
+    pub advertising: bool,
+    pub powered: Option<bool>, // None until the adapter reports its state
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Certificate {
+    pub fingerprint: String,     // hex encoded SHA256
+    pub expires_at: Option<i64>, // in seconds since Unix epoch
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Submissions {
+    pub providers: BTreeMap<String, ProviderStats>,
+    pub last_error: Option<LastError>,
+}
+
+/// JSON body returned by `/status`
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusReport {
+    pub version: &'static str,
+    pub uptime_secs: u64,
+    pub certificate: Option<Certificate>,
+    pub wifi: AdapterState,
+    pub bluetooth: AdapterState,
+    pub cellular: AdapterState,
+    pub last_scan: Option<LastScan>,
+    pub queue_depth: usize,
+    pub submissions: Submissions,
+    pub peripheral: PeripheralState,
+    pub dropped: BTreeMap<String, u64>, // devices kept out of uploads by the device rules, by reason
+}
+
+/// Everything `/status` reports, kept in one process-wide instance
+pub struct Stats {
+    started_at: Instant,
+    certificate: Option<Certificate>,
+    wifi: AdapterState,
+    bluetooth: AdapterState,
+    cellular: AdapterState,
+    last_scan: Option<LastScan>,
+    queue_depth: usize,
+    providers: BTreeMap<String, ProviderStats>,
+    last_error: Option<LastError>,
+    peripheral: PeripheralState,
+    dropped: BTreeMap<String, u64>,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Stats {
+            started_at: Instant::now(),
+            certificate: None,
+            wifi: AdapterState::Unknown,
+            bluetooth: AdapterState::Unknown,
+            cellular: AdapterState::Unknown,
+            last_scan: None,
+            queue_depth: 0,
+            providers: BTreeMap::new(),
+            last_error: None,
+            peripheral: PeripheralState::default(),
+            dropped: BTreeMap::new(),
+        }
+    }
+
+    /// Record the outcome of a submission to a geolocation provider
+    pub fn record_submission(&mut self, provider: &str, result: Result<(), String>) {
+        let counts = self.providers.entry(provider.to_string()).or_default();
+        match result {
+            Ok(()) => counts.succeeded += 1,
+            Err(message) => {
+                counts.failed += 1;
+                self.last_error = Some(LastError {
+                    provider: provider.to_string(),
+                    message,
+                    timestamp: unix_millis(),
+                });
+            }
+        }
+    }
+
+    /// Devices the device rules kept out of an upload
+    pub fn record_dropped(&mut self, reason: &str, count: usize) {
+        *self.dropped.entry(reason.to_string()).or_default() += count as u64;
+    }
+
+    pub fn queue_push(&mut self) {
+        self.queue_depth += 1;
+    }
+
+    pub fn queue_pop(&mut self) {
+        self.queue_depth = self.queue_depth.saturating_sub(1);
+    }
+
+    pub fn queue_depth(&self) -> usize {
+        self.queue_depth
+    }
+
+    pub fn report(&self) -> StatusReport {
+        StatusReport {
+            version: env!("CARGO_PKG_VERSION"),
+            uptime_secs: self.started_at.elapsed().as_secs(),
+            certificate: self.certificate.clone(),
+            wifi: self.wifi.clone(),
+            bluetooth: self.bluetooth.clone(),
+            cellular: self.cellular.clone(),
+            last_scan: self.last_scan.clone(),
+            queue_depth: self.queue_depth,
+            submissions: Submissions {
+                providers: self.providers.clone(),
+                last_error: self.last_error.clone(),
+            },
+            peripheral: self.peripheral.clone(),
+            dropped: self.dropped.clone(),
+        }
+    }
+}
+
+fn with_stats<T>(f: impl FnOnce(&mut Stats) -> T) -> T {
+    let mut stats = STATUS.lock().unwrap_or_else(|e| e.into_inner());
+    f(&mut stats)
+}
+
+/// Start the uptime clock and record the TLS identity being served
+pub fn init(identity: &Identity) {
+    with_stats(|stats| {
+        stats.started_at = Instant::now();
+        stats.certificate = Some(Certificate {
+            fingerprint: hex::encode(identity.certs_hash),
+            expires_at: identity.not_after,
+        });
+    });
+}
+
+pub fn record_wifi_adapter(state: AdapterState) {
+    with_stats(|stats| stats.wifi = state);
+}
+
+pub fn record_ble_adapter(state: AdapterState) {
+    with_stats(|stats| stats.bluetooth = state);
+}
+
+pub fn record_cell_adapter(state: AdapterState) {
+    with_stats(|stats| stats.cellular = state);
+}
+
+pub fn record_scan(scan: LastScan) {
+    with_stats(|stats| stats.last_scan = Some(scan));
+}
+
+/// Record the outcome of a submission to a geolocation provider
+pub fn record_submission(provider: &str, result: Result<(), String>) {
+    with_stats(|stats| stats.record_submission(provider, result));
+}
+
+/// Devices the device rules kept out of an upload
+pub fn record_dropped(dropped: &BTreeMap<&str, usize>) {
+    with_stats(|stats| {
+        for (reason, count) in dropped {
+            stats.record_dropped(reason, *count);
+        }
+    });
+}
+
+pub fn record_advertising(advertising: bool) {
+    with_stats(|stats| stats.peripheral.advertising = advertising);
+}
+
+pub fn record_powered(powered: bool) {
+    with_stats(|stats| stats.peripheral.powered = Some(powered));
+}
+
+/// A payload was queued for the submission worker
+pub fn queue_push() {
+    with_stats(Stats::queue_push);
+}
+
+/// The submission worker picked up a queued payload
+pub fn queue_pop() {
+    with_stats(Stats::queue_pop);
+}
+
+/// Payloads waiting for the submission worker
+pub fn queue_depth() -> usize {
+    with_stats(|stats| stats.queue_depth())
+}
+
+/// Snapshot the current status
+pub fn report() -> StatusReport {
+    with_stats(|stats| stats.report())
+}
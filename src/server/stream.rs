@@ -0,0 +1,230 @@
+//! Location updates streamed by phones, over `/location` and BLE
+//!
+//! Phones send one JSON position per line. While any phone is streaming, a
+//! background task scans on its own and holds on to the results until the
+//! sender's trajectory covers them, then archives and submits each scan
+//! with an interpolated position.
+
+use axum::Json;
+use axum::body::Body;
+use axum::extract::ConnectInfo;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio_stream::StreamExt;
+
+use crate::collect::runner::dispatch;
+use crate::collect::{Archive, Policy, Queue, Report};
+use crate::config::{
+    LOCATION_LINE_MAX_BYTES, STREAM_IDLE_MS, STREAM_SCAN_INTERVAL_SECS, TRAJECTORY_WINDOW_MS,
+};
+use crate::error::{Error, Result};
+use crate::geosubmit::Position;
+use crate::position::filter;
+use crate::position::trajectory::{self, Rejection, Sample};
+use crate::scanner::cache::{self, ScanKind};
+use crate::server::{metrics, rate_limit};
+use crate::time::unix_millis;
+
+// scans waiting for their client's trajectory to catch up with them
+static PENDING: Lazy<Mutex<Vec<(String, Report)>>> = Lazy::new(|| Mutex::new(Vec::new()));
+static SCANNING: AtomicBool = AtomicBool::new(false);
+// held while pending scans are positioned, so waiting ones are put back in order
+static GEOREFERENCING: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// One line of a location stream
+#[derive(Deserialize, Debug, Clone)]
+pub struct LocationUpdate {
+    pub timestamp: Option<u128>, // in milliseconds since Unix epoch, defaults to arrival minus age
+    #[serde(flatten)]
+    pub position: Position,
+}
+
+impl LocationUpdate {
+    /// The update as a sample, `received_at` being when it arrived
+    pub fn into_sample(self, received_at: u128) -> Sample {
+        let mut position = self.position;
+        let age = position.age.take(); // relative to arrival, meaningless once stored
+        let timestamp = self
+            .timestamp
+            .unwrap_or_else(|| received_at.saturating_sub(age.unwrap_or(0) as u128));
+        Sample {
+            timestamp,
+            position,
+        }
+    }
+}
+
+/// Splits a byte stream into lines, whatever the chunking
+#[derive(Debug, Default)]
+pub struct LineBuffer {
+    buffer: Vec<u8>,
+}
+
+impl LineBuffer {
+    /// Complete lines in `chunk` and whatever came before it.
+    /// A line longer than `LOCATION_LINE_MAX_BYTES` is dropped.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+        let mut lines = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line).trim().to_string();
+            if !line.is_empty() {
+                lines.push(line);
+            }
+        }
+        if self.buffer.len() > LOCATION_LINE_MAX_BYTES {
+            tracing::warn!("Dropping {} bytes without a line break", self.buffer.len());
+            self.buffer.clear();
+        }
+        lines
+    }
+}
+
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+pub struct StreamSummary {
+    pub accepted: usize,
+    pub rejected: usize,
+}
+
+/// Parse one line, filter it and add it to `client`'s trajectory
+pub fn record(client: &str, line: &str) -> Result<Sample> {
+    let update: LocationUpdate =
+        serde_json::from_str(line).map_err(|e| Error::Serialization(e.to_string()))?;
+    let mut sample = update.into_sample(unix_millis());
+    sample.position = filter::process(client, sample.timestamp, sample.position)
+        .map_err(Error::PositionRejected)?;
+    trajectory::record(client, sample.clone());
+    Ok(sample)
+}
+
+/// Record every line from one transport, counting the outcomes
+pub fn receive(transport: &str, client: &str, lines: Vec<String>, summary: &mut StreamSummary) {
+    let mut accepted = 0;
+    for line in lines {
+        match record(client, &line) {
+            Ok(_) => {
+                accepted += 1;
+                summary.accepted += 1;
+                metrics::LOCATION_UPDATES
+                    .with_label_values(&[transport, "accepted"])
+                    .inc();
+            }
+            Err(e) => {
+                summary.rejected += 1;
+                metrics::LOCATION_UPDATES
+                    .with_label_values(&[transport, "rejected"])
+                    .inc();
+                tracing::debug!("Rejected location update from {}: {}", client, e);
+            }
+        }
+    }
+    if accepted > 0 {
+        start_scanning();
+        tokio::spawn(georeference(false));
+    }
+}
+
+/// `POST /location`: a chunked body of newline-delimited positions
+pub async fn handle_location_stream(
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    body: Body,
+) -> Result<Json<StreamSummary>> {
+    let client = format!("http:{}", peer.ip());
+    rate_limit::STREAMS.check(&client)?;
+    let mut lines = LineBuffer::default();
+    let mut summary = StreamSummary::default();
+
+    let mut chunks = body.into_data_stream();
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk.map_err(|e| Error::Transport(e.to_string()))?;
+        receive("http", &client, lines.push(&chunk), &mut summary);
+    }
+    // the last line may lack its line break
+    receive("http", &client, lines.push(b"\n"), &mut summary);
+
+    Ok(Json(summary))
+}
+
+/// Run the background scanner unless it already is
+fn start_scanning() {
+    if !SCANNING.swap(true, Ordering::SeqCst) {
+        tokio::spawn(background_scans());
+    }
+}
+
+/// Scan periodically until every phone has stopped streaming
+async fn background_scans() {
+    tracing::info!("Location stream started, scanning in the background");
+    while trajectory::active(STREAM_IDLE_MS) {
+        let observations = cache::scan(ScanKind::All, Duration::ZERO).await;
+        let report = Report::new(observations, None);
+        // the phone that is streaming is the one carried along with the scanner
+        if !report.is_empty()
+            && let Some(client) = trajectory::latest_client()
+        {
+            PENDING
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push((client, report));
+        }
+        georeference(false).await;
+        tokio::time::sleep(Duration::from_secs(STREAM_SCAN_INTERVAL_SECS)).await;
+    }
+    SCANNING.store(false, Ordering::SeqCst);
+    georeference(true).await; // nothing more is coming for the last scans
+    tracing::info!("Location stream idle, background scanning stopped");
+}
+
+/// Position the pending scans their clients' trajectories now cover. Scans
+/// that can't be positioned are archived without a position, for a later GPX
+/// import. With `finished`, scans past the end of a trajectory stop waiting.
+pub async fn georeference(finished: bool) {
+    let guard = GEOREFERENCING.lock().await;
+    let pending = std::mem::take(&mut *PENDING.lock().unwrap_or_else(|e| e.into_inner()));
+    if pending.is_empty() {
+        return;
+    }
+
+    let expired = unix_millis().saturating_sub(TRAJECTORY_WINDOW_MS as u128);
+    let mut ready = Vec::new();
+    let mut waiting = Vec::new();
+    for (client, mut report) in pending {
+        match trajectory::locate(&client, report.timestamp) {
+            Ok(position) => {
+                report.position = Some(position);
+                ready.push(report);
+            }
+            Err(Rejection::AfterTrack) if !finished && report.timestamp >= expired => {
+                waiting.push((client, report))
+            }
+            Err(rejection) => {
+                tracing::info!(
+                    "Background scan {}, archiving without a position",
+                    rejection
+                );
+                ready.push(report);
+            }
+        }
+    }
+    PENDING
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .extend(waiting);
+    // taken from `PENDING`, these scans are ours alone to dispatch
+    drop(guard);
+
+    let (archive, queue) = (Archive::local(), Queue::local());
+    let policy = Policy::local();
+    for report in ready {
+        let positioned = report.position.is_some();
+        let outcome = dispatch(&report, &archive, &queue, policy, positioned).await;
+        if positioned {
+            tracing::info!("Background scan positioned from stream: {:?}", outcome);
+        }
+    }
+}
@@ -0,0 +1,11 @@
+//! Wall clock helpers
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Milliseconds since Unix epoch, 0 if the clock is set before it
+pub fn unix_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+}
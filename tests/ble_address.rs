@@ -0,0 +1,155 @@
+//! BLE address types and dropping rotating addresses
+
+use btleplug::api::AddressType as Bluez;
+use service_berry::BleDevice;
+use service_berry::collect::Report;
+use service_berry::collect::privacy::{DeviceRuleStore, DeviceRules, Dropped};
+use service_berry::scanner::address::{AddressType, RandomAddressPolicy};
+use service_berry::scanner::beacon::Advertisement;
+
+mod common;
+use common::{address, scratch_dir};
+
+fn device(mac: &str, address_type: Option<AddressType>) -> BleDevice {
+    let advertisement = Advertisement {
+        address_type,
+        ..Default::default()
+    };
+    BleDevice {
+        rssi: Some(-70),
+        ..BleDevice::advertised(address(mac), advertisement)
+    }
+}
+
+#[test]
+fn random_addresses_are_told_apart_by_their_top_bits() {
+    let classify = |mac: &str, kind| AddressType::classify(&address(mac), kind);
+    assert_eq!(
+        classify("D4:01:02:03:04:05", Bluez::Public),
+        Some(AddressType::Public)
+    );
+    assert_eq!(
+        classify("D4:01:02:03:04:05", Bluez::Random),
+        Some(AddressType::RandomStatic)
+    );
+    assert_eq!(
+        classify("4A:01:02:03:04:05", Bluez::Random),
+        Some(AddressType::ResolvablePrivate)
+    );
+    assert_eq!(
+        classify("1A:01:02:03:04:05", Bluez::Random),
+        Some(AddressType::NonResolvablePrivate)
+    );
+    assert_eq!(classify("9A:01:02:03:04:05", Bluez::Random), None); // reserved
+
+    assert!(AddressType::ResolvablePrivate.is_rotating());
+    assert!(!AddressType::RandomStatic.is_rotating());
+    assert_eq!(
+        serde_json::to_value(AddressType::NonResolvablePrivate).unwrap(),
+        "nrpa"
+    );
+    assert_eq!(
+        serde_json::to_value(device(
+            "4A:01:02:03:04:05",
+            Some(AddressType::ResolvablePrivate)
+        ))
+        .unwrap()["addressType"],
+        "rpa"
+    );
+}
+
+#[test]
+fn policies_choose_which_random_addresses_go() {
+    use AddressType::*;
+    let kinds = [
+        Public,
+        RandomStatic,
+        ResolvablePrivate,
+        NonResolvablePrivate,
+    ];
+    let dropped =
+        |policy: RandomAddressPolicy| kinds.iter().filter(|&&kind| policy.drops(kind)).count();
+    assert_eq!(dropped(RandomAddressPolicy::Keep), 0);
+    assert_eq!(dropped(RandomAddressPolicy::DropRotating), 2);
+    assert_eq!(dropped(RandomAddressPolicy::DropRandom), 3);
+    assert_eq!(
+        DeviceRules::default().random_addresses,
+        RandomAddressPolicy::DropRotating
+    );
+
+    let rules: DeviceRules = serde_json::from_str(r#"{"randomAddresses":"dropRandom"}"#).unwrap();
+    assert_eq!(rules.random_addresses, RandomAddressPolicy::DropRandom);
+    let rules: DeviceRules = serde_json::from_str(r#"{"vendors":[]}"#).unwrap(); // older file
+    assert_eq!(rules.random_addresses, RandomAddressPolicy::DropRotating);
+}
+
+#[test]
+fn rotating_addresses_are_dropped_and_counted() {
+    let mut report = Report {
+        timestamp: 1_748_779_200_000,
+        position: None,
+        wifi_access_points: Vec::new(),
+        bluetooth_beacons: vec![
+            device("D4:01:02:03:04:05", Some(AddressType::Public)),
+            device("D4:01:02:03:04:06", Some(AddressType::RandomStatic)),
+            device("4A:01:02:03:04:05", Some(AddressType::ResolvablePrivate)),
+            device("5B:01:02:03:04:05", Some(AddressType::ResolvablePrivate)),
+            device("1A:01:02:03:04:05", Some(AddressType::NonResolvablePrivate)),
+            device("1B:01:02:03:04:05", None), // archived before address types
+        ],
+        cell_towers: Vec::new(),
+    };
+    let store = DeviceRuleStore::open(scratch_dir("ble-address").join("devices.json"));
+
+    assert_eq!(
+        store.strip(&mut report.clone()),
+        Dropped::from([("nrpa", 1), ("rpa", 2)])
+    );
+
+    store
+        .set(DeviceRules {
+            random_addresses: RandomAddressPolicy::DropRandom,
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(store.strip(&mut report).values().sum::<usize>(), 4);
+    let kept: Vec<_> = report
+        .bluetooth_beacons
+        .iter()
+        .map(|b| b.mac_address.to_string())
+        .collect();
+    assert_eq!(kept, vec!["D4:01:02:03:04:05", "1B:01:02:03:04:05"]);
+}
+
+#[test]
+fn random_addresses_have_no_vendor() {
+    // a Google OUI, but randomly drawn by the device
+    let random = device("F4:F5:D8:01:02:03", Some(AddressType::RandomStatic));
+    assert_eq!(random.mac_info.vendor, None);
+    assert!(!random.mac_info.locally_administered);
+    let public = device("F4:F5:D8:01:02:03", Some(AddressType::Public));
+    assert_eq!(public.mac_info.vendor.as_deref(), Some("Google, Inc."));
+    // archived before address types
+    let unknown = device("F4:F5:D8:01:02:03", None);
+    assert_eq!(unknown.mac_info, public.mac_info);
+
+    let store = DeviceRuleStore::open(scratch_dir("ble-vendor").join("devices.json"));
+    store
+        .set(DeviceRules {
+            vendors: vec!["google".into()],
+            ..Default::default()
+        })
+        .unwrap();
+    let mut report = Report {
+        timestamp: 1_748_779_200_000,
+        position: None,
+        wifi_access_points: Vec::new(),
+        bluetooth_beacons: vec![random, public],
+        cell_towers: Vec::new(),
+    };
+    assert_eq!(store.strip(&mut report), Dropped::from([("vendor", 1)]));
+    assert_eq!(
+        report.bluetooth_beacons[0].advertisement.address_type,
+        Some(AddressType::RandomStatic)
+    );
+}
@@ -0,0 +1,191 @@
+//! Beacon frames in manufacturer and service data
+
+use std::collections::HashMap;
+
+use service_berry::BleDevice;
+use service_berry::collect::Report;
+use service_berry::collect::privacy::{DeviceRuleStore, DeviceRules, Dropped};
+use service_berry::geosubmit::BluetoothBeacon;
+use service_berry::scanner::beacon::{Advertisement, BeaconFrame};
+use uuid::Uuid;
+
+mod common;
+use common::scratch_dir;
+
+const EDDYSTONE: Uuid = Uuid::from_u128(0x0000feaa_0000_1000_8000_00805f9b34fb);
+
+fn eddystone(frame: &[u8]) -> Option<BeaconFrame> {
+    let data = HashMap::from([(EDDYSTONE, frame.to_vec())]);
+    BeaconFrame::from_service_data(&data).pop()
+}
+
+fn device(address: &str, beacons: Vec<BeaconFrame>) -> BleDevice {
+    BleDevice {
+        advertisement: Advertisement {
+            beacons,
+            ..Default::default()
+        },
+        ..common::device(address)
+    }
+}
+
+#[test]
+fn parses_ibeacon_and_altbeacon() {
+    let mut ibeacon = vec![0x02, 0x15];
+    ibeacon.extend(hex::decode("f7826da64fa24e988024bc5b71e0893e").unwrap());
+    ibeacon.extend([0x00, 0x2a, 0x01, 0x00, 0xc5]);
+    let mut altbeacon = vec![0xbe, 0xac];
+    altbeacon.extend(1..=20u8);
+    altbeacon.extend([0xbc, 0x00]);
+    let data = HashMap::from([
+        (0x004c, ibeacon.clone()),
+        (0x0118, altbeacon),
+        (0x0059, vec![0x01, 0x02]), // not a beacon
+    ]);
+
+    let frames = BeaconFrame::from_manufacturer_data(&data);
+    assert_eq!(
+        frames,
+        vec![
+            BeaconFrame::IBeacon {
+                uuid: "f7826da6-4fa2-4e98-8024-bc5b71e0893e".parse().unwrap(),
+                major: 42,
+                minor: 256,
+                tx_power: -59,
+            },
+            BeaconFrame::AltBeacon {
+                id: "0102030405060708090a0b0c0d0e0f1011121314".into(),
+                manufacturer: 0x0118,
+                reference_rssi: -68,
+            },
+        ]
+    );
+
+    // iBeacon layout under another company is not an iBeacon
+    let other = HashMap::from([(0x0006, ibeacon)]);
+    assert!(BeaconFrame::from_manufacturer_data(&other).is_empty());
+}
+
+#[test]
+fn parses_eddystone_frames() {
+    let mut uid = vec![0x00, 0xee];
+    uid.extend(1..=16u8);
+    assert_eq!(
+        eddystone(&uid),
+        Some(BeaconFrame::EddystoneUid {
+            namespace: "0102030405060708090a".into(),
+            instance: "0b0c0d0e0f10".into(),
+            tx_power: -18,
+        })
+    );
+    assert_eq!(
+        eddystone(b"\x10\xeb\x03goo.gl/abc"),
+        Some(BeaconFrame::EddystoneUrl {
+            url: "https://goo.gl/abc".into(),
+            tx_power: -21,
+        })
+    );
+    assert_eq!(
+        eddystone(b"\x10\xeb\x01example\x00"),
+        Some(BeaconFrame::EddystoneUrl {
+            url: "https://www.example.com/".into(),
+            tx_power: -21,
+        })
+    );
+    assert_eq!(eddystone(b"\x10\xeb\x07x"), None); // unknown scheme
+    assert_eq!(
+        eddystone(&[
+            0x20, 0x00, 0x0b, 0xb8, 0x17, 0x80, 0, 0, 0x01, 0x00, 0, 0, 0x0e, 0x10
+        ]),
+        Some(BeaconFrame::EddystoneTlm {
+            battery: Some(3000),
+            temperature: Some(23.5),
+            advertisements: 256,
+            uptime: 3600,
+        })
+    );
+    assert_eq!(
+        eddystone(&[0x20, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+        None
+    ); // encrypted
+    assert_eq!(
+        eddystone(&[0x30, 0xf0, 1, 2, 3, 4, 5, 6, 7, 8]),
+        Some(BeaconFrame::EddystoneEid {
+            eid: "0102030405060708".into(),
+            tx_power: -16,
+        })
+    );
+}
+
+#[test]
+fn frames_are_archived_but_not_submitted() {
+    let mut beacon = device(
+        "D4:01:02:03:04:05",
+        vec![BeaconFrame::EddystoneEid {
+            eid: "0102030405060708".into(),
+            tx_power: -16,
+        }],
+    );
+    beacon.advertisement.tx_power = Some(4);
+    beacon.advertisement.service_uuids = vec![EDDYSTONE];
+
+    let archived = serde_json::to_value(&beacon).unwrap();
+    assert_eq!(archived["txPower"], 4);
+    assert_eq!(
+        archived["serviceUuids"][0],
+        "0000feaa-0000-1000-8000-00805f9b34fb"
+    );
+    assert_eq!(archived["beacons"][0]["type"], "eddystoneEid");
+    assert_eq!(archived["beacons"][0]["txPower"], -16);
+    let parsed: BleDevice = serde_json::from_value(archived).unwrap();
+    assert_eq!(parsed.advertisement, beacon.advertisement);
+
+    let submitted = serde_json::to_value(BluetoothBeacon::from(&beacon)).unwrap();
+    for field in ["txPower", "serviceUuids", "beacons"] {
+        assert!(submitted.get(field).is_none(), "{} submitted", field);
+    }
+}
+
+#[test]
+fn can_submit_only_stationary_beacons() {
+    let ibeacon = BeaconFrame::IBeacon {
+        uuid: Uuid::nil(),
+        major: 1,
+        minor: 2,
+        tx_power: -59,
+    };
+    let tlm = BeaconFrame::EddystoneTlm {
+        battery: None,
+        temperature: None,
+        advertisements: 1,
+        uptime: 1,
+    };
+    let mut report = Report {
+        timestamp: 1_748_779_200_000,
+        position: None,
+        wifi_access_points: Vec::new(),
+        bluetooth_beacons: vec![
+            device("C0:00:00:00:00:01", vec![tlm.clone(), ibeacon]),
+            device("C0:00:00:00:00:02", vec![tlm]),
+            device("C0:00:00:00:00:03", Vec::new()), // a phone, say
+        ],
+        cell_towers: Vec::new(),
+    };
+
+    let store = DeviceRuleStore::open(scratch_dir("ble-beacons").join("devices.json"));
+    assert!(store.strip(&mut report.clone()).is_empty());
+    store
+        .set(DeviceRules {
+            stationary_beacons_only: true,
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(
+        store.strip(&mut report),
+        Dropped::from([("notStationary", 2)])
+    );
+    assert_eq!(
+        report.bluetooth_beacons[0].mac_address.to_string(),
+        "C0:00:00:00:00:01"
+    );
+}
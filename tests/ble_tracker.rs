@@ -0,0 +1,83 @@
+//! Advertisements kept by the background BLE scan
+
+use service_berry::BleDevice;
+use service_berry::config::{BLE_MAX_SIGHTINGS, BLE_RETENTION_MS};
+use service_berry::scanner::bluetooth::Tracker;
+
+mod common;
+
+fn heard(address: &str, rssi: Option<i16>) -> BleDevice {
+    BleDevice {
+        rssi,
+        ..common::device(address)
+    }
+}
+
+#[test]
+fn snapshots_hold_recent_devices_as_last_heard() {
+    let mut tracker = Tracker::new();
+    tracker.record(heard("D4:01:02:03:04:05", Some(-80)), 1_000);
+    tracker.record(heard("C0:0A:0B:0C:0D:0E", Some(-60)), 4_000);
+    let mut renamed = heard("D4:01:02:03:04:05", Some(-72));
+    renamed.name = Some("Thermometer".into());
+    tracker.record(renamed, 9_000);
+    assert_eq!(tracker.len(), 2);
+
+    let devices = tracker.snapshot(12_000, 5_000);
+    assert_eq!(devices.len(), 1); // C0:.. was last heard 8 s ago
+    assert_eq!(devices[0].rssi, Some(-72));
+    assert_eq!(devices[0].name.as_deref(), Some("Thermometer"));
+    assert_eq!(devices[0].age, Some(3_000));
+
+    let devices = tracker.snapshot(12_000, 10_000);
+    let addresses: Vec<_> = devices.iter().map(|d| d.mac_address.to_string()).collect();
+    assert_eq!(addresses, vec!["C0:0A:0B:0C:0D:0E", "D4:01:02:03:04:05"]);
+}
+
+#[test]
+fn windows_combine_advertisements_since_they_began() {
+    let mut tracker = Tracker::new();
+    tracker.record(heard("D4:01:02:03:04:05", Some(-90)), 500); // before the window
+    tracker.record(heard("D4:01:02:03:04:05", Some(-70)), 1_500);
+    tracker.record(heard("D4:01:02:03:04:05", Some(-74)), 2_500);
+    tracker.record(heard("D4:01:02:03:04:05", Some(-78)), 3_500);
+    tracker.record(heard("C0:0A:0B:0C:0D:0E", Some(-60)), 2_000); // heard once
+
+    let devices = tracker.window(1_000, 4_000);
+    assert_eq!(devices.len(), 1);
+    let samples = devices[0].samples.unwrap();
+    assert_eq!(samples.count, 3);
+    assert_eq!(samples.median_rssi, -74);
+    assert_eq!(samples.max_rssi, -70);
+    assert_eq!((samples.first_seen, samples.last_seen), (1_500, 3_500));
+    assert_eq!(devices[0].age, Some(500));
+}
+
+#[test]
+fn events_for_one_advertisement_are_one_sighting() {
+    let mut tracker = Tracker::new();
+    tracker.record(heard("D4:01:02:03:04:05", None), 1_000); // manufacturer data
+    tracker.record(heard("D4:01:02:03:04:05", Some(-66)), 1_004); // its RSSI
+    tracker.record(heard("D4:01:02:03:04:05", Some(-70)), 1_100);
+
+    let devices = tracker.window(0, 2_000);
+    assert_eq!(devices[0].samples.unwrap().count, 2);
+    assert_eq!(devices[0].samples.unwrap().max_rssi, -66);
+}
+
+#[test]
+fn old_advertisements_are_forgotten() {
+    let mut tracker = Tracker::new();
+    for i in 0..BLE_MAX_SIGHTINGS as u128 + 10 {
+        tracker.record(heard("D4:01:02:03:04:05", Some(-70)), 1_000 + i * 100);
+    }
+    let all = tracker.window(0, u128::MAX);
+    assert_eq!(all[0].samples.unwrap().count as usize, BLE_MAX_SIGHTINGS);
+    tracker.record(heard("C0:0A:0B:0C:0D:0E", Some(-60)), 500);
+
+    let now = 1_000 + BLE_RETENTION_MS as u128;
+    tracker.prune(now);
+    assert_eq!(tracker.len(), 1);
+    tracker.prune(now + 1_000_000);
+    assert!(tracker.is_empty());
+}
@@ -0,0 +1,34 @@
+//! Submissions written to the GATT characteristic in pieces
+
+use service_berry::peripheral::gatt::take_payloads;
+
+#[test]
+fn each_line_is_parsed_once_and_partial_lines_kept() {
+    let mut buffer = Vec::new();
+    assert!(take_payloads(&mut buffer, br#"{"position":{"latitude":1"#).is_empty());
+
+    // a complete payload and the start of the next in one write
+    let payloads = take_payloads(&mut buffer, b"}}\n{\"position\":");
+    assert_eq!(payloads.len(), 1);
+    assert_eq!(payloads[0].position["latitude"], 1);
+    assert_eq!(buffer, br#"{"position":"#);
+
+    let payloads = take_payloads(&mut buffer, b"{\"latitude\":2}}\n");
+    assert_eq!(payloads[0].position["latitude"], 2);
+    assert!(buffer.is_empty());
+}
+
+#[test]
+fn broken_lines_are_not_kept_around() {
+    let mut buffer = Vec::new();
+    assert!(take_payloads(&mut buffer, b"not json\n{\"position\"").is_empty());
+    assert_eq!(buffer, br#"{"position""#);
+
+    let payloads = take_payloads(&mut buffer, b":{}}\n");
+    assert_eq!(payloads.len(), 1);
+    assert!(buffer.is_empty());
+
+    // a whole payload without a line break, as older apps send it
+    assert_eq!(take_payloads(&mut buffer, br#"{"position":{}}"#).len(), 1);
+    assert!(buffer.is_empty());
+}
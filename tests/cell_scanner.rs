@@ -0,0 +1,296 @@
+//! Cell scanner tests against a mock ModemManager on a private bus
+//!
+//! Each test starts its own `dbus-daemon` and is skipped when it isn't installed.
+
+use dbus::arg::{RefArg, Variant};
+use dbus::blocking::Connection;
+use dbus::channel::Channel;
+use dbus::{MethodErr, Path};
+use dbus_crossroads::Crossroads;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+use service_berry::geosubmit::RadioType;
+use service_berry::scanner::cell::{
+    MM_LOCATION, MM_MODEM, MM_PATH, MM_SERVICE, MM_SIGNAL, parse_3gpp_location, read_cell_towers_at,
+};
+
+const BUS_CONFIG: &str = r#"<!DOCTYPE busconfig PUBLIC "-//freedesktop//DTD D-Bus Bus Configuration 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/busconfig.dtd">
+<busconfig>
+  <type>session</type>
+  <listen>unix:tmpdir=/tmp</listen>
+  <auth>EXTERNAL</auth>
+  <policy context="default">
+    <allow send_destination="*" eavesdrop="true"/>
+    <allow receive_sender="*"/>
+    <allow eavesdrop="true"/>
+    <allow own="*"/>
+  </policy>
+</busconfig>
+"#;
+
+/// Private bus daemon, killed on drop
+struct Bus {
+    daemon: Child,
+    address: String,
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
+
+fn start_bus() -> Option<Bus> {
+    let config = std::env::temp_dir().join(format!("serviceberry-bus-{}.conf", std::process::id()));
+    std::fs::write(&config, BUS_CONFIG).unwrap();
+
+    let mut daemon = match Command::new("dbus-daemon")
+        .arg(format!("--config-file={}", config.display()))
+        .args(["--nofork", "--print-address"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+    {
+        Ok(daemon) => daemon,
+        Err(e) => {
+            eprintln!("skipping, dbus-daemon not available: {}", e);
+            return None;
+        }
+    };
+
+    let mut address = String::new();
+    BufReader::new(daemon.stdout.take().unwrap())
+        .read_line(&mut address)
+        .unwrap();
+    Some(Bus {
+        daemon,
+        address: address.trim().to_string(),
+    })
+}
+
+#[derive(Clone, Default)]
+struct MockModem {
+    access_technologies: u32,
+    lac_ci: Option<&'static str>, // 3GPP location string, "MCC,MNC,LAC,CI,TAC"
+    signal: HashMap<&'static str, HashMap<String, f64>>, // Signal property -> values
+    cell_info: Option<Vec<MockCell>>, // None when GetCellInfo is unsupported
+}
+
+#[derive(Clone)]
+struct MockCell {
+    cell_type: u32,
+    serving: bool,
+    strings: Vec<(&'static str, &'static str)>,
+    numbers: Vec<(&'static str, u32)>,
+    dbm: Vec<(&'static str, f64)>,
+}
+
+impl MockCell {
+    fn to_dict(&self) -> HashMap<String, Variant<Box<dyn RefArg>>> {
+        let mut dict: HashMap<String, Variant<Box<dyn RefArg>>> = HashMap::new();
+        dict.insert("cell-type".into(), Variant(Box::new(self.cell_type)));
+        dict.insert("serving".into(), Variant(Box::new(self.serving)));
+        for (key, value) in &self.strings {
+            dict.insert(key.to_string(), Variant(Box::new(value.to_string())));
+        }
+        for (key, value) in &self.numbers {
+            dict.insert(key.to_string(), Variant(Box::new(*value)));
+        }
+        for (key, value) in &self.dbm {
+            dict.insert(key.to_string(), Variant(Box::new(*value)));
+        }
+        dict
+    }
+}
+
+/// Serve a fake ModemManager with one object per modem on `bus`
+fn serve_modem_manager(bus: &Bus, modems: Vec<MockModem>) {
+    let mut channel = Channel::open_private(&bus.address).unwrap();
+    channel.register().unwrap();
+    let conn = Connection::from(channel);
+    conn.request_name(MM_SERVICE, false, true, false).unwrap();
+
+    std::thread::spawn(move || {
+        let mut cr = Crossroads::new();
+        let modem = cr.register(MM_MODEM, |b| {
+            b.property("AccessTechnologies")
+                .get(|_, m: &mut MockModem| Ok(m.access_technologies));
+            b.method(
+                "GetCellInfo",
+                (),
+                ("cell_info",),
+                |_, m: &mut MockModem, ()| match &m.cell_info {
+                    Some(cells) => Ok((cells.iter().map(MockCell::to_dict).collect::<Vec<_>>(),)),
+                    None => Err(MethodErr::no_method("GetCellInfo")),
+                },
+            );
+        });
+        let location = cr.register(MM_LOCATION, |b| {
+            b.property("Enabled").get(|_, _: &mut MockModem| Ok(1u32));
+            b.method(
+                "Setup",
+                ("sources", "signal_location"),
+                (),
+                |_, _: &mut MockModem, (_, _): (u32, bool)| Ok(()),
+            );
+            b.method(
+                "GetLocation",
+                (),
+                ("location",),
+                |_, m: &mut MockModem, ()| {
+                    let mut location = HashMap::new();
+                    if let Some(lac_ci) = m.lac_ci {
+                        location.insert(1u32, Variant(lac_ci.to_string()));
+                    }
+                    Ok((location,))
+                },
+            );
+        });
+        let signal = cr.register(MM_SIGNAL, |b| {
+            b.property("Rate").get(|_, _: &mut MockModem| Ok(5u32));
+            for name in ["Lte", "Nr5g", "Umts", "Gsm", "Cdma"] {
+                b.property(name).get(move |_, m: &mut MockModem| {
+                    let values = m.signal.get(name).cloned().unwrap_or_default();
+                    Ok(values
+                        .into_iter()
+                        .map(|(k, v)| (k, Variant(v)))
+                        .collect::<HashMap<_, _>>())
+                });
+            }
+        });
+
+        let object_manager = cr.object_manager();
+        cr.insert(MM_PATH, &[object_manager], MockModem::default());
+        for (i, m) in modems.into_iter().enumerate() {
+            let path = Path::from(format!("{}/Modem/{}", MM_PATH, i));
+            cr.insert(path, &[modem, location, signal], m);
+        }
+        let _ = cr.serve(&conn); // returns once the bus goes away
+    });
+}
+
+fn read_cells(modems: Vec<MockModem>) -> Option<Vec<service_berry::CellTower>> {
+    let bus = start_bus()?;
+    serve_modem_manager(&bus, modems);
+    std::thread::sleep(Duration::from_millis(100)); // let the mock claim its name
+    Some(read_cell_towers_at(&bus.address).expect("reading cells failed"))
+}
+
+fn lte_signal(rsrp: f64) -> HashMap<&'static str, HashMap<String, f64>> {
+    HashMap::from([("Lte", HashMap::from([("rsrp".to_string(), rsrp)]))])
+}
+
+#[test]
+fn serving_and_neighbour_cells_from_cell_info() {
+    let modem = MockModem {
+        access_technologies: 1 << 14, // LTE
+        lac_ci: Some("262,01,FFFE,1A2B3C4,00BEEF"),
+        signal: lte_signal(-97.4),
+        cell_info: Some(vec![
+            MockCell {
+                cell_type: 5,
+                serving: true,
+                strings: vec![
+                    ("operator-id", "26201"),
+                    ("tac", "BEEF"),
+                    ("ci", "1A2B3C4"),
+                    ("physical-ci", "1F"),
+                ],
+                numbers: vec![("earfcn", 1300), ("timing-advance", 3)],
+                dbm: vec![],
+            },
+            MockCell {
+                cell_type: 5,
+                serving: false,
+                strings: vec![
+                    ("operator-id", "26201"),
+                    ("tac", "BEEF"),
+                    ("ci", "1A2B3C5"),
+                    ("physical-ci", "20"),
+                ],
+                numbers: vec![("earfcn", 1300)],
+                dbm: vec![("rsrp", -110.0)],
+            },
+            MockCell {
+                cell_type: 4, // TD-SCDMA has no geosubmit radio type
+                serving: false,
+                strings: vec![("operator-id", "26201"), ("lac", "1"), ("ci", "2")],
+                numbers: vec![],
+                dbm: vec![],
+            },
+        ]),
+    };
+    let Some(cells) = read_cells(vec![modem]) else {
+        return;
+    };
+
+    assert_eq!(cells.len(), 2);
+    let serving = &cells[0];
+    assert_eq!(serving.radioType, Some(RadioType::Lte));
+    assert_eq!(serving.mobileCountryCode, 262);
+    assert_eq!(serving.mobileNetworkCode, 1);
+    assert_eq!(serving.locationAreaCode, 0xBEEF);
+    assert_eq!(serving.cellId, 0x1A2B3C4);
+    assert_eq!(serving.psc, Some(0x1F));
+    assert_eq!(serving.arfcn, Some(1300));
+    assert_eq!(serving.timingAdvance, Some(3));
+    assert_eq!(serving.serving, Some(1));
+    assert_eq!(serving.signalStrength, Some(-97)); // filled in from the Signal interface
+
+    let neighbour = &cells[1];
+    assert_eq!(neighbour.serving, Some(0));
+    assert_eq!(neighbour.signalStrength, Some(-110));
+    assert!(cells.iter().all(|c| c.validate().is_ok()));
+}
+
+#[test]
+fn serving_cell_from_location_without_cell_info() {
+    let umts = MockModem {
+        access_technologies: 1 << 5, // UMTS
+        lac_ci: Some("310,410,2D3A,5B2F01,0"),
+        signal: HashMap::from([("Umts", HashMap::from([("rscp".to_string(), -88.0)]))]),
+        cell_info: None,
+    };
+    let idle = MockModem {
+        access_technologies: 0,
+        lac_ci: None,
+        ..Default::default()
+    };
+    let Some(cells) = read_cells(vec![umts, idle]) else {
+        return;
+    };
+
+    assert_eq!(cells.len(), 1);
+    let cell = &cells[0];
+    assert_eq!(cell.radioType, Some(RadioType::Wcdma));
+    assert_eq!(cell.mobileCountryCode, 310);
+    assert_eq!(cell.mobileNetworkCode, 410);
+    assert_eq!(cell.locationAreaCode, 0x2D3A);
+    assert_eq!(cell.cellId, 0x5B2F01);
+    assert_eq!(cell.signalStrength, Some(-88));
+}
+
+#[test]
+fn no_modem_manager_is_an_error() {
+    let Some(bus) = start_bus() else {
+        return;
+    };
+    assert!(read_cell_towers_at(&bus.address).is_err());
+}
+
+#[test]
+fn location_string_uses_tac_for_lte() {
+    let cell = parse_3gpp_location("234,15,0,ABCDEF,1F40", Some(RadioType::Lte)).unwrap();
+    assert_eq!(cell.locationAreaCode, 0x1F40);
+    assert_eq!(cell.cellId, 0xABCDEF);
+
+    let cell = parse_3gpp_location("234,15,1F3,ABCD,0", Some(RadioType::Gsm)).unwrap();
+    assert_eq!(cell.locationAreaCode, 0x1F3);
+
+    assert!(parse_3gpp_location("234,15", None).is_none());
+}
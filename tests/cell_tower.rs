@@ -0,0 +1,76 @@
+//! Per-radio validation of cell tower observations
+
+use service_berry::geosubmit::{CellTower, RadioType};
+
+fn cell(radio: RadioType, area_code: u32, cell_id: u64) -> CellTower {
+    CellTower {
+        radioType: Some(radio),
+        mobileCountryCode: 302,
+        mobileNetworkCode: 720,
+        locationAreaCode: area_code,
+        cellId: cell_id,
+        age: None,
+        asu: None,
+        psc: None,
+        serving: Some(1),
+        signalStrength: Some(-95),
+        timingAdvance: None,
+        arfcn: None,
+    }
+}
+
+#[test]
+fn nr_accepts_36_bit_cell_ids() {
+    let nci = (1u64 << 36) - 1;
+    assert!(cell(RadioType::Nr, 0x0A_BCDE, nci).validate().is_ok());
+    assert!(cell(RadioType::Nr, 0x0A_BCDE, nci + 1).validate().is_err());
+}
+
+#[test]
+fn lte_rejects_wide_cell_ids_and_pci() {
+    assert!(cell(RadioType::Lte, 4100, 1 << 28).validate().is_err());
+
+    let mut lte = cell(RadioType::Lte, 4100, 0x0123_4567);
+    lte.psc = Some(503);
+    assert!(lte.validate().is_ok());
+    lte.psc = Some(504);
+    assert!(lte.validate().is_err());
+}
+
+#[test]
+fn gsm_rejects_reserved_area_codes() {
+    assert!(cell(RadioType::Gsm, 65534, 1234).validate().is_err());
+    assert!(cell(RadioType::Gsm, 0, 1234).validate().is_err());
+    assert!(cell(RadioType::Gsm, 1, 1234).validate().is_ok());
+}
+
+#[test]
+fn country_code_is_checked_for_every_radio() {
+    let mut cdma = cell(RadioType::Cdma, 5, 17);
+    cdma.mobileNetworkCode = 4100; // SID
+    assert!(cdma.validate().is_ok());
+    cdma.mobileCountryCode = 1000;
+    assert!(cdma.validate().is_err());
+}
+
+#[test]
+fn unknown_radio_type_is_an_error() {
+    let mut tower = cell(RadioType::Gsm, 1, 1);
+    assert!(tower.set_radio_type("NR").is_ok());
+    assert_eq!(tower.radioType, Some(RadioType::Nr));
+    assert!(tower.set_radio_type("tdscdma").is_err());
+    assert_eq!(tower.radioType, Some(RadioType::Nr));
+}
+
+#[test]
+fn psc_alias_deserializes() {
+    let json = r#"{"radioType":"wcdma","mobileCountryCode":208,"mobileNetworkCode":1,
+        "locationAreaCode":2,"cellId":123456,"psc":300,"arfcn":10700}"#;
+    let tower: CellTower = serde_json::from_str(json).unwrap();
+    assert_eq!(tower.psc, Some(300));
+    assert!(tower.validate().is_ok());
+    assert_eq!(
+        serde_json::to_value(&tower).unwrap()["primaryScramblingCode"],
+        300
+    );
+}
@@ -0,0 +1,199 @@
+//! Collect mode: option parsing, scan pacing and the archive/queue pipeline
+
+use std::time::Duration;
+
+use service_berry::collect::runner::{
+    CollectOptions, Outcome, PositionProvider, dispatch, next_interval,
+};
+use service_berry::collect::{Archive, Queue};
+use service_berry::config::{COLLECT_MAX_INTERVAL_SECS, COLLECT_MIN_INTERVAL_SECS};
+use service_berry::geosubmit::{Position, PositionSource};
+use service_berry::position::nmea::NmeaSource;
+use service_berry::{CellTower, Error};
+
+mod common;
+use common::{policy, report, scratch_dir};
+
+fn args(list: &[&str]) -> Vec<String> {
+    list.iter().map(|s| s.to_string()).collect()
+}
+
+fn position() -> Position {
+    Position {
+        latitude: 52.5163,
+        longitude: 13.3777,
+        accuracy: Some(5.0),
+        source: Some(PositionSource::Gps),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn options_default_to_local_gpsd() {
+    let options = CollectOptions::from_args(args(&[])).unwrap();
+    assert!(
+        matches!(options.provider, Some(PositionProvider::Gpsd(ref a)) if a == "127.0.0.1:2947")
+    );
+    assert!(options.submit);
+
+    let options = CollectOptions::from_args(args(&["--no-position"])).unwrap();
+    assert!(options.provider.is_none());
+}
+
+#[test]
+fn options_select_nmea_serial_and_baud_in_any_order() {
+    let options = CollectOptions::from_args(args(&[
+        "--nmea",
+        "/dev/ttyACM0",
+        "--baud",
+        "38400",
+        "--no-submit",
+    ]))
+    .unwrap();
+    match options.provider {
+        Some(PositionProvider::Nmea(NmeaSource::Serial { path, baud_rate })) => {
+            assert_eq!(path, "/dev/ttyACM0");
+            assert_eq!(baud_rate, 38400);
+        }
+        other => panic!("unexpected provider {:?}", other),
+    }
+    assert!(!options.submit);
+
+    assert!(CollectOptions::from_args(args(&["--gpsd"])).is_err());
+    assert!(CollectOptions::from_args(args(&["--fast"])).is_err());
+}
+
+#[test]
+fn interval_shrinks_with_speed() {
+    let max = Duration::from_secs(COLLECT_MAX_INTERVAL_SECS);
+    let min = Duration::from_secs(COLLECT_MIN_INTERVAL_SECS);
+
+    assert_eq!(next_interval(None), max);
+    assert_eq!(next_interval(Some(0.1)), max); // standing still
+    assert_eq!(
+        next_interval(Some(1.4)),
+        Duration::from_secs_f64(50.0 / 1.4)
+    ); // walking
+    assert!(next_interval(Some(13.9)) < next_interval(Some(5.0))); // driving vs cycling
+    assert_eq!(next_interval(Some(70.0)), min);
+}
+
+#[test]
+fn reports_need_a_position_to_be_submitted() {
+    assert!(report(None).to_items().is_none());
+
+    let mut positioned = report(Some(position()));
+    positioned.cell_towers.push(CellTower {
+        radioType: None,
+        mobileCountryCode: 0, // out of range, dropped from the upload
+        mobileNetworkCode: 1,
+        locationAreaCode: 1,
+        cellId: 1,
+        age: None,
+        asu: None,
+        psc: None,
+        serving: None,
+        signalStrength: None,
+        timingAdvance: None,
+        arfcn: None,
+    });
+    let items = positioned.to_items().unwrap();
+    assert_eq!(items.wifiAccessPoints.len(), 1);
+    assert!(items.cellTowers.is_none());
+}
+
+#[tokio::test]
+async fn dispatch_archives_without_submitting() {
+    let dir = scratch_dir("dispatch");
+    let archive = Archive::open(dir.join("archive.jsonl"));
+    let queue = Queue::open(dir.join("queue.jsonl"));
+    let policy = policy(&dir);
+
+    let outcome = dispatch(&report(None), &archive, &queue, policy, true).await;
+    assert_eq!(outcome, Outcome::Archived);
+    let outcome = dispatch(&report(Some(position())), &archive, &queue, policy, false).await;
+    assert_eq!(outcome, Outcome::Archived);
+
+    let archived = archive.read().unwrap();
+    assert_eq!(archived.len(), 2);
+    assert!(archived[0].position.is_none());
+    assert_eq!(
+        archived[1].wifi_access_points[0].ssid.as_deref(),
+        Some("Dragon")
+    );
+    assert!(queue.pending().unwrap().is_empty());
+}
+
+#[test]
+fn archive_skips_corrupt_lines() {
+    let dir = scratch_dir("archive");
+    let archive = Archive::open(dir.join("archive.jsonl"));
+    assert!(archive.read().unwrap().is_empty()); // missing file
+
+    archive.append(&report(None)).unwrap();
+    std::fs::write(
+        archive.path(),
+        std::fs::read_to_string(archive.path()).unwrap() + "{\"truncated\n",
+    )
+    .unwrap();
+    archive.append(&report(Some(position()))).unwrap();
+
+    assert_eq!(archive.read().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn flushing_drops_rejected_reports_and_keeps_the_rest() {
+    let path = scratch_dir("queue").join("queue.jsonl");
+    let queue = Queue::open(&path);
+    let queued = |timestamp| {
+        let mut items = report(Some(position())).to_items().unwrap();
+        items.timestamp = timestamp;
+        items
+    };
+    std::fs::write(&path, "{\"truncated\n").unwrap();
+    queue.push(&queued(1)).unwrap();
+    queue.push(&queued(2)).unwrap();
+    queue.push(&queued(3)).unwrap();
+    queue.push(&queued(4)).unwrap();
+
+    let submitted = queue
+        .flush_with(|items| async move {
+            match items.timestamp {
+                1 => Ok(()),
+                2 => Err(Error::HttpStatus {
+                    status: 400,
+                    body: String::new(),
+                }),
+                _ => Err(Error::Transport("offline".into())),
+            }
+        })
+        .await
+        .unwrap();
+    assert_eq!(submitted, 1);
+
+    let timestamps: Vec<_> = queue
+        .pending()
+        .unwrap()
+        .iter()
+        .map(|i| i.timestamp)
+        .collect();
+    assert_eq!(timestamps, [3, 4]); // 2 was rejected for good
+    assert!(
+        std::fs::read_to_string(&path)
+            .unwrap()
+            .starts_with("{\"truncated\n")
+    );
+}
+
+#[test]
+fn only_transient_failures_are_retried() {
+    let status = |status| Error::HttpStatus {
+        status,
+        body: String::new(),
+    };
+    assert!(Error::Transport("timed out".into()).is_transient());
+    assert!(status(503).is_transient());
+    assert!(status(429).is_transient());
+    assert!(!status(400).is_transient());
+    assert!(!status(413).is_transient());
+}
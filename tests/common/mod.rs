@@ -0,0 +1,67 @@
+//! Helpers shared by the integration tests, not every test uses all of them
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+
+use btleplug::api::BDAddr;
+use service_berry::collect::privacy::DeviceRuleStore;
+use service_berry::collect::{MobileStore, Policy, Report, ZoneStore};
+use service_berry::geosubmit::Position;
+use service_berry::scanner::wifi::PhyType;
+use service_berry::{BleDevice, WifiBssid};
+
+pub const TIMESTAMP: u128 = 1_748_779_200_000; // 2025-06-01T12:00:00Z
+
+/// An empty directory for this test run
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("serviceberry-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Stores in `dir`, kept for the rest of the run like the local ones
+pub fn policy(dir: &Path) -> Policy<'static> {
+    Policy {
+        zones: Box::leak(Box::new(ZoneStore::open(dir.join("zones.json")))),
+        mobiles: Box::leak(Box::new(MobileStore::open(dir.join("mobile.json")))),
+        rules: Box::leak(Box::new(DeviceRuleStore::open(dir.join("devices.json")))),
+    }
+}
+
+pub fn address(s: &str) -> BDAddr {
+    s.parse().unwrap()
+}
+
+/// An 802.11ac access point on channel 36
+pub fn wifi(bssid: &str) -> WifiBssid {
+    WifiBssid {
+        channel: Some(36),
+        phy: PhyType::Vht,
+        rssi: -60,
+        ..WifiBssid::new(address(bssid), 5180)
+    }
+}
+
+/// A device heard at -70 dBm
+pub fn device(mac: &str) -> BleDevice {
+    BleDevice {
+        rssi: Some(-70),
+        ..BleDevice::new(address(mac))
+    }
+}
+
+/// A report of one access point named Dragon
+pub fn report(position: Option<Position>) -> Report {
+    Report {
+        timestamp: TIMESTAMP,
+        position,
+        wifi_access_points: vec![WifiBssid {
+            ssid: Some("Dragon".into()),
+            age: Some(1200),
+            ..wifi("82:27:F5:62:2B:4B")
+        }],
+        bluetooth_beacons: Vec::new(),
+        cell_towers: Vec::new(),
+    }
+}
@@ -0,0 +1,59 @@
+//! Live events served on `/events`
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use service_berry::server::create_router;
+use service_berry::server::events::{self, Event, PairingState};
+use std::time::Duration;
+use tokio_stream::StreamExt;
+use tower::ServiceExt;
+
+#[test]
+fn event_names_match_the_json_type() {
+    let samples = [
+        Event::WifiScan {
+            timestamp: 1,
+            access_points: Vec::new(),
+        },
+        Event::BleScan {
+            timestamp: 1,
+            beacons: Vec::new(),
+        },
+        Event::Submission {
+            provider: "beacondb".into(),
+            status: Some(200),
+            error: None,
+        },
+        Event::Pairing {
+            client: "ble:1".into(),
+            state: PairingState::Subscribed,
+        },
+    ];
+    for event in samples {
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], event.name());
+    }
+}
+
+#[tokio::test]
+async fn listeners_receive_published_events() {
+    let request = Request::get("/events").body(Body::empty()).unwrap();
+    let response = create_router().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+
+    events::publish(Event::Pairing {
+        client: "ble:7".into(),
+        state: PairingState::PayloadReceived,
+    });
+
+    let mut body = response.into_body().into_data_stream();
+    let chunk = tokio::time::timeout(Duration::from_secs(1), body.next())
+        .await
+        .expect("no event within a second")
+        .unwrap()
+        .unwrap();
+    let text = String::from_utf8(chunk.to_vec()).unwrap();
+    assert!(text.starts_with("event: pairing\n"), "{}", text);
+    assert!(text.contains(r#""state":"payloadReceived""#), "{}", text);
+}
@@ -0,0 +1,71 @@
+//! Golden-file tests for the geosubmit v2 wire model
+
+use serde_json::Value;
+use service_berry::WifiBssid;
+use service_berry::geosubmit::{Geosubmit, PositionSource, WifiAccessPoint, WifiRadioType};
+use service_berry::scanner::wifi::PhyType;
+
+fn fixture(name: &str) -> Value {
+    let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+    let text = std::fs::read_to_string(&path).expect("missing fixture");
+    serde_json::from_str(&text).expect("fixture is not JSON")
+}
+
+fn round_trip(original: &Value) -> Value {
+    let parsed: Geosubmit = serde_json::from_value(original.clone()).expect("does not parse");
+    serde_json::to_value(&parsed).unwrap()
+}
+
+#[test]
+fn full_spec_example_round_trips() {
+    let original = fixture("geosubmit_v2_full.json");
+    assert_eq!(round_trip(&original), original);
+}
+
+#[test]
+fn minimal_spec_example_round_trips() {
+    let original = fixture("geosubmit_v2_minimal.json");
+    assert_eq!(round_trip(&original), original);
+}
+
+#[test]
+fn phone_sample_parses_without_heading_or_speed() {
+    let path = format!("{}/sample.json", env!("CARGO_MANIFEST_DIR"));
+    let sample: Geosubmit =
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).expect("does not parse");
+
+    let item = &sample.items[0];
+    assert_eq!(item.position.heading, None);
+    assert_eq!(item.position.speed, None);
+    assert_eq!(item.position.source, Some(PositionSource::Gps));
+    assert_eq!(item.wifiAccessPoints[0].radioType, Some(WifiRadioType::Ax));
+
+    let json = serde_json::to_value(item).unwrap();
+    assert!(json.get("cellTowers").is_none());
+    assert!(json["wifiAccessPoints"][0].get("wifiVersion").is_none());
+    assert_eq!(json["wifiAccessPoints"][0]["radioType"], "802.11ax");
+}
+
+#[test]
+fn scanner_record_maps_to_schema_values() {
+    let ap = WifiBssid {
+        ssid: Some("Dragon".into()),
+        age: Some(1200),
+        channel: Some(36),
+        phy: PhyType::Vht,
+        rssi: -60,
+        ..WifiBssid::new("82:27:F5:62:2B:4B".parse().unwrap(), 5180)
+    };
+
+    let json = serde_json::to_value(WifiAccessPoint::from(&ap)).unwrap();
+    assert_eq!(json["macAddress"], "82:27:f5:62:2b:4b");
+    assert_eq!(json["radioType"], "802.11ac");
+    assert_eq!(json["frequency"], 5180);
+
+    let legacy = WifiBssid {
+        phy: PhyType::Legacy,
+        ..ap
+    };
+    let json = serde_json::to_value(WifiAccessPoint::from(&legacy)).unwrap();
+    assert!(json.get("radioType").is_none());
+}
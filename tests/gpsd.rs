@@ -0,0 +1,111 @@
+//! gpsd provider tests against a fake gpsd on a local socket
+
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+
+use service_berry::geosubmit::PositionSource;
+use service_berry::position::FixMode;
+use service_berry::position::gpsd::{GpsdState, watch_gpsd};
+
+const SESSION: &[&str] = &[
+    r#"{"class":"VERSION","release":"3.25","rev":"3.25","proto_major":3,"proto_minor":15}"#,
+    r#"{"class":"DEVICES","devices":[{"class":"DEVICE","path":"/dev/ttyACM0","driver":"u-blox"}]}"#,
+    r#"{"class":"WATCH","enable":true,"json":true}"#,
+    r#"{"class":"SKY","device":"/dev/ttyACM0","hdop":0.9,"satellites":[{"PRN":1,"used":true},{"PRN":3,"used":true},{"PRN":7,"used":false},{"PRN":9,"used":true},{"PRN":12,"used":true}]}"#,
+    r#"{"class":"TPV","device":"/dev/ttyACM0","mode":3,"time":"2025-06-01T12:00:00.500Z","lat":52.5163,"lon":13.3777,"altHAE":81.2,"altMSL":34.5,"epx":3.0,"epy":4.0,"epv":7.5,"track":92.3,"speed":12.5}"#,
+];
+
+/// Serve `lines` to the first client once it sends a WATCH command
+async fn fake_gpsd(lines: &'static [&'static str]) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (read, mut write) = stream.into_split();
+        let mut command = String::new();
+        BufReader::new(read).read_line(&mut command).await.unwrap();
+        assert!(command.starts_with("?WATCH="));
+
+        for line in lines {
+            write.write_all(line.as_bytes()).await.unwrap();
+            write.write_all(b"\r\n").await.unwrap();
+        }
+    });
+
+    address
+}
+
+#[tokio::test]
+async fn fix_from_fake_gpsd() {
+    let address = fake_gpsd(SESSION).await;
+    let (tx, mut rx) = watch::channel(None);
+
+    let session = tokio::spawn(async move { watch_gpsd(&address, &tx).await });
+    tokio::time::timeout(Duration::from_secs(5), rx.changed())
+        .await
+        .expect("no fix from gpsd")
+        .unwrap();
+
+    let fix = rx.borrow().clone().expect("3D fix expected");
+    assert_eq!(fix.mode, FixMode::ThreeD);
+    assert_eq!(fix.satellites, Some(4));
+    assert_eq!(fix.timestamp, 1_748_779_200_500);
+    assert_eq!(fix.position.latitude, 52.5163);
+    assert_eq!(fix.position.longitude, 13.3777);
+    assert_eq!(fix.position.accuracy, Some(5.0)); // from epx and epy
+    assert_eq!(fix.position.altitude, Some(34.5));
+    assert_eq!(fix.position.altitudeAccuracy, Some(7.5));
+    assert_eq!(fix.position.heading, Some(92.3));
+    assert_eq!(fix.position.speed, Some(12.5));
+    assert_eq!(fix.position.source, Some(PositionSource::Gps));
+    assert_eq!(fix.position_at(1_748_779_201_000).age, Some(500));
+
+    // the fake hangs up after its last report
+    assert!(session.await.unwrap().is_err());
+}
+
+#[tokio::test]
+async fn unreachable_gpsd_is_an_error() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    drop(listener);
+
+    let (tx, _rx) = watch::channel(None);
+    assert!(watch_gpsd(&address, &tx).await.is_err());
+}
+
+#[test]
+fn accuracy_falls_back_to_hdop() {
+    let mut state = GpsdState::default();
+    state
+        .update(r#"{"class":"SKY","hdop":1.2,"uSat":6}"#)
+        .unwrap();
+    assert!(
+        state
+            .update(r#"{"class":"TPV","mode":2,"lat":1.0,"lon":2.0,"altMSL":30.0,"epv":5.0}"#)
+            .unwrap()
+    );
+
+    let fix = state.fix().unwrap();
+    assert_eq!(fix.mode, FixMode::TwoD);
+    assert_eq!(fix.satellites, Some(6));
+    assert_eq!(fix.position.accuracy, Some(6.0));
+    assert_eq!(fix.position.altitude, None); // not trustworthy without a 3D fix
+}
+
+#[test]
+fn losing_the_fix_clears_it() {
+    let mut state = GpsdState::default();
+    state.update(SESSION[4]).unwrap();
+    assert!(state.fix().is_some());
+
+    state
+        .update(r#"{"class":"TPV","mode":1,"time":"2025-06-01T12:00:01.000Z"}"#)
+        .unwrap();
+    assert!(state.fix().is_none());
+
+    assert!(state.update("not json").is_err());
+}
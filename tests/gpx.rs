@@ -0,0 +1,137 @@
+//! GPX georeferencing: interpolation, rejections and the archive import
+
+use std::path::PathBuf;
+
+use service_berry::collect::import::{ImportOptions, ImportSummary, import_gpx};
+use service_berry::collect::{Archive, Queue, Report};
+use service_berry::position::gpx::{Rejection, Track};
+
+mod common;
+use common::{policy, scratch_dir};
+
+const START: u128 = 1_748_779_200_000; // 2025-06-01T12:00:00Z, first track point
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+fn blind_report(timestamp: u128) -> Report {
+    Report {
+        timestamp,
+        ..common::report(None)
+    }
+}
+
+#[test]
+fn parses_timed_points_across_segments() {
+    let track = Track::read(&fixture("drive.gpx")).unwrap();
+    let points = track.points();
+    assert_eq!(points.len(), 5); // the point without a time is skipped
+    assert_eq!(points[0].timestamp, START);
+    assert_eq!(points[0].altitude, Some(34.0));
+    assert_eq!(points[1].hdop, Some(2.0));
+    assert_eq!(points[4].timestamp, START + 90_000);
+
+    assert!(Track::parse("<gpx></gpx>").is_err());
+    assert!(Track::parse("<gpx><trk>").is_err());
+}
+
+#[test]
+fn interpolates_between_track_points() {
+    let track = Track::read(&fixture("drive.gpx")).unwrap();
+
+    let position = track.position_at(START + 5_000).unwrap();
+    assert!((position.latitude - 52.5165).abs() < 1e-9);
+    assert!((position.longitude - 13.3777).abs() < 1e-9);
+    assert_eq!(position.altitude, Some(35.0));
+    assert_eq!(position.accuracy, Some(10.0)); // the worse HDOP of the two
+    assert_eq!(position.age, Some(5_000));
+    let speed = position.speed.unwrap();
+    assert!((speed - 11.1).abs() < 0.1, "speed {}", speed); // 0.001° of latitude in 10 s
+    assert!(position.heading.unwrap() < 0.01); // due north
+
+    let exact = track.position_at(START + 20_000).unwrap();
+    assert!((exact.latitude - 52.5180).abs() < 1e-9);
+    assert_eq!(exact.age, Some(0));
+}
+
+#[test]
+fn rejects_scans_outside_the_track_or_in_gaps() {
+    let track = Track::read(&fixture("drive.gpx")).unwrap();
+
+    assert_eq!(
+        track.position_at(START - 1).unwrap_err(),
+        Rejection::BeforeTrack
+    );
+    assert_eq!(
+        track.position_at(START + 90_001).unwrap_err(),
+        Rejection::AfterTrack
+    );
+    assert_eq!(
+        track.position_at(START + 50_000).unwrap_err(),
+        Rejection::Gap { millis: 60_000 }
+    );
+
+    let lenient = track.with_max_gap(60_000);
+    assert!(lenient.position_at(START + 50_000).is_ok());
+}
+
+#[tokio::test]
+async fn import_positions_archived_scans() {
+    let dir = scratch_dir("import-gpx");
+    let archive = Archive::open(dir.join("archive.jsonl"));
+    let queue = Queue::open(dir.join("queue.jsonl"));
+    let policy = policy(&dir);
+    for timestamp in [
+        START - 60_000,  // before
+        START + 5_000,   // inside
+        START + 15_000,  // inside
+        START + 50_000,  // gap
+        START + 120_000, // after
+    ] {
+        archive.append(&blind_report(timestamp)).unwrap();
+    }
+
+    let track = Track::read(&fixture("drive.gpx")).unwrap();
+    let summary = import_gpx(&track, &archive, &queue, policy, false)
+        .await
+        .unwrap();
+    assert_eq!(
+        summary,
+        ImportSummary {
+            positioned: 2,
+            before_track: 1,
+            after_track: 1,
+            in_gaps: 1,
+            ..Default::default()
+        }
+    );
+
+    let archived = archive.read().unwrap();
+    assert_eq!(archived.len(), 5);
+    let positioned: Vec<_> = archived.iter().map(|r| r.position.is_some()).collect();
+    assert_eq!(positioned, [false, true, true, false, false]);
+    assert!(archived[1].to_items().is_some());
+    assert!(queue.pending().unwrap().is_empty());
+
+    // already positioned reports are left alone
+    let again = import_gpx(&track, &archive, &queue, policy, false)
+        .await
+        .unwrap();
+    assert_eq!(again.positioned, 0);
+}
+
+#[test]
+fn import_options_need_one_file() {
+    let args = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+    let options = ImportOptions::from_args(args(&["drive.gpx", "--no-submit"])).unwrap();
+    assert_eq!(options.path, PathBuf::from("drive.gpx"));
+    assert!(!options.submit);
+
+    assert!(ImportOptions::from_args(args(&[])).is_err());
+    assert!(ImportOptions::from_args(args(&["a.gpx", "b.gpx"])).is_err());
+    assert!(ImportOptions::from_args(args(&["a.gpx", "--fast"])).is_err());
+}
@@ -0,0 +1,105 @@
+//! Streamed location updates: line splitting, trajectories and interpolation
+
+use service_berry::geosubmit::{Position, PositionSource};
+use service_berry::position::trajectory::{self, Rejection, Sample, Trajectory};
+use service_berry::server::stream::{self, LineBuffer, LocationUpdate};
+use service_berry::time::unix_millis as now;
+
+fn sample(timestamp: u128, latitude: f64, accuracy: f64) -> Sample {
+    Sample {
+        timestamp,
+        position: Position {
+            latitude,
+            longitude: 13.3777,
+            accuracy: Some(accuracy),
+            source: Some(PositionSource::Fused),
+            ..Default::default()
+        },
+    }
+}
+
+#[test]
+fn lines_survive_arbitrary_chunking() {
+    let mut buffer = LineBuffer::default();
+    assert!(buffer.push(b"{\"latitude\":52.5,").is_empty());
+    assert_eq!(
+        buffer.push(b"\"longitude\":13.4}\n\n{\"lat"),
+        ["{\"latitude\":52.5,\"longitude\":13.4}"]
+    );
+    assert_eq!(buffer.push(b"itude\":1}\r\n"), ["{\"latitude\":1}"]);
+
+    // a runaway line is dropped instead of growing forever
+    assert!(buffer.push(&[b'x'; 2000]).is_empty());
+    assert_eq!(buffer.push(b"ok\n"), ["ok"]);
+}
+
+#[test]
+fn updates_are_timestamped_from_their_age() {
+    let update: LocationUpdate =
+        serde_json::from_str(r#"{"latitude":52.5163,"longitude":13.3777,"age":1500}"#).unwrap();
+    let sample = update.into_sample(10_000);
+    assert_eq!(sample.timestamp, 8_500);
+    assert!(sample.position.age.is_none());
+
+    let update: LocationUpdate =
+        serde_json::from_str(r#"{"timestamp":7000,"latitude":52.5,"longitude":13.3,"age":1500}"#)
+            .unwrap();
+    assert_eq!(update.into_sample(10_000).timestamp, 7_000);
+
+    assert!(stream::record("test:garbage", "{\"latitude\":\"north\"}").is_err());
+}
+
+#[test]
+fn trajectory_interpolates_and_keeps_order() {
+    let start = now();
+    let mut trajectory = Trajectory::default();
+    trajectory.push(sample(start + 4_000, 52.5170, 8.0));
+    trajectory.push(sample(start, 52.5160, 4.0)); // late arrival
+    assert_eq!(trajectory.samples()[0].timestamp, start);
+
+    let position = trajectory.position_at(start + 1_000).unwrap();
+    assert!((position.latitude - 52.51625).abs() < 1e-9);
+    assert_eq!(position.accuracy, Some(8.0));
+    assert_eq!(position.age, Some(1_000));
+    assert_eq!(position.source, Some(PositionSource::Fused));
+
+    assert_eq!(
+        trajectory.position_at(start + 5_000).unwrap_err(),
+        Rejection::AfterTrack
+    );
+    trajectory.push(sample(start + 60_000, 52.5300, 4.0));
+    assert!(matches!(
+        trajectory.position_at(start + 30_000).unwrap_err(),
+        Rejection::Gap { .. }
+    ));
+
+    // samples older than the window are dropped
+    trajectory.push(sample(start + 3_600_000, 52.6, 4.0));
+    assert_eq!(trajectory.samples().len(), 1);
+}
+
+#[test]
+fn locate_only_uses_the_scanning_client() {
+    let start = now();
+    trajectory::record("test:coarse", sample(start, 52.0, 50.0));
+    trajectory::record("test:coarse", sample(start + 2_000, 52.0, 50.0));
+    trajectory::record("test:fine", sample(start, 53.0, 3.0));
+    trajectory::record("test:fine", sample(start + 2_000, 53.0, 3.0));
+
+    let position = trajectory::locate("test:coarse", start + 1_000).unwrap();
+    assert_eq!(position.latitude, 52.0);
+    let position = trajectory::locate("test:fine", start + 1_000).unwrap();
+    assert_eq!(position.latitude, 53.0);
+    assert!(trajectory::active(5_000));
+    assert_eq!(
+        trajectory::locate("test:fine", start + 10_000).unwrap_err(),
+        Rejection::AfterTrack
+    );
+    assert_eq!(
+        trajectory::locate("test:silent", start + 1_000).unwrap_err(),
+        Rejection::AfterTrack
+    );
+
+    trajectory::record("test:fine", sample(start + 3_000, 53.0, 3.0));
+    assert_eq!(trajectory::latest_client().as_deref(), Some("test:fine"));
+}
@@ -0,0 +1,39 @@
+//! Prometheus metrics exported on `/metrics`
+
+use service_berry::server::{metrics, status};
+use std::time::Duration;
+
+#[test]
+fn untouched_metrics_are_exported() {
+    let text = metrics::render();
+    // labelled metrics only appear once a label has been used
+    for name in [
+        "serviceberry_scan_cells_count",
+        "serviceberry_ble_parse_failures_total 0",
+        "serviceberry_ble_write_frames_total 0",
+        "serviceberry_tls_handshake_errors_total 0",
+    ] {
+        assert!(text.contains(name), "{} missing from:\n{}", name, text);
+    }
+}
+
+#[test]
+fn queue_depth_follows_the_submission_queue() {
+    status::queue_push();
+    status::queue_push();
+    status::queue_pop();
+    assert_eq!(status::queue_depth(), 1);
+    assert!(metrics::render().contains("serviceberry_queue_depth 1"));
+}
+
+#[test]
+fn scans_are_recorded_per_scanner() {
+    metrics::observe_scan("wifi", Duration::from_millis(2_500), 12);
+    metrics::observe_scan("ble", Duration::from_secs(10), 3);
+
+    let text = metrics::render();
+    assert!(text.contains(r#"serviceberry_scan_duration_seconds_count{scanner="wifi"} 1"#));
+    assert!(text.contains(r#"serviceberry_scan_duration_seconds_sum{scanner="ble"} 10"#));
+    assert!(text.contains("serviceberry_scan_access_points_sum 12"));
+    assert!(text.contains("serviceberry_scan_beacons_sum 3"));
+}
@@ -0,0 +1,173 @@
+//! Mobile access point and beacon detection from the observation history
+
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use service_berry::Error;
+use service_berry::collect::mobility::{Classification, DeviceKind};
+use service_berry::collect::{Archive, MobileStore, Report};
+use service_berry::geosubmit::Position;
+
+mod common;
+use common::{device, scratch_dir, wifi};
+
+const HOUR: u128 = 3_600_000;
+const HOTSPOT: &str = "0A:11:22:33:44:55";
+const ROUTER: &str = "82:27:F5:62:2B:4B";
+const WATCH: &str = "C4:7C:8D:6A:11:02";
+
+fn report(timestamp: u128, latitude: f64, wifi_at: &[&str], ble_at: &[&str]) -> Report {
+    Report {
+        timestamp,
+        position: Some(Position {
+            latitude,
+            longitude: 13.3777,
+            accuracy: Some(5.0),
+            ..Default::default()
+        }),
+        wifi_access_points: wifi_at.iter().map(|b| wifi(b)).collect(),
+        bluetooth_beacons: ble_at.iter().map(|a| device(a)).collect(),
+        cell_towers: Vec::new(),
+    }
+}
+
+#[test]
+fn devices_that_travel_are_flagged_and_stripped() {
+    let dir = scratch_dir("mobility");
+    let store = MobileStore::open(dir.join("mobile.json"));
+
+    // home: router, hotspot and watch
+    let home = report(0, 52.5163, &[ROUTER, HOTSPOT], &[WATCH]);
+    assert_eq!(store.observe(&home).unwrap(), 0);
+    // 300 m away is within reach of a strong access point
+    assert_eq!(
+        store
+            .observe(&report(HOUR / 2, 52.5190, &[ROUTER], &[]))
+            .unwrap(),
+        0
+    );
+    // 5 km away an hour later, the hotspot and the watch came along
+    assert_eq!(
+        store
+            .observe(&report(HOUR, 52.5613, &[HOTSPOT], &[WATCH]))
+            .unwrap(),
+        2
+    );
+
+    let mut later = report(2 * HOUR, 52.5613, &[ROUTER, HOTSPOT], &[WATCH]);
+    assert_eq!(store.strip(&mut later), 2);
+    let items = later.to_items().unwrap();
+    assert_eq!(items.wifiAccessPoints.len(), 1);
+    assert_eq!(items.wifiAccessPoints[0].macAddress.to_string(), ROUTER);
+    assert!(items.bluetoothBeacons.is_empty());
+
+    let listed = MobileStore::open(dir.join("mobile.json")).list().unwrap();
+    assert_eq!(listed.len(), 2);
+    assert_eq!(listed[0].kind, DeviceKind::Wifi);
+    assert_eq!(listed[0].classification, Classification::Detected);
+    assert!(listed[0].distance.unwrap() > 4_900.0);
+}
+
+#[test]
+fn sightings_outside_the_window_are_not_compared() {
+    let dir = scratch_dir("mobility-window");
+    let store = MobileStore::open(dir.join("mobile.json"));
+    store.observe(&report(0, 52.5163, &[ROUTER], &[])).unwrap();
+    // the router moved house a week later
+    let moved = report(7 * 24 * HOUR, 48.1372, &[ROUTER], &[]);
+    assert_eq!(store.observe(&moved).unwrap(), 0);
+    assert!(store.list().unwrap().is_empty());
+}
+
+#[test]
+fn devices_not_seen_within_the_window_are_forgotten() {
+    let dir = scratch_dir("mobility-forget");
+    let store = MobileStore::open(dir.join("mobile.json"));
+    store
+        .observe(&report(0, 52.5163, &[ROUTER, HOTSPOT], &[WATCH]))
+        .unwrap();
+    store
+        .observe(&report(HOUR, 52.5163, &[ROUTER], &[]))
+        .unwrap();
+    assert_eq!(store.tracked().unwrap(), 3);
+
+    store
+        .observe(&report(25 * HOUR, 52.5163, &[ROUTER], &[]))
+        .unwrap();
+    assert_eq!(store.tracked().unwrap(), 1);
+}
+
+#[test]
+fn overrides_beat_detection() {
+    let dir = scratch_dir("mobility-override");
+    let store = MobileStore::open(dir.join("mobile.json"));
+    store.observe(&report(0, 52.5163, &[HOTSPOT], &[])).unwrap();
+    store
+        .observe(&report(HOUR, 52.5613, &[HOTSPOT], &[]))
+        .unwrap();
+
+    store
+        .classify(
+            DeviceKind::Wifi,
+            &HOTSPOT.to_lowercase(),
+            Classification::Allowed,
+        )
+        .unwrap();
+    store
+        .classify(DeviceKind::Wifi, ROUTER, Classification::Denied)
+        .unwrap();
+
+    let reopened = MobileStore::open(dir.join("mobile.json"));
+    let mut later = report(2 * HOUR, 52.5613, &[ROUTER, HOTSPOT], &[]);
+    assert_eq!(reopened.strip(&mut later), 1);
+    assert_eq!(later.wifi_access_points[0].bssid.to_string(), HOTSPOT);
+
+    assert!(reopened.forget(DeviceKind::Wifi, ROUTER).unwrap());
+    assert!(!reopened.forget(DeviceKind::Ble, ROUTER).unwrap());
+    assert_eq!(reopened.list().unwrap().len(), 1);
+}
+
+#[test]
+fn overrides_need_a_valid_address() {
+    let dir = scratch_dir("mobility-address");
+    let store = MobileStore::open(dir.join("mobile.json"));
+    for address in ["", "router", "82:27:F5:62:2B", "82:27:F5:62:2B:4B:00"] {
+        let result = store.classify(DeviceKind::Wifi, address, Classification::Denied);
+        assert!(
+            matches!(result, Err(Error::InvalidAddress(_))),
+            "{:?}",
+            address
+        );
+        assert!(matches!(
+            store.forget(DeviceKind::Wifi, address),
+            Err(Error::InvalidAddress(_))
+        ));
+    }
+    assert!(store.list().unwrap().is_empty());
+    assert!(!dir.join("mobile.json").exists());
+    assert_eq!(
+        Error::InvalidAddress("router".into())
+            .into_response()
+            .status(),
+        StatusCode::BAD_REQUEST
+    );
+}
+
+#[test]
+fn history_is_learned_from_the_archive() {
+    let dir = scratch_dir("mobility-archive");
+    let archive = Archive::open(dir.join("archive.jsonl"));
+    // archived out of order, replayed by time
+    archive
+        .append(&report(HOUR, 52.5613, &[HOTSPOT], &[]))
+        .unwrap();
+    archive
+        .append(&report(0, 52.5163, &[HOTSPOT], &[]))
+        .unwrap();
+    let mut unpositioned = report(2 * HOUR, 0.0, &[HOTSPOT], &[]);
+    unpositioned.position = None;
+    archive.append(&unpositioned).unwrap();
+
+    let store = MobileStore::open(dir.join("mobile.json"));
+    assert_eq!(store.learn(&archive).unwrap(), 1);
+    assert_eq!(store.list().unwrap()[0].address, HOTSPOT);
+}
@@ -0,0 +1,89 @@
+//! NMEA 0183 provider tests against a recorded drive and hand-made sentences
+
+use std::path::PathBuf;
+
+use service_berry::position::FixMode;
+use service_berry::position::nmea::{NmeaParser, read_file};
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+fn close(actual: Option<f64>, expected: f64) -> bool {
+    actual.is_some_and(|a| (a - expected).abs() < 1e-6)
+}
+
+#[test]
+fn recorded_drive_yields_gated_fixes() {
+    let fixes = read_file(&fixture("drive.nmea")).unwrap();
+    assert_eq!(fixes.len(), 2); // the dead reckoning epoch and the corrupt sentence are dropped
+
+    let first = &fixes[0];
+    assert_eq!(first.timestamp, 1_748_735_999_000);
+    assert_eq!(first.mode, FixMode::ThreeD);
+    assert_eq!(first.satellites, Some(9));
+    assert!(close(Some(first.position.latitude), 52.5163));
+    assert!(close(Some(first.position.longitude), 13.3777));
+    assert!(close(first.position.accuracy, 3.0)); // from GST lat/lon errors
+    assert!(close(first.position.altitudeAccuracy, 3.2));
+    assert!(close(first.position.altitude, 34.5));
+    assert!(close(first.position.speed, 5.144_444));
+    assert!(close(first.position.heading, 90.0));
+
+    // past midnight, without GST the accuracy comes from the DOPs
+    let second = &fixes[1];
+    assert_eq!(second.timestamp, 1_748_736_000_000);
+    assert!(close(second.position.accuracy, 6.0));
+    assert!(close(second.position.altitudeAccuracy, 7.0));
+}
+
+#[test]
+fn checksum_errors_are_rejected() {
+    let mut parser = NmeaParser::default();
+    let corrupted = "$GPGGA,120000.00,4807.03800,N,01131.00000,E,1,08,0.9,545.4,M,46.9,M,,*66";
+    assert!(parser.update(corrupted).is_err());
+    assert!(parser.update("$GPGGA,120000.00,4807.03800,N").is_err()); // truncated, no checksum
+    assert!(parser.fix().is_none());
+}
+
+#[test]
+fn single_constellation_talkers_are_accepted() {
+    let mut parser = NmeaParser::default();
+    assert!(
+        parser
+            .update("$GLGGA,120000.00,4807.03800,N,01131.00000,E,1,05,1.5,545.4,M,46.9,M,,*7B")
+            .unwrap()
+    );
+    assert!(
+        parser
+            .update("$GPGGA,120000.00,4807.03800,N,01131.00000,E,1,08,0.9,545.4,M,46.9,M,,*67")
+            .unwrap()
+    );
+
+    let fix = parser.fix().unwrap();
+    assert_eq!(fix.satellites, Some(8));
+    assert!(close(Some(fix.position.latitude), 48.1173));
+    assert!(close(Some(fix.position.longitude), 11.516_666_666));
+    assert!(close(fix.position.accuracy, 4.5));
+}
+
+#[test]
+fn invalid_fixes_are_gated() {
+    let mut parser = NmeaParser::default();
+    parser
+        .update("$GPGGA,120000.00,4807.03800,N,01131.00000,E,1,08,0.9,545.4,M,46.9,M,,*67")
+        .unwrap();
+    assert!(parser.fix().is_some());
+
+    // quality 0, then an RMC with a void status
+    parser
+        .update("$GPGGA,120001.00,4807.03800,N,01131.00000,E,0,00,,,M,,M,,*73")
+        .unwrap();
+    assert!(parser.fix().is_none());
+    parser
+        .update("$GPRMC,120002.00,V,,,,,,,010625,,,N*7C")
+        .unwrap();
+    assert!(parser.fix().is_none());
+}
@@ -0,0 +1,115 @@
+//! OUI vendor lookup and the device rules built on it
+
+use service_berry::WifiBssid;
+use service_berry::collect::Report;
+use service_berry::collect::privacy::{DeviceRuleStore, DeviceRules, Dropped};
+use service_berry::scanner::oui::{MacInfo, OuiDatabase};
+
+mod common;
+use common::{address, device, scratch_dir, wifi};
+
+#[test]
+fn parses_the_ieee_csv_export() {
+    let csv = "\
+Registry,Assignment,Organization Name,Organization Address
+MA-L,0015FF,\"Novatel Wireless, Inc.\",9645 Scranton Road San Diego CA US 92121
+MA-L,AABBCC,\"The \"\"Quoted\"\" Company\",
+MA-M,AABBCD1,Medium Block Ltd,
+MA-L,not hex,Broken Row,
+";
+    let database = OuiDatabase::parse(csv).unwrap();
+    assert_eq!(database.len(), 2);
+    assert_eq!(
+        database.lookup(&address("00:15:FF:12:34:56")),
+        Some("Novatel Wireless, Inc.")
+    );
+    assert_eq!(
+        database.lookup(&address("AA:BB:CC:00:00:01")),
+        Some("The \"Quoted\" Company")
+    );
+    assert_eq!(database.lookup(&address("AA:BB:CD:10:00:01")), None);
+
+    assert!(OuiDatabase::parse("Registry,Assignment,Organization Name\n").is_err());
+}
+
+#[test]
+fn address_bits_are_decoded() {
+    let database = OuiDatabase::bundled();
+    assert!(!database.is_empty());
+
+    let info = MacInfo::with(&database, &address("B8:27:EB:01:02:03"));
+    assert_eq!(info.vendor.as_deref(), Some("Raspberry Pi Foundation"));
+    assert!(!info.locally_administered && !info.multicast);
+
+    // same bytes after the first, but set by software
+    let info = MacInfo::with(&database, &address("BA:27:EB:01:02:03"));
+    assert_eq!(info.vendor, None);
+    assert!(info.locally_administered);
+
+    let info = MacInfo::with(&database, &address("01:00:5E:00:00:FB"));
+    assert!(info.multicast && !info.locally_administered);
+
+    // flattened into archived and `/request` records
+    let json = serde_json::to_value(wifi("00:15:FF:12:34:56")).unwrap();
+    assert_eq!(json["vendor"], "Novatel Wireless, Inc.");
+    assert!(json.get("locallyAdministered").is_none());
+    let back: WifiBssid = serde_json::from_value(json).unwrap();
+    assert_eq!(
+        back.mac_info.vendor.as_deref(),
+        Some("Novatel Wireless, Inc.")
+    );
+}
+
+#[test]
+fn database_is_read_from_a_file() {
+    let dir = scratch_dir("oui-file");
+    let path = dir.join("oui.csv");
+    std::fs::write(
+        &path,
+        "Registry,Assignment,Organization Name,Organization Address\nMA-L,001122,Example,\n",
+    )
+    .unwrap();
+    let database = OuiDatabase::from_file(&path).unwrap();
+    assert_eq!(
+        database.lookup(&address("00:11:22:33:44:55")),
+        Some("Example")
+    );
+    assert!(OuiDatabase::from_file(dir.join("missing.csv")).is_err());
+}
+
+#[test]
+fn device_rules_strip_hotspot_vendors() {
+    let dir = scratch_dir("oui-rules");
+    let store = DeviceRuleStore::open(dir.join("devices.json"));
+    let mut report = Report {
+        timestamp: 1_748_779_200_000,
+        position: None,
+        wifi_access_points: vec![
+            wifi("00:15:FF:12:34:56"), // Novatel MiFi
+            wifi("B8:27:EB:01:02:03"),
+            wifi("BA:27:EB:01:02:03"),
+        ],
+        bluetooth_beacons: vec![device("00:14:3E:00:00:01")], // Sierra Wireless
+        cell_towers: Vec::new(),
+    };
+
+    assert_eq!(
+        store.strip(&mut report.clone()),
+        Dropped::from([("vendor", 2)])
+    );
+
+    let rules = store
+        .set(DeviceRules {
+            vendors: vec!["raspberry".into(), "  ".into()],
+            locally_administered: true,
+            stationary_beacons_only: false,
+            random_addresses: Default::default(),
+        })
+        .unwrap();
+    assert_eq!(rules.vendors, vec!["raspberry".to_string()]);
+    let reopened = DeviceRuleStore::open(dir.join("devices.json"));
+    assert_eq!(reopened.get().unwrap(), rules);
+    assert_eq!(reopened.strip(&mut report), Dropped::from([("vendor", 2)]));
+    assert_eq!(report.wifi_access_points.len(), 1);
+    assert_eq!(report.bluetooth_beacons.len(), 1);
+}
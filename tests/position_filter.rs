@@ -0,0 +1,122 @@
+//! Position filter: accuracy threshold, jump detection and smoothing
+
+use axum::response::IntoResponse;
+use service_berry::Error;
+use service_berry::geosubmit::Position;
+use service_berry::position::filter::{self, FilterConfig, PositionFilter, Rejection};
+
+fn position(latitude: f64, longitude: f64, accuracy: f64) -> Position {
+    Position {
+        latitude,
+        longitude,
+        accuracy: Some(accuracy),
+        ..Default::default()
+    }
+}
+
+fn config(smoothing: bool) -> FilterConfig {
+    FilterConfig {
+        max_accuracy: 50.0,
+        max_speed: 40.0,
+        smoothing,
+    }
+}
+
+#[test]
+fn coarse_and_invalid_positions_are_rejected() {
+    let mut filter = PositionFilter::new(config(false));
+    assert_eq!(
+        filter.process(0, position(52.5, 13.4, 800.0)).unwrap_err(),
+        Rejection::Inaccurate {
+            accuracy: 800.0,
+            limit: 50.0
+        }
+    );
+    assert_eq!(
+        filter.process(0, position(95.0, 13.4, 5.0)).unwrap_err(),
+        Rejection::OutOfRange
+    );
+    assert!(filter.process(0, position(52.5, 13.4, 5.0)).is_ok());
+}
+
+#[test]
+fn jumps_are_rejected_until_the_phone_insists() {
+    let mut filter = PositionFilter::new(config(false));
+    filter.process(0, position(52.5000, 13.4, 5.0)).unwrap();
+    // about 111 m in 10 s is a brisk bike ride
+    filter
+        .process(10_000, position(52.5010, 13.4, 5.0))
+        .unwrap();
+
+    // about 1.1 km in 2 s is not
+    let rejection = filter
+        .process(12_000, position(52.5110, 13.4, 5.0))
+        .unwrap_err();
+    let Rejection::Jump {
+        meters, seconds, ..
+    } = rejection
+    else {
+        panic!("unexpected rejection {:?}", rejection);
+    };
+    assert!((meters - 1112.0).abs() < 5.0, "meters {}", meters);
+    assert_eq!(seconds, 2.0);
+    assert!(rejection.to_string().starts_with("jumped 1112 m in 2.0 s"));
+
+    assert!(
+        filter
+            .process(13_000, position(52.5110, 13.4, 5.0))
+            .is_err()
+    );
+    // the third time in a row, the new place wins
+    assert!(filter.process(14_000, position(52.5110, 13.4, 5.0)).is_ok());
+    assert!(filter.process(15_000, position(52.5111, 13.4, 5.0)).is_ok());
+
+    // after a long pause any distance is fine
+    assert!(filter.process(900_000, position(48.0, 11.0, 5.0)).is_ok());
+}
+
+#[test]
+fn a_client_with_a_wrong_clock_leaves_other_filters_alone() {
+    let now = 1_700_000_000_000;
+    assert!(filter::process("test:steady", now, position(52.5, 13.4, 5.0)).is_ok());
+    // a day ahead
+    assert!(filter::process("test:skewed", now + 86_400_000, position(48.1, 11.6, 5.0)).is_ok());
+
+    // still checked against the last fix, 30 km away a second ago
+    assert!(matches!(
+        filter::process("test:steady", now + 1_000, position(52.77, 13.4, 5.0)),
+        Err(Rejection::Jump { .. })
+    ));
+}
+
+#[test]
+fn smoothing_pulls_noisy_fixes_together() {
+    let mut filter = PositionFilter::new(config(true));
+    let first = filter.process(0, position(52.5000, 13.4, 20.0)).unwrap();
+    assert_eq!(first.latitude, 52.5000);
+    assert_eq!(first.accuracy, Some(20.0));
+
+    // a precise fix 1 s later dominates, but not completely
+    let second = filter.process(1_000, position(52.5002, 13.4, 5.0)).unwrap();
+    assert!(second.latitude > 52.5001 && second.latitude < 52.5002);
+    assert!(second.accuracy.unwrap() < 5.0);
+}
+
+#[test]
+fn rejections_are_explained_in_the_response() {
+    let response = Error::PositionRejected(Rejection::Inaccurate {
+        accuracy: 800.0,
+        limit: 100.0,
+    })
+    .into_response();
+    assert_eq!(response.status().as_u16(), 422);
+
+    let json = serde_json::to_value(Rejection::Jump {
+        meters: 900.0,
+        seconds: 1.0,
+        speed: 900.0,
+    })
+    .unwrap();
+    assert_eq!(json["reason"], "jump");
+    assert_eq!(json["meters"], 900.0);
+}
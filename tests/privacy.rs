@@ -0,0 +1,141 @@
+//! Privacy zones: geometry, persistence and their effect on collected reports
+
+use service_berry::collect::privacy::{NewZone, Shape, Verdict, ZoneAction};
+use service_berry::collect::runner::{Outcome, dispatch};
+use service_berry::collect::{Archive, Queue, Report, ZoneStore};
+use service_berry::geosubmit::Position;
+
+mod common;
+use common::{policy, scratch_dir};
+
+fn home() -> Shape {
+    Shape::Circle {
+        latitude: 52.5163,
+        longitude: 13.3777,
+        radius: 200.0,
+    }
+}
+
+fn office() -> Shape {
+    Shape::Polygon {
+        points: vec![
+            [48.10, 11.50],
+            [48.10, 11.60],
+            [48.20, 11.60],
+            [48.20, 11.50],
+        ],
+    }
+}
+
+fn report(latitude: f64, longitude: f64) -> Report {
+    common::report(Some(Position {
+        latitude,
+        longitude,
+        accuracy: Some(5.0),
+        ..Default::default()
+    }))
+}
+
+#[test]
+fn shapes_contain_what_they_should() {
+    assert!(home().contains(52.5170, 13.3777)); // about 80 m north
+    assert!(!home().contains(52.5200, 13.3777)); // about 400 m north
+
+    assert!(office().contains(48.15, 11.55));
+    assert!(!office().contains(48.15, 11.65));
+    assert!(!office().contains(48.25, 11.55));
+
+    // no coordinates in logs
+    assert_eq!(format!("{:?}", home()), "circle(<redacted>)");
+}
+
+#[test]
+fn zones_persist_and_list_without_coordinates() {
+    let dir = scratch_dir("zones");
+    let store = ZoneStore::open(dir.join("zones.json"));
+    let home_id = store
+        .add(NewZone {
+            action: ZoneAction::DropReport,
+            shape: home(),
+        })
+        .unwrap();
+    store
+        .add(NewZone {
+            action: ZoneAction::DropPosition,
+            shape: office(),
+        })
+        .unwrap();
+
+    let reloaded = ZoneStore::open(dir.join("zones.json"));
+    let listed = serde_json::to_string(&reloaded.list().unwrap()).unwrap();
+    assert!(listed.contains(&home_id));
+    assert!(listed.contains("polygon"));
+    assert!(!listed.contains("52.5163"));
+
+    assert!(reloaded.remove(&home_id).unwrap());
+    assert!(!reloaded.remove(&home_id).unwrap());
+    assert_eq!(reloaded.list().unwrap().len(), 1);
+
+    let invalid = NewZone {
+        action: ZoneAction::DropReport,
+        shape: Shape::Circle {
+            latitude: 52.5,
+            longitude: 13.4,
+            radius: -1.0,
+        },
+    };
+    assert!(reloaded.add(invalid).is_err());
+    let degenerate = NewZone {
+        action: ZoneAction::DropReport,
+        shape: Shape::Polygon {
+            points: vec![[48.1, 11.5], [48.2, 11.6]],
+        },
+    };
+    assert!(reloaded.add(degenerate).is_err());
+}
+
+#[test]
+fn new_zones_default_to_dropping_the_report() {
+    let zone: NewZone = serde_json::from_str(
+        r#"{"shape":{"type":"circle","latitude":1,"longitude":2,"radius":50}}"#,
+    )
+    .unwrap();
+    assert_eq!(zone.action, ZoneAction::DropReport);
+}
+
+#[tokio::test]
+async fn reports_inside_zones_are_dropped_or_stripped() {
+    let dir = scratch_dir("zones-dispatch");
+    let archive = Archive::open(dir.join("archive.jsonl"));
+    let queue = Queue::open(dir.join("queue.jsonl"));
+    let policy = policy(&dir);
+    let zones = policy.zones;
+    zones
+        .add(NewZone {
+            action: ZoneAction::DropReport,
+            shape: home(),
+        })
+        .unwrap();
+    zones
+        .add(NewZone {
+            action: ZoneAction::DropPosition,
+            shape: office(),
+        })
+        .unwrap();
+
+    let mut stripped = report(48.15, 11.55);
+    assert_eq!(zones.apply(&mut stripped), Verdict::PositionDropped);
+    assert!(stripped.position.is_none());
+    assert_eq!(zones.apply(&mut report(40.0, 3.0)), Verdict::Keep);
+
+    let home = dispatch(&report(52.5163, 13.3777), &archive, &queue, policy, true).await;
+    assert_eq!(home, Outcome::Suppressed);
+    let office = dispatch(&report(48.15, 11.55), &archive, &queue, policy, true).await;
+    assert_eq!(office, Outcome::Archived); // nothing left to submit
+
+    let archived = archive.read().unwrap();
+    assert_eq!(archived.len(), 1);
+    assert!(archived[0].position.is_none());
+    assert_eq!(archived[0].wifi_access_points.len(), 1);
+    assert!(queue.pending().unwrap().is_empty());
+}
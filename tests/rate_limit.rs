@@ -0,0 +1,89 @@
+//! Per-client token buckets for submissions
+
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use service_berry::Error;
+use service_berry::server::rate_limit::{MAX_TRACKED_CLIENTS, RateLimiter};
+use std::time::{Duration, Instant};
+
+fn retry_after(result: service_berry::Result<()>) -> Duration {
+    match result {
+        Err(Error::RateLimited { retry_after }) => retry_after,
+        other => panic!("expected to be rate limited, got {:?}", other),
+    }
+}
+
+#[test]
+fn a_full_bucket_allows_a_burst() {
+    let limiter = RateLimiter::new(3, Duration::from_secs(10));
+    let now = Instant::now();
+    for _ in 0..3 {
+        assert!(limiter.check_at("http:10.0.0.2", now).is_ok());
+    }
+    assert_eq!(
+        retry_after(limiter.check_at("http:10.0.0.2", now)),
+        Duration::from_secs(10)
+    );
+}
+
+#[test]
+fn tokens_come_back_one_interval_at_a_time() {
+    let limiter = RateLimiter::new(2, Duration::from_secs(10));
+    let start = Instant::now();
+    limiter.check_at("ble:1", start).unwrap();
+    limiter.check_at("ble:1", start).unwrap();
+
+    // a quarter of a token earned, three quarters to go
+    let later = start + Duration::from_millis(2_500);
+    assert_eq!(
+        retry_after(limiter.check_at("ble:1", later)),
+        Duration::from_millis(7_500)
+    );
+    let refilled = start + Duration::from_secs(10);
+    assert!(limiter.check_at("ble:1", refilled).is_ok());
+    assert!(limiter.check_at("ble:1", refilled).is_err());
+
+    // an idle client never saves up more than the burst
+    let idle = start + Duration::from_secs(3_600);
+    assert!(limiter.check_at("ble:1", idle).is_ok());
+    assert!(limiter.check_at("ble:1", idle).is_ok());
+    assert!(limiter.check_at("ble:1", idle).is_err());
+}
+
+#[test]
+fn clients_have_separate_buckets() {
+    let limiter = RateLimiter::new(1, Duration::from_secs(60));
+    let now = Instant::now();
+    assert!(limiter.check_at("http:10.0.0.2", now).is_ok());
+    assert!(limiter.check_at("http:10.0.0.2", now).is_err());
+    assert!(limiter.check_at("http:10.0.0.3", now).is_ok());
+    assert!(limiter.check_at("ble:10.0.0.2", now).is_ok());
+}
+
+#[test]
+fn rate_limited_responses_say_when_to_retry() {
+    let response = Error::RateLimited {
+        retry_after: Duration::from_millis(7_500),
+    }
+    .into_response();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()["retry-after"], "8");
+}
+
+#[test]
+fn new_clients_wait_while_every_tracked_one_is_busy() {
+    let limiter = RateLimiter::new(1, Duration::from_secs(60));
+    let now = Instant::now();
+    for n in 0..MAX_TRACKED_CLIENTS {
+        limiter.check_at(&format!("http:{}", n), now).unwrap();
+    }
+    assert_eq!(
+        retry_after(limiter.check_at("http:new", now)),
+        Duration::from_secs(60)
+    );
+    assert!(limiter.check_at("http:0", now).is_err()); // still tracked
+
+    // once the others have refilled they make room
+    let later = now + Duration::from_secs(60);
+    assert!(limiter.check_at("http:new", later).is_ok());
+}
@@ -0,0 +1,81 @@
+//! Sharing scans between callers
+
+use service_berry::scanner::ScanProfile;
+use service_berry::scanner::cache::{ScanOptions, Slot};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use tokio::time::{Instant, sleep};
+
+/// A scanner that takes a while and returns how many times it has run
+async fn slow_scan(runs: &AtomicU32) -> Vec<u32> {
+    let run = runs.fetch_add(1, Ordering::SeqCst) + 1;
+    sleep(Duration::from_millis(50)).await;
+    vec![run]
+}
+
+#[tokio::test]
+async fn concurrent_callers_share_one_scan() {
+    let slot = Slot::new();
+    let runs = AtomicU32::new(0);
+    let options = ScanOptions::default();
+
+    let (first, second, third) = tokio::join!(
+        slot.scan(Duration::ZERO, options, || slow_scan(&runs)),
+        slot.scan(Duration::ZERO, options, || slow_scan(&runs)),
+        slot.scan(Duration::ZERO, options, || slow_scan(&runs)),
+    );
+
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+    assert_eq!(first.0.records, vec![1]);
+    assert_eq!(second.0.records, vec![1]);
+    assert_eq!(third.0.records, vec![1]);
+    let scanned = [first.1, second.1, third.1];
+    assert_eq!(scanned.iter().filter(|&&s| s).count(), 1);
+}
+
+#[tokio::test]
+async fn fresh_results_are_reused_and_stale_ones_rescanned() {
+    let slot = Slot::new();
+    let runs = AtomicU32::new(0);
+    let options = ScanOptions::default();
+
+    let (_, scanned) = slot
+        .scan(Duration::ZERO, options, || slow_scan(&runs))
+        .await;
+    assert!(scanned);
+
+    let (snapshot, scanned) = slot
+        .scan(Duration::from_secs(60), options, || slow_scan(&runs))
+        .await;
+    assert!(!scanned);
+    assert_eq!(snapshot.records, vec![1]);
+
+    // a scan finished before this call is not fresh enough for Duration::ZERO
+    let (snapshot, scanned) = slot
+        .scan(Duration::ZERO, options, || slow_scan(&runs))
+        .await;
+    assert!(scanned);
+    assert_eq!(snapshot.records, vec![2]);
+    assert!(
+        slot.cached_at(Instant::now(), Duration::from_secs(60), options)
+            .is_some()
+    );
+}
+
+#[tokio::test]
+async fn cached_results_only_cover_smaller_requests() {
+    let slot = Slot::new();
+    let runs = AtomicU32::new(0);
+    let single = ScanOptions::default();
+    let window = ScanOptions::new(Some(3), Some(ScanProfile::Fast)).unwrap();
+    let max_age = Duration::from_secs(60);
+
+    slot.scan(max_age, single, || slow_scan(&runs)).await;
+    let (_, scanned) = slot.scan(max_age, window, || slow_scan(&runs)).await;
+    assert!(scanned, "one pass can't stand in for three");
+
+    // three fast passes don't cover a full single pass either
+    let (_, scanned) = slot.scan(max_age, single, || slow_scan(&runs)).await;
+    assert!(scanned);
+    assert_eq!(runs.load(Ordering::SeqCst), 3);
+}
@@ -0,0 +1,119 @@
+//! Wi-Fi scan profiles and how they are chosen
+
+use service_berry::collect::runner::CollectOptions;
+use service_berry::scanner::ScanProfile;
+use service_berry::scanner::band::{self, Band};
+use service_berry::scanner::cache::{ScanOptions, Slot};
+use std::time::Duration;
+use tokio::time::Instant;
+
+fn args(list: &[&str]) -> Vec<String> {
+    list.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn profiles_set_channels_dwell_and_probing() {
+    let fast = ScanProfile::Fast.settings();
+    assert_eq!(
+        fast.trigger_args(),
+        args(&[
+            "freq", "2412", "2437", "2462", "5180", "5200", "5220", "5240", "5745", "5765", "5785",
+            "5805", "duration", "40", "flush",
+        ])
+    );
+    assert_eq!(
+        ScanProfile::Full.settings().trigger_args(),
+        args(&["flush"])
+    );
+    assert_eq!(
+        ScanProfile::Passive.settings().trigger_args(),
+        args(&["duration", "110", "flush", "passive"])
+    );
+}
+
+#[test]
+fn dwell_times_can_be_dropped_for_drivers_that_refuse_them() {
+    let passive = ScanProfile::Passive.settings().without_dwell().unwrap();
+    assert_eq!(passive.dwell_tu, None);
+    assert_eq!(passive.trigger_args(), args(&["flush", "passive"]));
+    assert!(ScanProfile::Full.settings().without_dwell().is_none());
+}
+
+#[test]
+fn six_ghz_profile_asks_for_every_20_mhz_channel() {
+    let settings = ScanProfile::SixGhz.settings();
+    let count = |wanted: Band| {
+        settings
+            .frequencies
+            .iter()
+            .filter(|&&f| band::channel(f).is_some_and(|(b, _)| b == wanted))
+            .count()
+    };
+    assert_eq!(count(Band::TwoPointFour), 13);
+    assert_eq!(count(Band::Five), 25);
+    assert_eq!(count(Band::Six), 59);
+    assert!(settings.frequencies.contains(&5955)); // channel 1
+    assert!(settings.frequencies.contains(&7115)); // channel 233
+    assert!(!settings.frequencies.contains(&5935)); // channel 2 is not a 20 MHz channel
+    assert!(!settings.passive);
+}
+
+#[test]
+fn profiles_are_chosen_by_name() {
+    assert_eq!("fast".parse::<ScanProfile>().unwrap(), ScanProfile::Fast);
+    assert_eq!("6GHz".parse::<ScanProfile>().unwrap(), ScanProfile::SixGhz);
+    assert!("slow".parse::<ScanProfile>().is_err());
+    assert_eq!(
+        serde_json::from_value::<ScanProfile>("passive".into()).unwrap(),
+        ScanProfile::Passive
+    );
+    assert_eq!(serde_json::to_value(ScanProfile::SixGhz).unwrap(), "6ghz");
+
+    let options = ScanOptions::new(Some(2), Some(ScanProfile::Passive)).unwrap();
+    assert_eq!((options.passes, options.profile), (2, ScanProfile::Passive));
+    assert_eq!(
+        ScanOptions::new(None, None).unwrap(),
+        ScanOptions::default()
+    );
+    assert!(ScanOptions::new(Some(0), None).is_err());
+
+    let collect = CollectOptions::from_args(args(&["--profile", "passive"])).unwrap();
+    assert_eq!(collect.profile, ScanProfile::Passive);
+    assert_eq!(
+        CollectOptions::from_args(args(&[])).unwrap().profile,
+        ScanProfile::Full
+    );
+    assert!(CollectOptions::from_args(args(&["--profile", "loud"])).is_err());
+}
+
+#[test]
+fn wider_scans_cover_narrower_ones() {
+    assert!(ScanProfile::Full.covers(ScanProfile::Fast));
+    assert!(ScanProfile::Passive.covers(ScanProfile::Full));
+    assert!(ScanProfile::SixGhz.covers(ScanProfile::Passive));
+    assert!(!ScanProfile::Fast.covers(ScanProfile::Full));
+    assert!(!ScanProfile::Full.covers(ScanProfile::SixGhz));
+}
+
+#[tokio::test]
+async fn cached_full_scans_are_not_served_for_6ghz_requests() {
+    let slot = Slot::new();
+    let max_age = Duration::from_secs(60);
+    let options = |profile| ScanOptions::new(None, Some(profile)).unwrap();
+    slot.scan(max_age, options(ScanProfile::Full), || async { vec![1] })
+        .await;
+
+    let now = Instant::now();
+    assert!(
+        slot.cached_at(now, max_age, options(ScanProfile::Fast))
+            .is_some()
+    );
+    assert!(
+        slot.cached_at(now, max_age, options(ScanProfile::SixGhz))
+            .is_none()
+    );
+    let (_, scanned) = slot
+        .scan(max_age, options(ScanProfile::SixGhz), || async { vec![2] })
+        .await;
+    assert!(scanned);
+}
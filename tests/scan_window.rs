@@ -0,0 +1,104 @@
+//! Combining sightings over a scan window
+
+use service_berry::scanner::BleDevice;
+use service_berry::scanner::wifi::{self, WifiBssid};
+use service_berry::scanner::window::{self, Samples, Window};
+
+mod common;
+
+fn fixture(name: &str) -> String {
+    let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+    std::fs::read_to_string(&path).expect("missing fixture")
+}
+
+fn ap(bssid: &str, rssi: i32) -> WifiBssid {
+    WifiBssid {
+        ssid: Some("Kitchen".into()),
+        rssi,
+        ..common::wifi(bssid)
+    }
+}
+
+fn beacon(address: &str, rssi: Option<i16>) -> BleDevice {
+    BleDevice {
+        rssi,
+        ..common::device(address)
+    }
+}
+
+#[test]
+fn reports_median_and_max_of_each_device() {
+    let mut window = Window::new();
+    window.add(ap("3C:5A:B4:01:02:03", -70), 1_000);
+    window.add(ap("3C:5A:B4:0A:0B:0C", -50), 1_500); // seen once
+    window.add(ap("3C:5A:B4:01:02:03", -58), 11_000);
+    window.add(ap("3C:5A:B4:01:02:03", -61), 21_000);
+    window.add(ap("3C:5A:B4:01:02:03", -61), 21_000); // same sighting again
+    assert_eq!(window.len(), 2);
+
+    let records = window.finish(22_500);
+    assert_eq!(records.len(), 1);
+    let kitchen = &records[0];
+    assert_eq!(kitchen.rssi, -61);
+    assert_eq!(kitchen.age, Some(1_500));
+    assert_eq!(
+        kitchen.samples,
+        Some(Samples {
+            count: 3,
+            median_rssi: -61,
+            max_rssi: -58,
+            first_seen: 1_000,
+            last_seen: 21_000,
+        })
+    );
+}
+
+#[test]
+fn even_sample_counts_average_the_middle_readings() {
+    let mut window = Window::new();
+    window.add(beacon("D4:01:02:03:04:05", Some(-80)), 300);
+    window.add(beacon("D4:01:02:03:04:05", None), 350); // no reading, not a sample
+    window.add(beacon("D4:01:02:03:04:05", Some(-71)), 100);
+    window.add(beacon("D4:01:02:03:04:05", Some(-75)), 200);
+    window.add(beacon("D4:01:02:03:04:05", Some(-90)), 250);
+
+    let devices = window.finish(1_000);
+    assert_eq!(devices.len(), 1);
+    let samples = devices[0].samples.unwrap();
+    assert_eq!(samples.count, 4);
+    assert_eq!(samples.median_rssi, -77);
+    assert_eq!(samples.max_rssi, -71);
+    assert_eq!((samples.first_seen, samples.last_seen), (100, 300));
+    assert_eq!(devices[0].rssi, Some(-77));
+    assert_eq!(devices[0].age, Some(700));
+}
+
+#[test]
+fn samples_are_archived_but_single_scans_have_none() {
+    let mut window = Window::new();
+    window.add(ap("3C:5A:B4:01:02:03", -60), 1_000);
+    window.add(ap("3C:5A:B4:01:02:03", -64), 2_000);
+    let archived = serde_json::to_value(&window.finish(2_000)[0]).unwrap();
+    assert_eq!(archived["signalStrength"], -62);
+    assert_eq!(archived["samples"]["count"], 2);
+    assert_eq!(archived["samples"]["medianRssi"], -62);
+    assert_eq!(archived["samples"]["maxRssi"], -60);
+    assert_eq!(archived["samples"]["lastSeen"], 2_000);
+
+    let single = serde_json::to_value(ap("3C:5A:B4:01:02:03", -60)).unwrap();
+    assert!(single.get("samples").is_none());
+    let parsed: WifiBssid = serde_json::from_value(single).unwrap();
+    assert_eq!(parsed.samples, None);
+}
+
+#[test]
+fn pass_counts_and_ages_are_checked() {
+    assert!(window::check_passes(1).is_ok());
+    assert!(window::check_passes(3).is_ok());
+    assert!(window::check_passes(0).is_err());
+    assert!(window::check_passes(1_000).is_err());
+
+    // iw prints "last seen" in milliseconds already
+    let records = wifi::parse_scan_dump(&fixture("iw_scan.txt"));
+    assert_eq!(records[0].age, Some(120));
+}
@@ -0,0 +1,61 @@
+//! Statistics reported by `/status`
+
+use service_berry::server::status::{AdapterState, Stats};
+
+#[test]
+fn submissions_are_counted_per_provider() {
+    let mut stats = Stats::new();
+    stats.record_submission("beacondb", Ok(()));
+    stats.record_submission("beacondb", Ok(()));
+    stats.record_submission("beacondb", Err("HTTP 502: bad gateway".into()));
+    stats.record_submission("other", Ok(()));
+
+    let report = stats.report();
+    let beacondb = &report.submissions.providers["beacondb"];
+    assert_eq!((beacondb.succeeded, beacondb.failed), (2, 1));
+    assert_eq!(report.submissions.providers["other"].succeeded, 1);
+
+    let last_error = report.submissions.last_error.unwrap();
+    assert_eq!(last_error.provider, "beacondb");
+    assert_eq!(last_error.message, "HTTP 502: bad gateway");
+    assert!(last_error.timestamp > 0);
+}
+
+#[test]
+fn queue_depth_never_goes_negative() {
+    let mut stats = Stats::new();
+    stats.queue_push();
+    stats.queue_push();
+    stats.queue_pop();
+    assert_eq!(stats.report().queue_depth, 1);
+
+    stats.queue_pop();
+    stats.queue_pop(); // a send that failed after the worker already popped
+    assert_eq!(stats.report().queue_depth, 0);
+}
+
+#[test]
+fn dropped_devices_add_up_by_reason() {
+    let mut stats = Stats::new();
+    stats.record_dropped("mobile", 2);
+    stats.record_dropped("nrpa", 1);
+    stats.record_dropped("mobile", 3);
+
+    let dropped = stats.report().dropped;
+    assert_eq!(dropped["mobile"], 5);
+    assert_eq!(dropped["nrpa"], 1);
+}
+
+#[test]
+fn report_starts_unknown_and_serializes_adapter_reasons() {
+    let report = serde_json::to_value(Stats::new().report()).unwrap();
+    assert_eq!(report["wifi"]["state"], "unknown");
+    assert_eq!(report["lastScan"], serde_json::Value::Null);
+    assert_eq!(report["queueDepth"], 0);
+    assert_eq!(report["peripheral"]["powered"], serde_json::Value::Null);
+
+    let unavailable = AdapterState::Unavailable("no adapters found".into());
+    let unavailable = serde_json::to_value(unavailable).unwrap();
+    assert_eq!(unavailable["state"], "unavailable");
+    assert_eq!(unavailable["reason"], "no adapters found");
+}
@@ -0,0 +1,87 @@
+//! Bands, channel numbering and channel/frequency agreement
+
+use service_berry::collect::Report;
+use service_berry::geosubmit::{Position, PositionSource, WifiAccessPoint};
+use service_berry::scanner::Band;
+use service_berry::scanner::band;
+use service_berry::scanner::wifi::{PhyType, WifiBssid};
+
+fn ap(frequency: u32, channel: Option<u8>) -> WifiBssid {
+    WifiBssid {
+        channel,
+        phy: PhyType::He,
+        rssi: -60,
+        ..WifiBssid::new("3C:5A:B4:01:02:03".parse().unwrap(), frequency)
+    }
+}
+
+#[test]
+fn channels_follow_each_bands_numbering() {
+    assert_eq!(band::channel(2412), Some((Band::TwoPointFour, 1)));
+    assert_eq!(band::channel(2484), Some((Band::TwoPointFour, 14)));
+    assert_eq!(band::channel(4920), Some((Band::Five, 184)));
+    assert_eq!(band::channel(5180), Some((Band::Five, 36)));
+    assert_eq!(band::channel(5885), Some((Band::Five, 177)));
+    assert_eq!(band::channel(5935), Some((Band::Six, 2)));
+    assert_eq!(band::channel(5955), Some((Band::Six, 1)));
+    assert_eq!(band::channel(7115), Some((Band::Six, 233)));
+    assert_eq!(band::channel(60480), Some((Band::Sixty, 2)));
+
+    assert_eq!(band::channel(2414), None); // off the raster
+    assert_eq!(band::channel(3600), None);
+    assert_eq!(Band::Six.frequency(5), Some(5975));
+    assert_eq!(Band::TwoPointFour.frequency(15), None);
+}
+
+#[test]
+fn channel_and_frequency_must_agree() {
+    assert_eq!(band::check(5975, 5).unwrap(), Band::Six);
+    assert!(band::check(5975, 37).is_err()); // 5 GHz numbering
+    assert!(band::check(900, 1).is_err());
+
+    let submitted = WifiAccessPoint::from(&ap(5180, Some(36)));
+    assert!(submitted.validate().is_ok());
+    assert!(
+        WifiAccessPoint::from(&ap(5180, Some(40)))
+            .validate()
+            .is_err()
+    );
+    assert!(WifiAccessPoint::from(&ap(3000, None)).validate().is_err());
+    assert!(WifiAccessPoint::from(&ap(0, None)).validate().is_ok()); // unknown
+}
+
+#[test]
+fn bands_are_exposed_for_filtering() {
+    let aps = [ap(2437, Some(6)), ap(5975, Some(5)), ap(60480, Some(2))];
+    let bands: Vec<_> = aps.iter().map(WifiBssid::band).collect();
+    assert_eq!(
+        bands,
+        vec![Some(Band::TwoPointFour), Some(Band::Six), Some(Band::Sixty)]
+    );
+    assert_eq!(serde_json::to_value(Band::Six).unwrap(), "6GHz");
+    assert_eq!(
+        serde_json::from_value::<Band>("2.4GHz".into()).unwrap(),
+        Band::TwoPointFour
+    );
+    assert_eq!(Band::Sixty.to_string(), "60 GHz");
+}
+
+#[test]
+fn mismatched_access_points_are_not_submitted() {
+    let report = Report {
+        timestamp: 1_748_779_200_000,
+        position: Some(Position {
+            latitude: 52.5163,
+            longitude: 13.3777,
+            accuracy: Some(5.0),
+            source: Some(PositionSource::Gps),
+            ..Default::default()
+        }),
+        wifi_access_points: vec![ap(5975, Some(5)), ap(5180, Some(40))],
+        bluetooth_beacons: Vec::new(),
+        cell_towers: Vec::new(),
+    };
+    let items = report.to_items().unwrap();
+    assert_eq!(items.wifiAccessPoints.len(), 1);
+    assert_eq!(items.wifiAccessPoints[0].frequency, Some(5975));
+}
@@ -0,0 +1,174 @@
+//! 802.11 information elements, from raw bytes, iw's output and nl80211
+
+use service_berry::geosubmit::WifiAccessPoint;
+use service_berry::scanner::ie::{self, Decoded, Feature, Security};
+use service_berry::scanner::wifi::{self, PhyType, WifiBssid};
+
+fn fixture(name: &str) -> String {
+    let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+    std::fs::read_to_string(&path).expect("missing fixture")
+}
+
+/// Elements of a Wi-Fi 7 access point on 6 GHz channel 5
+fn wifi7_elements() -> Vec<u8> {
+    let mut ies = vec![0, 4, b'L', b'a', b'b', b'6'];
+    ies.extend([7, 3, b'U', b'S', 4]);
+    ies.extend([48, 20, 1, 0, 0x00, 0x0f, 0xac, 4, 1, 0, 0x00, 0x0f, 0xac, 4]);
+    ies.extend([1, 0, 0x00, 0x0f, 0xac, 24, 0x0c, 0x00]); // SAE-EXT-KEY
+    ies.extend([255, 7, 35, 0x04, 0, 0, 0, 0, 0]); // HE capabilities, TWT responder
+    ies.extend([255, 12, 36, 0, 0, 0x02, 0x05, 0xfc, 0xff, 5, 3, 7, 15, 6]); // HE operation, 160 MHz
+    ies.extend([255, 2, 38, 0]);
+    ies.extend([255, 3, 108, 0, 0]);
+    ies.extend([255, 11, 106, 0x03, 0, 0, 0, 0, 4, 15, 31, 0x02, 0x00]); // EHT operation, 320 MHz
+    ies.extend([255, 3, 107, 0, 0]);
+    ies.extend([221, 10, 1, 2]); // truncated
+    ies
+}
+
+#[test]
+fn decodes_raw_elements() {
+    let ies = wifi7_elements();
+    assert_eq!(ie::elements(&ies).count(), 9);
+
+    let decoded = Decoded::decode(&ies, 5975);
+    assert_eq!(decoded.phy, Some(PhyType::Eht));
+    assert_eq!(decoded.channel, Some(5));
+    assert_eq!(decoded.info.width, Some(320));
+    assert_eq!(decoded.info.center_frequencies, vec![6105]);
+    assert_eq!(decoded.info.security, vec![Security::Sae]);
+    assert_eq!(decoded.info.country.as_deref(), Some("US"));
+    assert_eq!(
+        decoded.info.features,
+        vec![
+            Feature::TwtResponder,
+            Feature::BssColor,
+            Feature::MuEdca,
+            Feature::MultiLink,
+            Feature::Puncturing,
+        ]
+    );
+
+    assert_eq!(
+        ie::parse_hex("dd 04 00:50:f2 01"),
+        Some(vec![0xdd, 4, 0, 0x50, 0xf2, 1])
+    );
+    assert_eq!(ie::parse_hex("dd 0"), None);
+}
+
+#[test]
+fn parses_iw_scan_dump() {
+    let records = wifi::parse_scan_dump(&fixture("iw_scan.txt"));
+    assert_eq!(records.len(), 3);
+
+    // lines after the capability headings no longer reset the PHY
+    let kitchen = &records[0];
+    assert_eq!(kitchen.ssid.as_deref(), Some("Kitchen"));
+    assert_eq!(kitchen.phy, PhyType::Eht); // only in iw's hex
+    assert_eq!(kitchen.channel, Some(36));
+    assert_eq!(kitchen.rssi, -54);
+    assert_eq!(kitchen.info.width, Some(80));
+    assert_eq!(kitchen.info.center_frequencies, vec![5210]);
+    assert_eq!(kitchen.info.security, vec![Security::Psk, Security::Sae]);
+    assert_eq!(kitchen.info.country.as_deref(), Some("DE"));
+
+    let hotspot = &records[1];
+    assert_eq!(hotspot.phy, PhyType::Legacy);
+    assert_eq!(hotspot.channel, Some(6));
+    assert_eq!(hotspot.info.security, vec![Security::Wpa]);
+    assert_eq!(
+        hotspot.mac_info.vendor.as_deref(),
+        Some("Novatel Wireless, Inc.")
+    );
+
+    let hidden = &records[2];
+    assert_eq!(hidden.ssid, None);
+    assert_eq!(hidden.info.security, vec![Security::Wep]);
+}
+
+#[test]
+fn malformed_input_is_skipped_not_fatal() {
+    // a secondary channel above the primary has nowhere to go from 255
+    let decoded = Decoded::decode(&[61, 2, 255, 0x05], 2437);
+    assert_eq!(decoded.channel, Some(255));
+    assert_eq!(decoded.info.width, None);
+
+    let dump = "\
+BSS 3c:5a:b4:01:02:03(on wlan0)
+\tfreq: 2437
+\tSSID: \\x00\\x00 \\x01
+BSS 3c:5a:b4:01:02:04(on wlan0)
+\tfreq: 2437
+\tSSID: \\x7f
+";
+    let records = wifi::parse_scan_dump(dump);
+    assert_eq!(records.len(), 2);
+    assert!(records.iter().all(|r| r.ssid.is_none()));
+}
+
+fn attribute(kind: u16, value: &[u8]) -> Vec<u8> {
+    let mut attribute = Vec::new();
+    attribute.extend_from_slice(&(4 + value.len() as u16).to_ne_bytes());
+    attribute.extend_from_slice(&kind.to_ne_bytes());
+    attribute.extend_from_slice(value);
+    attribute.resize(attribute.len().next_multiple_of(4), 0);
+    attribute
+}
+
+#[test]
+fn parses_nl80211_attributes() {
+    let ies = [
+        &[0, 4, b'C', b'a', b'f', b'e'][..],
+        &[3, 1, 6],
+        &[45, 0],
+        &[61, 2, 6, 0],
+    ]
+    .concat();
+    let attributes = [
+        attribute(1, &[0x3c, 0x5a, 0xb4, 0x01, 0x02, 0x03]),
+        attribute(2, &2437u32.to_ne_bytes()),
+        attribute(5, &0x0431u16.to_ne_bytes()), // ESS, privacy
+        attribute(6, &ies),
+        attribute(7, &(-6500i32).to_ne_bytes()),
+        attribute(10, &250u32.to_ne_bytes()),
+    ]
+    .concat();
+
+    let bssid = WifiBssid::from_nl80211(&attributes).unwrap();
+    assert_eq!(bssid.bssid.to_string(), "3C:5A:B4:01:02:03");
+    assert_eq!(bssid.ssid.as_deref(), Some("Cafe"));
+    assert_eq!(bssid.frequency, 2437);
+    assert_eq!(bssid.rssi, -65);
+    assert_eq!(bssid.age, Some(250));
+    assert_eq!(bssid.phy, PhyType::Ht);
+    assert_eq!(bssid.channel, Some(6));
+    assert_eq!(bssid.info.width, Some(20));
+    assert_eq!(bssid.info.center_frequencies, vec![2437]);
+    assert_eq!(bssid.info.security, vec![Security::Wep]);
+
+    assert!(WifiBssid::from_nl80211(&attribute(2, &2437u32.to_ne_bytes())).is_none());
+}
+
+#[test]
+fn only_schema_fields_are_submitted() {
+    let mut attributes = attribute(1, &[0x3c, 0x5a, 0xb4, 0x01, 0x02, 0x03]);
+    attributes.extend(attribute(2, &5975u32.to_ne_bytes()));
+    attributes.extend(attribute(6, &wifi7_elements()));
+    let bssid = WifiBssid::from_nl80211(&attributes).unwrap();
+
+    let archived = serde_json::to_value(&bssid).unwrap();
+    assert_eq!(archived["width"], 320);
+    assert_eq!(archived["security"][0], "sae");
+
+    let submitted = serde_json::to_value(WifiAccessPoint::from(&bssid)).unwrap();
+    assert_eq!(submitted["radioType"], "802.11ax");
+    assert_eq!(submitted["channel"], 5);
+    for field in [
+        "width",
+        "centerFrequencies",
+        "security",
+        "country",
+        "features",
+    ] {
+        assert!(submitted.get(field).is_none(), "{} submitted", field);
+    }
+}
@@ -0,0 +1,68 @@
+//! Knowing when a Wi-Fi scan finished and leaving out what it didn't see
+
+use service_berry::scanner::wifi::{self, INTERFACE, ScanEnd};
+
+/// An entry the kernel kept from a scan 13 hours ago, as in `sample.json`
+const STALE_DUMP: &str = "\
+BSS 3c:5a:b4:01:02:03(on wlan0)
+\tlast seen: 47468974 ms [boottime: 47470.1s]
+\tfreq: 2412
+\tsignal: -71.00 dBm
+\tSSID: Bakery
+BSS 3c:5a:b4:0a:0b:0c(on wlan0)
+\tlast seen: 860 ms [boottime: 47470.1s]
+\tfreq: 2437
+\tsignal: -63.00 dBm
+\tSSID: Library
+BSS 3c:5a:b4:0d:0e:0f(on wlan0)
+\tfreq: 2462
+\tsignal: -80.00 dBm
+";
+
+#[test]
+fn recognizes_the_end_of_a_scan() {
+    assert_eq!(
+        ScanEnd::parse(
+            r#"wlan0 (phy #0): scan finished: 2412 2417 2422, """#,
+            "wlan0"
+        ),
+        Some(ScanEnd::Finished)
+    );
+    assert_eq!(
+        ScanEnd::parse("1712.345678: wlan0 (phy #1): scan aborted: 5180", "wlan0"),
+        Some(ScanEnd::Aborted)
+    );
+    assert_eq!(
+        ScanEnd::parse("wlan0 (phy #0): scan started", "wlan0"),
+        None
+    );
+    assert_eq!(
+        ScanEnd::parse("wlan1 (phy #2): scan finished: 2412", INTERFACE),
+        None
+    );
+    assert_eq!(
+        ScanEnd::parse("phy #0: regulatory domain changed", "wlan0"),
+        None
+    );
+}
+
+#[test]
+fn drops_entries_older_than_the_maximum_age() {
+    let mut records = wifi::parse_scan_dump(STALE_DUMP);
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[0].age, Some(47_468_974));
+
+    assert_eq!(wifi::drop_stale(&mut records, 30_000), 1);
+    let names: Vec<_> = records.iter().map(|ap| ap.ssid.as_deref()).collect();
+    assert_eq!(names, vec![Some("Library"), None]); // no age, kept
+}
+
+#[test]
+fn ages_at_the_limit_are_kept() {
+    let mut records = wifi::parse_scan_dump(STALE_DUMP);
+    assert_eq!(wifi::drop_stale(&mut records, 860), 1);
+    assert_eq!(records.len(), 2);
+    assert_eq!(wifi::drop_stale(&mut records, 859), 1);
+    assert_eq!(records.len(), 1);
+    assert_eq!(wifi::drop_stale(&mut records, 0), 0);
+}